@@ -4,11 +4,14 @@ use anyhow::Result;
 use bon::builder;
 use snafu::Snafu;
 
-use crate::config::{
-    module::{Module, ModuleKind},
-    relocation::{Relocation, RelocationModule, Relocations},
-    section::{Section, SectionKind, Sections},
-    symbol::{SymBss, SymData, SymbolMap, SymbolMaps},
+use crate::{
+    config::{
+        module::{Module, ModuleKind},
+        relocation::{Relocation, RelocationModule, Relocations},
+        section::{Section, SectionKind, Sections},
+        symbol::{SymBss, SymData, SymbolMap, SymbolMaps},
+    },
+    util::bytes::FromSlice,
 };
 
 use super::functions::Function;
@@ -46,6 +49,57 @@ pub fn find_local_data_from_pools(
         }
     }
 
+    if !function.is_thumb() {
+        // ARM jump tables can contain the absolute addresses of each case
+        for jump_table in function.jump_tables().filter(|table| !table.code) {
+            for entry_address in (jump_table.address..jump_table.address + jump_table.size).step_by(4) {
+                let offset = (entry_address - base_address) as usize;
+                let Some(bytes) = module_code.get(offset..offset + 4) else {
+                    log::warn!(
+                        "Jump table at {:#010x} in {} runs past the end of the module, ignoring entries from {:#010x}",
+                        jump_table.address,
+                        function.name(),
+                        entry_address
+                    );
+                    break;
+                };
+                let case_address = u32::from_slice(bytes, function.endian());
+                relocations.add_load(entry_address, case_address, 0, module_kind.try_into()?)?;
+            }
+        }
+    }
+
+    for &address in function.string_loads() {
+        if function.contains(address) {
+            // Adding data inside the function would split up its code
+            continue;
+        }
+        let Some((_, section)) = sections.get_by_contained_address(address) else {
+            continue;
+        };
+        if section.kind() != SectionKind::Data {
+            continue;
+        }
+        let start = (address - base_address) as usize;
+        let end = (section.end_address() - base_address) as usize;
+        let data = SymData::detect_string(&module_code[start..end]).unwrap_or(SymData::Any);
+        symbol_map.add_data(Some(format!("{}{:08x}", name_prefix, address)), address, data)?;
+    }
+
+    for &address in function.pc_relative_addresses() {
+        if function.contains(address) || function.string_loads().contains(&address) {
+            // Labels inside the function were already added, and strings are handled above
+            continue;
+        }
+        let Some((_, section)) = sections.get_by_contained_address(address) else {
+            continue;
+        };
+        if section.kind() != SectionKind::Data {
+            continue;
+        }
+        symbol_map.add_data(Some(format!("{}{:08x}", name_prefix, address)), address, SymData::Any)?;
+    }
+
     Ok(())
 }
 
@@ -212,6 +266,10 @@ fn add_function_calls_as_relocations(
             // Dumb mwld linker bug removes the condition code from relocated call instructions
             continue;
         }
+        if called_function.is_register_call() {
+            // The function address is relocated at the pool constant it was loaded from
+            continue;
+        }
 
         let local_module = &modules[module_index];
         let is_local = local_module.sections().get_by_contained_address(called_function.address).is_some();
@@ -235,7 +293,7 @@ fn add_function_calls_as_relocations(
                         let thumb_bit = if called_function.thumb { 1 } else { 0 };
                         let function_address = called_function.address | thumb_bit;
 
-                        let name = format!("{}{:08x}_unk", local_module.default_func_prefix, function_address);
+                        let name = format!("{}{:08x}_unk", local_module.default_func_prefix(), function_address);
                         let (_, symbol) = symbol_map.add_unknown_function(name, function_address, called_function.thumb);
                         symbol
                     }
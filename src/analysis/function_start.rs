@@ -3,11 +3,90 @@ use unarm::{
     arm, thumb, Ins, ParsedIns,
 };
 
-pub fn is_valid_function_start_arm(_address: u32, ins: arm::Ins, parsed_ins: &ParsedIns) -> bool {
+/// Why an instruction was rejected as the first instruction of a function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunctionStartRejection {
+    /// The instruction is illegal, or is half of a Thumb BL/BLX.
+    IllegalInstruction,
+    /// The instruction is data, not code.
+    NotCode,
+    /// The ARM instruction has a condition other than AL.
+    ConditionalFirstInsn,
+    /// An EOR which uses the same register twice.
+    WeirdEor,
+    /// A Thumb data operation whose operand is not an argument register, SP or PC.
+    BadDataOperand,
+    /// A `mov` from a register to itself.
+    UselessMov,
+    /// A shift by zero of a register to itself.
+    UselessDataOp,
+    /// A shift by a multiple of 4, typical of a table of small bytes disassembled as Thumb code.
+    ByteTable,
+    /// A load or store whose base is not an argument register, SP or PC.
+    BadLoadBase,
+    /// A store of a register to the address in itself.
+    SelfStore,
+    /// A load whose offset register is not an argument register.
+    BadOffsetRegister,
+    /// The ARM instruction is not a typical prologue, see [`FunctionStartHeuristics::require_arm_prologue`].
+    NoArmPrologue,
+}
+
+/// Toggles for the rules which reject function starts. Illegal and conditional instructions are always rejected. The
+/// default enables all rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FunctionStartHeuristics {
+    /// Reject [`FunctionStartRejection::WeirdEor`].
+    pub weird_eor: bool,
+    /// Reject [`FunctionStartRejection::BadDataOperand`].
+    pub data_operand: bool,
+    /// Reject [`FunctionStartRejection::UselessMov`].
+    pub useless_mov: bool,
+    /// Reject [`FunctionStartRejection::UselessDataOp`].
+    pub useless_data_op: bool,
+    /// Reject [`FunctionStartRejection::ByteTable`].
+    pub byte_table: bool,
+    /// Reject [`FunctionStartRejection::BadLoadBase`].
+    pub load_base: bool,
+    /// Reject [`FunctionStartRejection::SelfStore`].
+    pub self_store: bool,
+    /// Reject [`FunctionStartRejection::BadOffsetRegister`].
+    pub offset_register: bool,
+    /// Reject ARM instructions other than `stmdb sp!, {..., lr}`, `mov ip, sp` and `sub sp, sp, #imm`. Off by default, as
+    /// leaf functions often have no stack frame.
+    pub require_arm_prologue: bool,
+}
+
+impl Default for FunctionStartHeuristics {
+    fn default() -> Self {
+        Self {
+            weird_eor: true,
+            data_operand: true,
+            useless_mov: true,
+            useless_data_op: true,
+            byte_table: true,
+            load_base: true,
+            self_store: true,
+            offset_register: true,
+            require_arm_prologue: false,
+        }
+    }
+}
+
+pub fn is_valid_function_start_arm(address: u32, ins: arm::Ins, parsed_ins: &ParsedIns) -> bool {
+    check_function_start_arm(address, ins, parsed_ins, &Default::default()).is_ok()
+}
+
+pub fn check_function_start_arm(
+    _address: u32,
+    ins: arm::Ins,
+    parsed_ins: &ParsedIns,
+    heuristics: &FunctionStartHeuristics,
+) -> Result<(), FunctionStartRejection> {
     if ins.op == arm::Opcode::Illegal || parsed_ins.is_illegal() {
-        return false;
+        return Err(FunctionStartRejection::IllegalInstruction);
     } else if ins.has_cond() && ins.modifier_cond() != arm::Cond::Al {
-        return false;
+        return Err(FunctionStartRejection::ConditionalFirstInsn);
     }
     let args = &parsed_ins.args;
     match (parsed_ins.mnemonic, args[0], args[1], args[2], args[3]) {
@@ -17,26 +96,60 @@ pub fn is_valid_function_start_arm(_address: u32, ins: arm::Ins, parsed_ins: &Pa
             Argument::Reg(Reg { reg: src_a, .. }),
             Argument::Reg(Reg { reg: src_b, .. }),
             Argument::None,
-        ) if dest == src_a || dest == src_b || src_a == src_b => {
+        ) if heuristics.weird_eor && (dest == src_a || dest == src_b || src_a == src_b) => {
             // Weird EOR instruction
-            false
+            Err(FunctionStartRejection::WeirdEor)
+        }
+        _ if heuristics.require_arm_prologue && !is_arm_prologue(ins, parsed_ins) => {
+            Err(FunctionStartRejection::NoArmPrologue)
         }
-        _ => true,
+        _ => Ok(()),
     }
 }
 
-pub fn is_valid_function_start_thumb(_address: u32, ins: thumb::Ins, parsed_ins: &ParsedIns) -> bool {
+fn is_arm_prologue(ins: arm::Ins, parsed_ins: &ParsedIns) -> bool {
+    const MOV_IP_SP: u32 = 0xe1a0c00d;
+    if Ins::Arm(ins).code() == MOV_IP_SP {
+        return true;
+    }
+
+    let args = &parsed_ins.args;
+    match (parsed_ins.mnemonic, args[0], args[1], args[2]) {
+        // stmdb sp!, {..., lr}
+        ("stmdb" | "push", Argument::Reg(Reg { reg: Register::Sp, writeback: true, .. }), Argument::RegList(regs), _)
+        | ("push", Argument::RegList(regs), _, _) => regs.contains(Register::Lr),
+        // sub sp, sp, #imm
+        (
+            "sub",
+            Argument::Reg(Reg { reg: Register::Sp, .. }),
+            Argument::Reg(Reg { reg: Register::Sp, .. }),
+            Argument::UImm(_),
+        ) => true,
+        _ => false,
+    }
+}
+
+pub fn is_valid_function_start_thumb(address: u32, ins: thumb::Ins, parsed_ins: &ParsedIns) -> bool {
+    check_function_start_thumb(address, ins, parsed_ins, &Default::default()).is_ok()
+}
+
+pub fn check_function_start_thumb(
+    _address: u32,
+    ins: thumb::Ins,
+    parsed_ins: &ParsedIns,
+    heuristics: &FunctionStartHeuristics,
+) -> Result<(), FunctionStartRejection> {
     if matches!(ins.op, thumb::Opcode::Illegal | thumb::Opcode::Bl | thumb::Opcode::BlH) || parsed_ins.is_illegal() {
-        return false;
+        return Err(FunctionStartRejection::IllegalInstruction);
     }
 
     let args = &parsed_ins.args;
 
-    if ins.is_data_operation() {
+    if heuristics.data_operand && ins.is_data_operation() {
         if let Argument::Reg(Reg { reg, .. }) = args[1] {
             // Data operand must be an argument register, SP or PC
             if !matches!(reg, Register::R0 | Register::R1 | Register::R2 | Register::R3 | Register::Sp | Register::Pc) {
-                return false;
+                return Err(FunctionStartRejection::BadDataOperand);
             }
         }
     }
@@ -44,10 +157,10 @@ pub fn is_valid_function_start_thumb(_address: u32, ins: thumb::Ins, parsed_ins:
     match (parsed_ins.mnemonic, args[0], args[1], args[2], args[3]) {
         ("mov", Argument::Reg(Reg { reg: dst, .. }), Argument::Reg(Reg { reg: src, .. }), Argument::None, Argument::None)
         | ("movs", Argument::Reg(Reg { reg: dst, .. }), Argument::Reg(Reg { reg: src, .. }), Argument::None, Argument::None)
-            if src == dst =>
+            if heuristics.useless_mov && src == dst =>
         {
             // Useless mov
-            false
+            Err(FunctionStartRejection::UselessMov)
         }
         (
             "lsl",
@@ -62,9 +175,9 @@ pub fn is_valid_function_start_thumb(_address: u32, ins: thumb::Ins, parsed_ins:
             Argument::Reg(Reg { reg: src, .. }),
             Argument::UImm(0),
             Argument::None,
-        ) if src == dst => {
+        ) if heuristics.useless_data_op && src == dst => {
             // Useless data op
-            false
+            Err(FunctionStartRejection::UselessDataOp)
         }
         (
             "lsl",
@@ -93,10 +206,10 @@ pub fn is_valid_function_start_thumb(_address: u32, ins: thumb::Ins, parsed_ins:
             Argument::Reg(Reg { reg: src, .. }),
             Argument::UImm(shift),
             Argument::None,
-        ) if src == dst && (shift % 4) == 0 && shift != 16 && shift != 24 => {
+        ) if heuristics.byte_table && src == dst && (shift % 4) == 0 && shift != 16 && shift != 24 => {
             // Table of bytes with values 0-7 got interpreted as Thumb code
             // Shift by 16 or 24 is allowed since they may be used for integer type casts
-            false
+            Err(FunctionStartRejection::ByteTable)
         }
         ("ldr", Argument::Reg(_), Argument::Reg(Reg { deref: true, reg, .. }), _, _)
         | ("ldrh", Argument::Reg(_), Argument::Reg(Reg { deref: true, reg, .. }), _, _)
@@ -106,38 +219,49 @@ pub fn is_valid_function_start_thumb(_address: u32, ins: thumb::Ins, parsed_ins:
         | ("str", Argument::Reg(_), Argument::Reg(Reg { deref: true, reg, .. }), _, _)
         | ("strb", Argument::Reg(_), Argument::Reg(Reg { deref: true, reg, .. }), _, _)
         | ("strh", Argument::Reg(_), Argument::Reg(Reg { deref: true, reg, .. }), _, _)
-            if !matches!(reg, Register::R0 | Register::R1 | Register::R2 | Register::R3 | Register::Sp | Register::Pc) =>
+            if heuristics.load_base
+                && !matches!(reg, Register::R0 | Register::R1 | Register::R2 | Register::R3 | Register::Sp | Register::Pc) =>
         {
             // Load/store base must be an argument register, SP or PC
-            false
+            Err(FunctionStartRejection::BadLoadBase)
         }
         ("strh", Argument::Reg(Reg { reg, .. }), Argument::Reg(Reg { deref: true, reg: base, .. }), _, _)
         | ("strb", Argument::Reg(Reg { reg, .. }), Argument::Reg(Reg { deref: true, reg: base, .. }), _, _)
-            if base == reg =>
+            if heuristics.self_store && base == reg =>
         {
             // Weird self reference:
             // *ptr = (u16) ptr;
             // *ptr = (u8) ptr;
-            false
+            Err(FunctionStartRejection::SelfStore)
         }
         ("ldr", Argument::Reg(_), Argument::Reg(Reg { deref: true, .. }), Argument::OffsetReg(OffsetReg { reg, .. }), _)
         | ("ldrh", Argument::Reg(_), Argument::Reg(Reg { deref: true, .. }), Argument::OffsetReg(OffsetReg { reg, .. }), _)
         | ("ldrb", Argument::Reg(_), Argument::Reg(Reg { deref: true, .. }), Argument::OffsetReg(OffsetReg { reg, .. }), _)
         | ("ldrsh", Argument::Reg(_), Argument::Reg(Reg { deref: true, .. }), Argument::OffsetReg(OffsetReg { reg, .. }), _)
         | ("ldrsb", Argument::Reg(_), Argument::Reg(Reg { deref: true, .. }), Argument::OffsetReg(OffsetReg { reg, .. }), _)
-            if !matches!(reg, Register::R0 | Register::R1 | Register::R2 | Register::R3) =>
+            if heuristics.offset_register && !matches!(reg, Register::R0 | Register::R1 | Register::R2 | Register::R3) =>
         {
             // Offset register must be an argument register
-            false
+            Err(FunctionStartRejection::BadOffsetRegister)
         }
-        _ => true,
+        _ => Ok(()),
     }
 }
 
 pub fn is_valid_function_start(address: u32, ins: Ins, parsed_ins: &ParsedIns) -> bool {
+    check_function_start(address, ins, parsed_ins, &Default::default()).is_ok()
+}
+
+/// Returns why `ins` can't be the first instruction of a function, if it can't.
+pub fn check_function_start(
+    address: u32,
+    ins: Ins,
+    parsed_ins: &ParsedIns,
+    heuristics: &FunctionStartHeuristics,
+) -> Result<(), FunctionStartRejection> {
     match ins {
-        Ins::Arm(ins) => is_valid_function_start_arm(address, ins, parsed_ins),
-        Ins::Thumb(ins) => is_valid_function_start_thumb(address, ins, parsed_ins),
-        Ins::Data => false,
+        Ins::Arm(ins) => check_function_start_arm(address, ins, parsed_ins, heuristics),
+        Ins::Thumb(ins) => check_function_start_thumb(address, ins, parsed_ins, heuristics),
+        Ins::Data => Err(FunctionStartRejection::NotCode),
     }
 }
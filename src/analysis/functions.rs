@@ -1,19 +1,27 @@
 use std::{
     collections::{BTreeMap, BTreeSet},
-    io,
+    io::{self, Write},
+    iter,
+    ops::Range,
+    thread,
 };
 
 use anyhow::{bail, Result};
 use bon::bon;
+use snafu::Snafu;
 use unarm::{
     args::{Argument, Reg, Register},
-    arm, thumb, ArmVersion, DisplayOptions, Endian, Ins, ParseFlags, ParseMode, ParsedIns, Parser, RegNames,
+    arm, thumb, ArmVersion, DisplayOptions, Endian, Ins, LookupSymbol, ParseFlags, ParseMode, ParsedIns, Parser, RegNames,
 };
 
 use crate::{
-    analysis::function_start::is_valid_function_start,
-    config::symbol::{SymbolLookup, SymbolMap},
-    util::bytes::FromSlice,
+    analysis::function_start::{check_function_start, FunctionStartHeuristics},
+    config::{
+        module::{ModuleKind, DEFAULT_FUNC_PREFIX},
+        relocation::{RelocationKind, Relocations},
+        symbol::{SymData, Symbol, SymbolKind, SymbolLookup, SymbolMap},
+    },
+    util::{bytes::FromSlice, demangle::demangle},
 };
 
 use super::{
@@ -22,6 +30,7 @@ use super::{
     inline_table::{InlineTable, InlineTableState},
     jump_table::{JumpTable, JumpTableState},
     secure_area::SecureAreaState,
+    string_load::StringLoadState,
 };
 
 // All keys in the types below are instruction addresses
@@ -31,12 +40,19 @@ pub type JumpTables = BTreeMap<u32, JumpTable>;
 pub type InlineTables = BTreeMap<u32, InlineTable>;
 pub type FunctionCalls = BTreeMap<u32, CalledFunction>;
 pub type DataLoads = BTreeMap<u32, u32>;
+pub type StringLoads = BTreeSet<u32>;
+pub type PcRelativeAddresses = BTreeSet<u32>;
+
+/// Maximum number of literal pool words to skip when looking for a function body after its pool.
+const MAX_PRE_BODY_POOL_WORDS: u32 = 16;
 
 #[derive(Debug, Clone)]
 pub struct Function {
     name: String,
     start_address: u32,
     end_address: u32,
+    /// End of the function's instructions, before any trailing literal pool.
+    code_end_address: u32,
     first_instruction_address: u32,
     thumb: bool,
     labels: Labels,
@@ -44,6 +60,16 @@ pub struct Function {
     jump_tables: JumpTables,
     inline_tables: InlineTables,
     function_calls: FunctionCalls,
+    string_loads: StringLoads,
+    pc_relative_addresses: PcRelativeAddresses,
+    /// Whether the function should be parsed with Unified Assembler Language (UAL) syntax.
+    ual: bool,
+    /// Architecture version to parse the function with.
+    version: ArmVersion,
+    /// Byte order of the function's code and data.
+    endian: Endian,
+    /// Alignment of the function's start address in bytes.
+    alignment: u32,
 }
 
 #[bon]
@@ -52,22 +78,54 @@ impl Function {
         self.end_address - self.start_address
     }
 
-    fn is_thumb_function(address: u32, code: &[u8]) -> bool {
+    fn is_thumb_function(address: u32, code: &[u8], endian: Endian) -> bool {
+        // The condition code is in the most significant byte
+        let condition_byte = match endian {
+            Endian::Little => 3,
+            Endian::Big => 0,
+        };
         if (address & 3) != 0 {
             // Not 4-aligned, must be Thumb
-            true
+            return true;
         } else if code.len() < 4 {
             // Can't contain a full ARM instruction
-            true
-        } else if code[3] & 0xf0 == 0xe0 {
-            // First instruction has the AL condition code, must be ARM
-            false
+            return true;
+        }
+
+        // First instruction has the AL condition code, likely ARM
+        let condition_is_al = code[condition_byte] & 0xf0 == 0xe0;
+        if condition_is_al && Self::probe_mode(ParseMode::Arm, address, code, endian, 1).has_entry {
+            return false;
+        }
+
+        // Ambiguous, so decode a few instructions in both modes and look for a prologue and illegal instructions
+        const PROBE_BYTES: usize = 16;
+        let arm = Self::probe_mode(ParseMode::Arm, address, code, endian, PROBE_BYTES / 4);
+        let thumb = Self::probe_mode(ParseMode::Thumb, address, code, endian, PROBE_BYTES / 2);
+        let arm_prologue = arm.has_entry && !arm.has_illegal;
+        let thumb_prologue = thumb.has_entry && !thumb.has_illegal;
+        if arm_prologue != thumb_prologue {
+            thumb_prologue
+        } else if arm.has_illegal != thumb.has_illegal {
+            arm.has_illegal
         } else {
-            // Thumb otherwise
-            true
+            // Thumb unless the first instruction has the AL condition code
+            !condition_is_al
         }
     }
 
+    /// Decodes up to `count` instructions in the given mode, for [`Self::is_thumb_function`].
+    fn probe_mode(mode: ParseMode, address: u32, code: &[u8], endian: Endian, count: usize) -> ModeProbe {
+        let mut parser = Parser::new(mode, address, endian, ParseFlags { version: ArmVersion::V5Te, ual: false }, code);
+        let mut probe = ModeProbe::default();
+        for _ in 0..count {
+            let Some((_, ins, parsed_ins)) = parser.next() else { break };
+            probe.has_entry |= Self::is_entry_instruction(ins, &parsed_ins);
+            probe.has_illegal |= parsed_ins.is_illegal();
+        }
+        probe
+    }
+
     fn is_push(ins: Ins) -> bool {
         match ins {
             Ins::Arm(op) => op.op == arm::Opcode::StmW && op.modifier_addr_ldm_stm() == arm::AddrLdmStm::Db,
@@ -76,6 +134,9 @@ impl Function {
         }
     }
 
+    /// Returns true for instructions that push LR to the stack. Stores to other base registers, such as the `str`/`stmia`
+    /// sequence to `[r0]` of a function returning a struct by value, are deliberately not matched so that such epilogues
+    /// are not mistaken for the start of a new function.
     fn is_entry_instruction(ins: Ins, parsed_ins: &ParsedIns) -> bool {
         if ins.is_conditional() {
             return false;
@@ -126,6 +187,60 @@ impl Function {
         }
     }
 
+    /// Returns true for a conditional `bx lr`, `mov pc, lr` or `ldm *, {..., pc}`. Mnemonics include the condition code,
+    /// so only their prefix is compared.
+    fn is_conditional_return(ins: Ins, parsed_ins: &ParsedIns) -> bool {
+        if !ins.is_conditional() {
+            return false;
+        }
+
+        let args = &parsed_ins.args;
+        let mnemonic = parsed_ins.mnemonic;
+        match (args[0], args[1], args[2]) {
+            // bx<cond> lr
+            (Argument::Reg(Reg { reg: Register::Lr, .. }), Argument::None, _) => mnemonic.starts_with("bx"),
+            // mov<cond> pc, lr
+            (Argument::Reg(Reg { reg: Register::Pc, .. }), Argument::Reg(Reg { reg: Register::Lr, .. }), Argument::None) => {
+                mnemonic.starts_with("mov")
+            }
+            // ldm<cond>* *, {..., pc}
+            (_, Argument::RegList(reg_list), _) => mnemonic.starts_with("ldm") && reg_list.contains(Register::Pc),
+            _ => false,
+        }
+    }
+
+    fn is_mov_lr_pc(parsed_ins: &ParsedIns) -> bool {
+        matches!(
+            (parsed_ins.mnemonic, parsed_ins.args[0], parsed_ins.args[1], parsed_ins.args[2]),
+            (
+                "mov",
+                Argument::Reg(Reg { reg: Register::Lr, deref: false, .. }),
+                Argument::Reg(Reg { reg: Register::Pc, deref: false, .. }),
+                Argument::None
+            )
+        )
+    }
+
+    /// Returns true for `bx rN` where rN is not LR.
+    fn is_bx_register(parsed_ins: &ParsedIns) -> bool {
+        match (parsed_ins.mnemonic, parsed_ins.args[0]) {
+            ("bx", Argument::Reg(Reg { reg, .. })) => reg != Register::Lr,
+            _ => false,
+        }
+    }
+
+    /// Returns true for `ldr pc, [rN, ...]` where rN is not PC, which jumps to a loaded function pointer such as a virtual
+    /// function in a vtable.
+    fn is_computed_jump(ins: Ins, parsed_ins: &ParsedIns) -> bool {
+        if ins.mnemonic() != "ldr" {
+            return false;
+        }
+        match (parsed_ins.args[0], parsed_ins.args[1]) {
+            (Argument::Reg(Reg { reg: Register::Pc, .. }), Argument::Reg(Reg { reg, deref: true, .. })) => reg != Register::Pc,
+            _ => false,
+        }
+    }
+
     fn is_branch(ins: Ins, parsed_ins: &ParsedIns, address: u32) -> Option<u32> {
         if ins.mnemonic() != "b" {
             return None;
@@ -157,6 +272,26 @@ impl Function {
         }
     }
 
+    /// Returns the address computed by `add rN, pc, #imm` or `sub rN, pc, #imm`, i.e. the `adr` pseudo-instruction. Like
+    /// pool loads, the PC is 8 bytes ahead in ARM mode and 4 bytes ahead of the word-aligned address in Thumb mode. Returns
+    /// the destination register along with the address, or None if the address would overflow.
+    pub fn is_pc_relative_address(parsed_ins: &ParsedIns, address: u32, thumb: bool) -> Option<(Register, u32)> {
+        let args = &parsed_ins.args;
+        let (destination, negative, offset) = match (parsed_ins.mnemonic, args[0], args[1], args[2], args[3]) {
+            (
+                mnemonic @ ("add" | "sub"),
+                Argument::Reg(Reg { reg, deref: false, .. }),
+                Argument::Reg(Reg { reg: Register::Pc, deref: false, .. }),
+                Argument::UImm(offset),
+                Argument::None,
+            ) if reg != Register::Pc => (reg, mnemonic == "sub", offset),
+            _ => return None,
+        };
+        let pc = if thumb { (address & !3).checked_add(4)? } else { address.checked_add(8)? };
+        let target = if negative { pc.checked_sub(offset)? } else { pc.checked_add(offset)? };
+        Some((destination, target))
+    }
+
     fn is_function_call(ins: Ins, parsed_ins: &ParsedIns, address: u32, thumb: bool) -> Option<CalledFunction> {
         let args = &parsed_ins.args;
         match (ins.mnemonic(), args[0], args[1]) {
@@ -179,15 +314,33 @@ impl Function {
         start_address: u32,
         thumb: bool,
         mut parser: Parser<'a>,
+        module_code: &'a [u8],
+        base_address: u32,
         known_end_address: Option<u32>,
+        next_symbol_address: Option<u32>,
         module_start_address: u32,
         module_end_address: u32,
+        #[builder(default)] ual: bool,
+        #[builder(default = ArmVersion::V5Te)] version: ArmVersion,
+        #[builder(default = Endian::Little)] endian: Endian,
+        #[builder(default)] start_heuristics: FunctionStartHeuristics,
     ) -> Result<ParseFunctionResult> {
-        let mut context =
-            ParseFunctionContext::new(start_address, thumb, known_end_address, module_start_address, module_end_address);
+        // The parser for analysis is always pre-UAL, as the state machines expect pre-UAL mnemonics
+        let mut context = ParseFunctionContext::builder()
+            .start_address(start_address)
+            .thumb(thumb)
+            .maybe_known_end_address(known_end_address)
+            .maybe_next_symbol_address(next_symbol_address)
+            .module_start_address(module_start_address)
+            .module_end_address(module_end_address)
+            .version(version)
+            .module_code(module_code)
+            .base_address(base_address)
+            .endian(endian)
+            .build();
 
         let Some((address, ins, parsed_ins)) = parser.next() else { return Ok(ParseFunctionResult::NoEpilogue) };
-        if !is_valid_function_start(address, ins, &parsed_ins) {
+        if check_function_start(address, ins, &parsed_ins, &start_heuristics).is_err() {
             return Ok(ParseFunctionResult::InvalidStart { address, ins, parsed_ins });
         }
 
@@ -210,6 +363,8 @@ impl Function {
         let ParseFunctionResult::Found(mut function) = result else {
             return Ok(result);
         };
+        function.ual = ual;
+        function.endian = endian;
 
         if let Some(first_pool_address) = function.pool_constants.first() {
             if *first_pool_address < function.start_address {
@@ -236,33 +391,45 @@ impl Function {
         module_code: &[u8],
         options: ParseFunctionOptions,
         known_end_address: Option<u32>,
+        next_symbol_address: Option<u32>,
         module_start_address: u32,
         module_end_address: u32,
     ) -> Result<ParseFunctionResult> {
-        let thumb = options.thumb.unwrap_or(Function::is_thumb_function(start_address, module_code));
+        let endian = options.endian.unwrap_or(Endian::Little);
+        let thumb = options.thumb.unwrap_or(Function::is_thumb_function(start_address, module_code, endian));
         let parse_mode = if thumb { ParseMode::Thumb } else { ParseMode::Arm };
+        let version = options.version.unwrap_or(ArmVersion::V5Te);
         let offset = first_instruction_offset.unwrap_or(0);
         let start = (start_address - base_address + offset) as usize;
         let function_code = &module_code[start..];
-        let parser = Parser::new(
-            parse_mode,
-            start_address,
-            Endian::Little,
-            ParseFlags { version: ArmVersion::V5Te, ual: false },
-            function_code,
-        );
+        let parser = Parser::new(parse_mode, start_address, endian, ParseFlags { version, ual: false }, function_code);
 
-        Self::function_parser_loop()
+        let result = Self::function_parser_loop()
             .name(name)
             .start_address(start_address)
             .thumb(thumb)
             .parser(parser)
+            .module_code(module_code)
+            .base_address(base_address)
             .maybe_known_end_address(known_end_address)
+            .maybe_next_symbol_address(next_symbol_address)
             .module_start_address(module_start_address)
             .module_end_address(module_end_address)
-            .call()
+            .ual(options.ual)
+            .version(version)
+            .endian(endian)
+            .call()?;
+        let ParseFunctionResult::Found(mut function) = result else {
+            return Ok(result);
+        };
+        if let Some(alignment) = options.alignment {
+            function.set_alignment(alignment)?;
+        }
+        Ok(ParseFunctionResult::Found(function))
     }
 
+    /// Parses the function at `start_address`. If `symbol_map` is given and no return instruction is found before the next
+    /// known function symbol, the function falls through into that function and ends at its address.
     #[builder]
     pub fn parse_function(
         name: String,
@@ -270,6 +437,7 @@ impl Function {
         base_address: u32,
         module_code: &[u8],
         options: Option<ParseFunctionOptions>,
+        symbol_map: Option<&SymbolMap>,
         module_start_address: u32,
         module_end_address: u32,
     ) -> Result<ParseFunctionResult> {
@@ -278,6 +446,9 @@ impl Function {
             .start_address(start_address)
             .module_code(module_code)
             .options(options.unwrap_or_default())
+            .maybe_next_symbol_address(
+                symbol_map.and_then(|symbol_map| Self::next_function_address(symbol_map, start_address)),
+            )
             .base_address(base_address)
             .module_start_address(module_start_address)
             .module_end_address(module_end_address)
@@ -308,41 +479,63 @@ impl Function {
             .call()
     }
 
+    /// Returns the address of the first function symbol after `address`.
+    fn next_function_address(symbol_map: &SymbolMap, address: u32) -> Option<u32> {
+        symbol_map
+            .iter_by_address(address + 1..u32::MAX)
+            .find(|symbol| matches!(symbol.kind, SymbolKind::Function(_)))
+            .map(|symbol| symbol.addr)
+    }
+
+    /// Finds functions in the given range of `module_code`. If `pool_data` is given, pool constants which point into this
+    /// module are also relocated and added as data symbols. New functions are named with `default_name_prefix`, which
+    /// defaults to [`DEFAULT_FUNC_PREFIX`] like
+    /// [`Module::default_func_prefix`](crate::config::module::Module::default_func_prefix) of the main module.
     #[builder]
     pub fn find_functions(
         module_code: &[u8],
         base_addr: u32,
-        default_name_prefix: &str,
+        default_name_prefix: Option<&str>,
         symbol_map: &mut SymbolMap,
         options: FindFunctionsOptions,
+        mut pool_data: Option<PoolDataSymbols<'_>>,
         module_start_address: u32,
         module_end_address: u32,
     ) -> Result<BTreeMap<u32, Function>> {
         let mut functions = BTreeMap::new();
+        let default_name_prefix = default_name_prefix.unwrap_or(DEFAULT_FUNC_PREFIX);
 
         let start_address = options.start_address.unwrap_or(base_addr);
         let start_offset = start_address - base_addr;
-        let end_address = options.end_address.unwrap_or(base_addr + module_code.len() as u32);
+        let end_address = options.search_end_address(base_addr, module_code.len());
         let end_offset = end_address - base_addr;
         let module_code = &module_code[..end_offset as usize];
         let mut function_code = &module_code[start_offset as usize..end_offset as usize];
+        // Addresses can be rounded past the end of the code, which ends the search instead of panicking
+        let code_from = |address: u32| module_code.get((address - base_addr) as usize..).unwrap_or_default();
 
         log::debug!("Searching for functions from {:08x} to {:08x}", start_address, end_address);
 
         let mut last_function_address = options.last_function_address.unwrap_or(end_address);
         let mut address = start_address;
+        let version = options.version.unwrap_or(ArmVersion::V5Te);
+        let endian = options.endian.unwrap_or(Endian::Little);
 
         while !function_code.is_empty() && address <= last_function_address {
-            let thumb = Function::is_thumb_function(address, function_code);
+            let known_function = if options.use_known_function_sizes {
+                symbol_map
+                    .get_function(address)?
+                    .map(|(function, _)| function)
+                    .filter(|function| !function.unknown && function.size > 0 && function.offset == 0)
+            } else {
+                None
+            };
+            let thumb = known_function
+                .and_then(|function| function.mode.into_thumb())
+                .unwrap_or_else(|| Function::is_thumb_function(address, function_code, endian));
 
             let parse_mode = if thumb { ParseMode::Thumb } else { ParseMode::Arm };
-            let parser = Parser::new(
-                parse_mode,
-                address,
-                Endian::Little,
-                ParseFlags { version: ArmVersion::V5Te, ual: false },
-                function_code,
-            );
+            let parser = Parser::new(parse_mode, address, endian, ParseFlags { version, ual: false }, function_code);
 
             let (name, new) = if let Some((_, symbol)) = symbol_map.by_address(address)? {
                 (symbol.name.clone(), false)
@@ -351,13 +544,40 @@ impl Function {
             };
 
             let function_result = Function::function_parser_loop()
-                .name(name)
+                .name(name.clone())
                 .start_address(address)
                 .thumb(thumb)
                 .parser(parser)
+                .module_code(module_code)
+                .base_address(base_addr)
+                .maybe_known_end_address(known_function.map(|function| address + function.size))
+                .maybe_next_symbol_address(Self::next_function_address(symbol_map, address))
                 .module_start_address(module_start_address)
                 .module_end_address(module_end_address)
+                .ual(options.ual)
+                .version(version)
+                .endian(endian)
+                .start_heuristics(options.start_heuristics)
                 .call()?;
+            let function_result = if matches!(
+                function_result,
+                ParseFunctionResult::IllegalIns { .. } | ParseFunctionResult::InvalidStart { .. }
+            ) {
+                // We may have attempted to analyze a literal pool placed ahead of the function's code
+                Function::find_function_after_pool()
+                    .name(name.clone())
+                    .address(address)
+                    .module_code(module_code)
+                    .base_address(base_addr)
+                    .module_start_address(module_start_address)
+                    .module_end_address(module_end_address)
+                    .version(version)
+                    .endian(endian)
+                    .call()?
+                    .map_or(function_result, ParseFunctionResult::Found)
+            } else {
+                function_result
+            };
             let function = match function_result {
                 ParseFunctionResult::Found(function) => function,
                 ParseFunctionResult::IllegalIns { address: illegal_address, ins, .. } => {
@@ -367,21 +587,27 @@ impl Function {
                         if thumb {
                             while !function_code.is_empty()
                                 && address <= last_function_address
-                                && Function::is_thumb_function(address, function_code)
+                                && Function::is_thumb_function(address, function_code, endian)
                             {
                                 address = (address + 1).next_multiple_of(4);
-                                function_code = &module_code[(address - base_addr) as usize..];
+                                function_code = code_from(address);
                             }
                         } else {
                             while !function_code.is_empty()
                                 && address <= last_function_address
-                                && !Function::is_thumb_function(address, function_code)
+                                && !Function::is_thumb_function(address, function_code, endian)
                             {
                                 address = (address + 1).next_multiple_of(2);
-                                function_code = &module_code[(address - base_addr) as usize..];
+                                function_code = code_from(address);
                             }
                         }
                         continue;
+                    } else if options.error_on_illegal_instruction {
+                        let error =
+                            IllegalInstructionSnafu { function: name, address: illegal_address, code: ins.code(), thumb }
+                                .build();
+                        log::error!("{error}");
+                        return Err(error.into());
                     } else {
                         if thumb {
                             log::debug!(
@@ -405,24 +631,27 @@ impl Function {
                 }
                 ParseFunctionResult::InvalidStart { address: start_address, ins, parsed_ins } => {
                     if options.keep_searching_for_valid_function_start {
-                        let ins_size = parse_mode.instruction_size(0);
+                        let ins_size = parse_mode.instruction_size(address);
                         address += ins_size as u32;
-                        function_code = &function_code[ins_size..];
+                        function_code = function_code.get(ins_size..).unwrap_or_default();
                         continue;
                     } else {
+                        let rejection = check_function_start(start_address, ins, &parsed_ins, &options.start_heuristics).err();
                         if thumb {
                             log::debug!(
-                                "Terminating function analysis due to invalid function start at {:08x}: {:04x} {}",
+                                "Terminating function analysis due to invalid function start at {:08x}: {:04x} {} ({:?})",
                                 start_address,
                                 ins.code(),
-                                parsed_ins.display(Default::default())
+                                parsed_ins.display(Default::default()),
+                                rejection
                             );
                         } else {
                             log::debug!(
-                                "Terminating function analysis due to invalid function start at {:08x}: {:08x} {}",
+                                "Terminating function analysis due to invalid function start at {:08x}: {:08x} {} ({:?})",
                                 start_address,
                                 ins.code(),
-                                parsed_ins.display(Default::default())
+                                parsed_ins.display(Default::default()),
+                                rejection
                             );
                         }
                         break;
@@ -430,13 +659,37 @@ impl Function {
                 }
             };
 
+            if function.end_address > end_address {
+                // The function runs past the end of the search, e.g. into a data section
+                log::debug!(
+                    "Terminating function analysis since function at {:08x} ends at {:08x}, past the end address {:08x}",
+                    function.start_address,
+                    function.end_address,
+                    end_address
+                );
+                break;
+            }
+
+            if new && function.size() < options.min_function_size {
+                // Likely padding or data which happened to decode as a function, e.g. a lone `bx lr`
+                log::debug!(
+                    "Skipping function at {:08x} since its size {:#x} is below the minimum {:#x}",
+                    function.start_address,
+                    function.size(),
+                    options.min_function_size
+                );
+                address = function.end_address;
+                function_code = code_from(address);
+                continue;
+            }
+
             if new {
                 symbol_map.add_function(&function);
             }
             function.add_local_symbols_to_map(symbol_map)?;
 
             address = function.end_address;
-            function_code = &module_code[(address - base_addr) as usize..];
+            function_code = code_from(address);
 
             // Look for pointers to data in this module, to use as an upper bound for finding functions
             if options.use_data_as_upper_bound {
@@ -448,16 +701,16 @@ impl Function {
                     if pointer_value >= start_address {
                         let offset = (pointer_value - base_addr) as usize;
                         if offset < module_code.len() {
-                            let thumb = Function::is_thumb_function(pointer_value, &module_code[offset..]);
+                            let thumb = Function::is_thumb_function(pointer_value, &module_code[offset..], endian);
                             let mut parser = Parser::new(
                                 if thumb { ParseMode::Thumb } else { ParseMode::Arm },
                                 pointer_value,
-                                Endian::Little,
-                                ParseFlags { ual: false, version: ArmVersion::V5Te },
+                                endian,
+                                ParseFlags { ual: false, version },
                                 &module_code[offset..],
                             );
                             let (address, ins, parsed_ins) = parser.next().unwrap();
-                            if !is_valid_function_start(address, ins, &parsed_ins) {
+                            if check_function_start(address, ins, &parsed_ins, &options.start_heuristics).is_err() {
                                 // The pool constant points to data, limit the upper bound
                                 last_function_address = pointer_value;
                                 log::debug!(
@@ -474,9 +727,205 @@ impl Function {
 
             functions.insert(function.start_address, function);
         }
+        // Pool constants may point to functions which are found later in the search, so wait until all are known
+        if let Some(pool_data) = &mut pool_data {
+            let module_range = module_start_address..module_end_address;
+            for function in functions.values() {
+                function.add_pool_data_symbols(module_code, base_addr, symbol_map, pool_data, module_range.clone())?;
+            }
+        }
+        if options.check_overlaps {
+            Self::check_overlaps(&functions)?;
+        }
         Ok(functions)
     }
 
+    /// Returns an error if any function ends after the start of the next function.
+    pub fn check_overlaps(functions: &BTreeMap<u32, Function>) -> Result<()> {
+        for (function, next) in functions.values().zip(functions.values().skip(1)) {
+            if function.end_address > next.start_address {
+                log::error!(
+                    "Function {} at {:#010x}..{:#010x} overlaps function {} at {:#010x}..{:#010x}",
+                    function.name,
+                    function.start_address,
+                    function.end_address,
+                    next.name,
+                    next.start_address,
+                    next.end_address
+                );
+                bail!(
+                    "Function {} at {:#010x}..{:#010x} overlaps function {} at {:#010x}..{:#010x}",
+                    function.name,
+                    function.start_address,
+                    function.end_address,
+                    next.name,
+                    next.start_address,
+                    next.end_address
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Same as [`Self::find_functions`], but leaves the symbol map unchanged. The symbols that would have been added are
+    /// returned instead, so the caller can decide which ones to keep.
+    #[builder]
+    pub fn preview_functions(
+        module_code: &[u8],
+        base_addr: u32,
+        default_name_prefix: Option<&str>,
+        symbol_map: &SymbolMap,
+        options: FindFunctionsOptions,
+        module_start_address: u32,
+        module_end_address: u32,
+    ) -> Result<(BTreeMap<u32, Function>, Vec<Symbol>)> {
+        let mut preview_symbol_map = symbol_map.clone();
+        let functions = Self::find_functions()
+            .module_code(module_code)
+            .base_addr(base_addr)
+            .maybe_default_name_prefix(default_name_prefix)
+            .symbol_map(&mut preview_symbol_map)
+            .options(options)
+            .module_start_address(module_start_address)
+            .module_end_address(module_end_address)
+            .call()?;
+        let new_symbols = preview_symbol_map.new_symbols_since(symbol_map).to_vec();
+        Ok((functions, new_symbols))
+    }
+
+    /// Same as [`Self::find_functions`], but splits the search range at known function symbols and searches the parts on
+    /// separate threads. Each thread searches with its own copy of `symbol_map`, and the new symbols are added to
+    /// `symbol_map` in address order once all threads are done. This means a thread will not see functions found by other
+    /// threads, so the search range should contain known functions for this to be useful.
+    #[builder]
+    pub fn find_functions_parallel(
+        module_code: &[u8],
+        base_addr: u32,
+        default_name_prefix: Option<&str>,
+        symbol_map: &mut SymbolMap,
+        options: FindFunctionsOptions,
+        module_start_address: u32,
+        module_end_address: u32,
+    ) -> Result<BTreeMap<u32, Function>> {
+        let start_address = options.start_address.unwrap_or(base_addr);
+        let end_address = options.search_end_address(base_addr, module_code.len());
+
+        let known_functions = symbol_map
+            .iter_by_address(start_address + 1..end_address)
+            .filter(|symbol| matches!(symbol.kind, SymbolKind::Function(_)))
+            .map(|symbol| symbol.addr)
+            .collect::<BTreeSet<_>>();
+        let num_threads = thread::available_parallelism().map_or(1, |n| n.get()).min(known_functions.len() + 1);
+        let chunk_size = known_functions.len().div_ceil(num_threads);
+        let mut boundaries = iter::once(start_address)
+            .chain(known_functions.into_iter().skip(chunk_size).step_by(chunk_size.max(1)))
+            .collect::<Vec<_>>();
+        boundaries.push(end_address);
+        boundaries.dedup();
+
+        let symbol_map_ref = &*symbol_map;
+        let results = thread::scope(|scope| {
+            let handles = boundaries
+                .windows(2)
+                .map(|range| {
+                    let (chunk_start, chunk_end) = (range[0], range[1]);
+                    let chunk_options = FindFunctionsOptions {
+                        start_address: Some(chunk_start),
+                        end_address: Some(chunk_end),
+                        last_function_address: options.last_function_address.map(|last| last.min(chunk_end)),
+                        ..options
+                    };
+                    scope.spawn(move || {
+                        Self::preview_functions()
+                            .module_code(module_code)
+                            .base_addr(base_addr)
+                            .maybe_default_name_prefix(default_name_prefix)
+                            .symbol_map(symbol_map_ref)
+                            .options(chunk_options)
+                            .module_start_address(module_start_address)
+                            .module_end_address(module_end_address)
+                            .call()
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| match handle.join() {
+                    Ok(result) => result,
+                    Err(_) => {
+                        log::error!("Function search thread panicked");
+                        bail!("Function search thread panicked");
+                    }
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let mut functions = BTreeMap::new();
+        for result in results {
+            let (chunk_functions, new_symbols) = result?;
+            functions.extend(chunk_functions);
+            for symbol in new_symbols {
+                symbol_map.add_if_new_address(symbol)?;
+            }
+        }
+        Ok(functions)
+    }
+
+    /// Looks for an ARM function whose literal pool is placed before its first instruction, which happens with far calls
+    /// (GCC's `-mlong-calls`). Returns the function if it loads from every word between `address` and its first instruction.
+    #[builder]
+    fn find_function_after_pool(
+        name: String,
+        address: u32,
+        module_code: &[u8],
+        base_address: u32,
+        module_start_address: u32,
+        module_end_address: u32,
+        #[builder(default = ArmVersion::V5Te)] version: ArmVersion,
+        #[builder(default = Endian::Little)] endian: Endian,
+    ) -> Result<Option<Function>> {
+        if (address & 3) != 0 {
+            return Ok(None);
+        }
+        for num_words in 1..=MAX_PRE_BODY_POOL_WORDS {
+            let entry = address + num_words * 4;
+            let offset = (entry - base_address) as usize;
+            if offset >= module_code.len() {
+                break;
+            }
+            let code = &module_code[offset..];
+            if Function::is_thumb_function(entry, code, endian) {
+                continue;
+            }
+
+            let parser = Parser::new(ParseMode::Arm, entry, endian, ParseFlags { version, ual: false }, code);
+            let ParseFunctionResult::Found(function) = Function::function_parser_loop()
+                .name(name.clone())
+                .start_address(entry)
+                .thumb(false)
+                .parser(parser)
+                .module_code(module_code)
+                .base_address(base_address)
+                .module_start_address(module_start_address)
+                .module_end_address(module_end_address)
+                .version(version)
+                .endian(endian)
+                .call()?
+            else {
+                continue;
+            };
+
+            // The parser loop moves the start address to the first pool constant, see `function_parser_loop`
+            let loads_whole_pool =
+                (address..entry).step_by(4).all(|pool_address| function.pool_constants.contains(&pool_address));
+            if function.start_address == address && loads_whole_pool {
+                log::debug!("Found function at {:#x} after a literal pool at {:#x}", entry, address);
+                return Ok(Some(function));
+            }
+        }
+        Ok(None)
+    }
+
     pub fn add_local_symbols_to_map(&self, symbol_map: &mut SymbolMap) -> Result<()> {
         for address in self.labels.iter() {
             symbol_map.add_label(*address, self.thumb)?;
@@ -490,6 +939,16 @@ impl Function {
         for inline_table in self.inline_tables().values() {
             symbol_map.add_data(None, inline_table.address, inline_table.clone().into())?;
         }
+        for &address in self.pc_relative_addresses.iter() {
+            // Only addresses which will be written as instructions can be labeled. Addresses outside of the function are
+            // added as data by the data analysis instead.
+            if self.code_range().contains(&address)
+                && !self.pool_constants.contains(&address)
+                && Self::inline_table_at(&self.inline_tables, address).is_none()
+            {
+                symbol_map.add_label(address, self.thumb)?;
+            }
+        }
         Ok(())
     }
 
@@ -515,6 +974,7 @@ impl Function {
                     name: function.name().to_string(),
                     start_address: function.start(),
                     end_address: function.end(),
+                    code_end_address: function.end(),
                     first_instruction_address: function.start(),
                     thumb: true,
                     labels: Labels::new(),
@@ -522,6 +982,12 @@ impl Function {
                     jump_tables: JumpTables::new(),
                     inline_tables: InlineTables::new(),
                     function_calls: FunctionCalls::new(),
+                    string_loads: StringLoads::new(),
+                    pc_relative_addresses: PcRelativeAddresses::new(),
+                    ual: false,
+                    version: ArmVersion::V5Te,
+                    endian: Endian::Little,
+                    alignment: 2,
                 };
                 symbol_map.add_function(&function);
                 functions.insert(function.start_address, function);
@@ -532,15 +998,37 @@ impl Function {
     }
 
     pub fn parser<'a>(&'a self, module_code: &'a [u8], base_address: u32) -> Parser {
+        self.parser_with_ual(module_code, base_address, self.ual)
+    }
+
+    fn parser_with_ual<'a>(&self, module_code: &'a [u8], base_address: u32, ual: bool) -> Parser<'a> {
         Parser::new(
-            if self.thumb { ParseMode::Thumb } else { ParseMode::Arm },
+            self.parse_mode(),
             self.start_address,
-            Endian::Little,
-            ParseFlags { ual: false, version: ArmVersion::V5Te },
+            self.endian,
+            ParseFlags { ual, version: self.version },
             self.code(module_code, base_address),
         )
     }
 
+    fn parse_mode(&self) -> ParseMode {
+        if self.thumb {
+            ParseMode::Thumb
+        } else {
+            ParseMode::Arm
+        }
+    }
+
+    /// Returns the decoded instructions of this function. Pool constants, inline tables and every word past
+    /// [`Self::code_end_address`] are decoded as data.
+    pub fn instructions<'a>(&'a self, module_code: &'a [u8], base_address: u32) -> FunctionInstructions<'a> {
+        self.instructions_with_ual(module_code, base_address, self.ual)
+    }
+
+    fn instructions_with_ual<'a>(&'a self, module_code: &'a [u8], base_address: u32, ual: bool) -> FunctionInstructions<'a> {
+        FunctionInstructions { function: self, parser: self.parser_with_ual(module_code, base_address, ual) }
+    }
+
     pub fn code<'a>(&self, module_code: &'a [u8], base_address: u32) -> &'a [u8] {
         let start = (self.start_address - base_address) as usize;
         let end = (self.end_address - base_address) as usize;
@@ -551,6 +1039,21 @@ impl Function {
         &self.name
     }
 
+    /// Returns the destination of a long-branch veneer, i.e. a function consisting only of `ldr pc, [pc, #-4]` followed
+    /// by the destination address. Returns None if the function is not a veneer.
+    pub fn veneer_target(&self, module_code: &[u8], base_address: u32) -> Option<u32> {
+        const LDR_PC_PC_MINUS_4: u32 = 0xe51ff004;
+
+        if self.thumb || self.size() != 8 {
+            return None;
+        }
+        let code = self.code(module_code, base_address);
+        if u32::from_slice(&code[0..4], self.endian) != LDR_PC_PC_MINUS_4 {
+            return None;
+        }
+        Some(u32::from_slice(&code[4..8], self.endian))
+    }
+
     pub fn start_address(&self) -> u32 {
         self.start_address
     }
@@ -559,6 +1062,30 @@ impl Function {
         self.end_address
     }
 
+    pub fn code_end_address(&self) -> u32 {
+        self.code_end_address
+    }
+
+    /// Returns the address range of the whole function, including any trailing literal pool.
+    pub fn address_range(&self) -> Range<u32> {
+        self.start_address..self.end_address
+    }
+
+    /// Returns true if `address` is within this function, including its trailing literal pool.
+    pub fn contains(&self, address: u32) -> bool {
+        self.address_range().contains(&address)
+    }
+
+    /// Returns the address range of the function's instructions, excluding any trailing literal pool.
+    pub fn code_range(&self) -> Range<u32> {
+        self.start_address..self.code_end_address
+    }
+
+    /// Returns the address range of the function's trailing literal pool, or None if it has none.
+    pub fn pool_range(&self) -> Option<Range<u32>> {
+        (self.code_end_address < self.end_address).then_some(self.code_end_address..self.end_address)
+    }
+
     pub fn first_instruction_address(&self) -> u32 {
         self.first_instruction_address
     }
@@ -587,64 +1114,233 @@ impl Function {
         inline_tables.values().find(|table| address >= table.address && address < table.address + table.size)
     }
 
+    /// Estimates how likely it is that this function is real code and not data which happened to disassemble into legal
+    /// instructions, from 0.0 (likely data) to 1.0 (likely code). Lowered by degenerate instructions like `lsl r0, r0, #0`,
+    /// by the absence of calls and returns, and by a low variety of instructions.
+    pub fn code_confidence(&self, module_code: &[u8], base_address: u32) -> f32 {
+        let mut mnemonic_counts: BTreeMap<&str, u32> = BTreeMap::new();
+        let mut num_ins = 0;
+        let mut num_degenerate = 0;
+        let mut has_return = false;
+        for (address, ins, parsed_ins) in self.instructions(module_code, base_address) {
+            if matches!(ins, Ins::Data) {
+                continue;
+            }
+
+            num_ins += 1;
+            *mnemonic_counts.entry(parsed_ins.mnemonic).or_default() += 1;
+            if Self::is_degenerate_instruction(&parsed_ins) {
+                num_degenerate += 1;
+            }
+            has_return |= Self::is_return(ins, &parsed_ins, address, self.start_address);
+        }
+        if num_ins == 0 {
+            return 0.0;
+        }
+
+        let mut confidence = 1.0 - num_degenerate as f32 / num_ins as f32;
+        if self.function_calls.is_empty() && !has_return {
+            confidence *= 0.5;
+        }
+        if num_ins > 1 {
+            let entropy: f32 = mnemonic_counts
+                .values()
+                .map(|&count| {
+                    let p = count as f32 / num_ins as f32;
+                    -p * p.log2()
+                })
+                .sum();
+            let normalized_entropy = entropy / (num_ins as f32).log2();
+            confidence *= 0.5 + 0.5 * normalized_entropy.min(1.0);
+        }
+        confidence.clamp(0.0, 1.0)
+    }
+
+    /// Returns true for illegal instructions and instructions with no effect, which are common when data is disassembled.
+    fn is_degenerate_instruction(parsed_ins: &ParsedIns) -> bool {
+        if parsed_ins.is_illegal() {
+            return true;
+        }
+        let args = &parsed_ins.args;
+        match (parsed_ins.mnemonic, args[0], args[1], args[2]) {
+            ("lsl" | "lsls", Argument::Reg(Reg { reg: dst, .. }), Argument::Reg(Reg { reg: src, .. }), Argument::UImm(0))
+            | ("mov" | "movs", Argument::Reg(Reg { reg: dst, .. }), Argument::Reg(Reg { reg: src, .. }), Argument::None) => {
+                dst == src
+            }
+            _ => false,
+        }
+    }
+
     pub fn pool_constants(&self) -> &PoolConstants {
         &self.pool_constants
     }
 
+    /// Returns the value of each pool constant. Constants which are misaligned or outside of this function's code are
+    /// skipped.
     pub fn iter_pool_constants<'a>(
         &'a self,
         module_code: &'a [u8],
         base_address: u32,
     ) -> impl Iterator<Item = PoolConstant> + '_ {
-        self.pool_constants.iter().map(move |&address| {
-            let start = (address - base_address) as usize;
-            let bytes = &module_code[start as usize..];
-            PoolConstant { address, value: u32::from_le_slice(bytes) }
+        let code = self.code(module_code, base_address);
+        self.pool_constants.iter().filter_map(move |&address| {
+            if address % 4 != 0 || address < self.start_address {
+                return None;
+            }
+            let start = (address - self.start_address) as usize;
+            let bytes = code.get(start..start + 4)?;
+            Some(PoolConstant { address, value: u32::from_slice(bytes, self.endian) })
         })
     }
 
+    fn add_pool_data_symbols(
+        &self,
+        module_code: &[u8],
+        base_address: u32,
+        symbol_map: &mut SymbolMap,
+        pool_data: &mut PoolDataSymbols,
+        module_range: Range<u32>,
+    ) -> Result<()> {
+        for pool_constant in self.iter_pool_constants(module_code, base_address) {
+            let pointer = pool_constant.value;
+            if !module_range.contains(&pointer) {
+                continue;
+            }
+            if symbol_map.get_function(pointer)?.is_none() {
+                let name = format!("{}{:08x}", pool_data.name_prefix, pointer);
+                symbol_map.add_data(Some(name), pointer, SymData::Any)?;
+            }
+            pool_data.relocations.add_load(pool_constant.address, pointer, 0, pool_data.module_kind.try_into()?)?;
+        }
+        Ok(())
+    }
+
     pub fn function_calls(&self) -> &FunctionCalls {
         &self.function_calls
     }
 
+    /// Returns `(from, to, from_thumb, to_thumb)` for every call in this function, which can be passed to
+    /// [`Relocations::add_call`](crate::config::relocation::Relocations::add_call). Calls through a register are left out,
+    /// see [`CalledFunction::is_register_call`].
+    pub fn calls(&self) -> impl Iterator<Item = (u32, u32, bool, bool)> + '_ {
+        self.function_calls
+            .iter()
+            .filter(|(_, called)| !called.is_register_call())
+            .map(|(&from, called)| (from, called.address, self.thumb, called.thumb))
+    }
+
+    /// Addresses of strings that this function reads via a PC-relative address, see [`StringLoadState`].
+    pub fn string_loads(&self) -> &StringLoads {
+        &self.string_loads
+    }
+
+    /// Addresses computed relative to the PC with `add rN, pc, #imm` or `sub rN, pc, #imm`.
+    pub fn pc_relative_addresses(&self) -> &PcRelativeAddresses {
+        &self.pc_relative_addresses
+    }
+
+    pub fn ual(&self) -> bool {
+        self.ual
+    }
+
+    pub fn endian(&self) -> Endian {
+        self.endian
+    }
+
+    pub fn alignment(&self) -> u32 {
+        self.alignment
+    }
+
+    /// Sets the alignment of this function. Alignments stronger than the natural alignment of ARM or Thumb code are written
+    /// as a `.balign` directive before the function.
+    pub fn set_alignment(&mut self, alignment: u32) -> Result<()> {
+        if !alignment.is_power_of_two() {
+            log::error!("Alignment {} of function {} is not a power of two", alignment, self.name);
+            bail!("Alignment {} of function {} is not a power of two", alignment, self.name);
+        }
+        self.alignment = alignment;
+        Ok(())
+    }
+
+    /// Writes this function as assembly. Relocated calls and pool constants are written with symbolic operands, e.g.
+    /// `bl SymbolName` and `.word SymbolName`, so the assembler recreates the relocations. Values without a symbol are
+    /// written as raw values.
     pub fn write_assembly<W: io::Write>(
         &self,
         w: &mut W,
         symbols: &SymbolLookup,
         module_code: &[u8],
         base_address: u32,
-        ual: bool,
+        options: WriteAssemblyOptions,
     ) -> Result<()> {
-        let mode = if self.thumb { ParseMode::Thumb } else { ParseMode::Arm };
-        let mut parser = Parser::new(
-            mode,
-            self.start_address,
-            Endian::Little,
-            ParseFlags { ual, version: ArmVersion::V5Te },
-            self.code(module_code, base_address),
-        );
+        let mut instructions = self.instructions_with_ual(module_code, base_address, options.ual);
+        let comment = options.dialect.comment();
 
         // declare self
-        writeln!(w, "    .global {}", self.name)?;
-        if self.thumb {
-            writeln!(w, "    thumb_func_start {}", self.name)?;
-        } else {
-            writeln!(w, "    arm_func_start {}", self.name)?;
+        if let Some(demangled) = demangle(&self.name) {
+            writeln!(w, "    {comment} {demangled}")?;
+        }
+        let natural_alignment = if self.thumb { 2 } else { 4 };
+        if self.alignment > natural_alignment {
+            writeln!(w, "    .balign {}", self.alignment)?;
+        }
+        if !options.omit_global {
+            writeln!(w, "    .global {}", self.name)?;
         }
-        writeln!(w, "{}: ; 0x{:08x}", self.name, self.start_address)?;
+        match (options.dialect, self.thumb) {
+            (AssemblerDialect::Macros, true) => writeln!(w, "    thumb_func_start {}", self.name)?,
+            (AssemblerDialect::Macros, false) => writeln!(w, "    arm_func_start {}", self.name)?,
+            (AssemblerDialect::Gnu, thumb) => {
+                writeln!(w, "    {}", if thumb { ".thumb" } else { ".arm" })?;
+                writeln!(w, "    .type {}, %function", self.name)?;
+            }
+        }
+        writeln!(w, "{}: {comment} 0x{:08x}", self.name, self.start_address)?;
+
+        let labels = symbols
+            .symbol_map
+            .iter_by_address(self.start_address..self.end_address)
+            .filter(|symbol| matches!(symbol.kind, SymbolKind::Label { .. }))
+            .map(|symbol| (symbol.addr, format!("{}{}", options.label_prefix, symbol.name)))
+            .collect();
+        let lookup = PrefixedLabelLookup { symbols, labels };
 
         let mut jump_table = None;
 
-        while let Some((address, ins, parsed_ins)) = parser.next() {
-            let ins_size = parser.mode.instruction_size(0) as u32;
+        while let Some((address, ins, parsed_ins)) = instructions.next() {
+            let ins_end = instructions.parser.address;
+            let ins_size = ins_end - address;
+
+            // write pool constant
+            if self.pool_constants.contains(&address) {
+                let bytes = &module_code[(address - base_address) as usize..(ins_end - base_address) as usize];
+                let const_value = u32::from_slice(bytes, self.endian);
+
+                let Some(pool_symbol) = symbols.symbol_map.get_pool_constant(address)? else {
+                    log::error!("Pool constant at 0x{:08x} in function {} has no symbol", address, self.name);
+                    bail!("Pool constant at 0x{:08x} in function {} has no symbol", address, self.name);
+                };
+                let mut line = format!("{}: ", pool_symbol.name).into_bytes();
+                if !symbols.write_symbol(&mut line, address, const_value, &mut false, "", comment)? {
+                    writeln!(line, ".word {const_value:#x}")?;
+                }
+                if options.raw_bytes_comments {
+                    // Insert the comment before the line break written above
+                    line.pop();
+                    Self::write_raw_bytes_comment(&mut line, address, bytes, comment)?;
+                    writeln!(line)?;
+                }
+                w.write_all(&line)?;
+                continue;
+            }
 
             // write label
-            if let Some(label) = symbols.symbol_map.get_label(address)? {
-                writeln!(w, "{}:", label.name)?;
+            if let Some(label) = lookup.labels.get(&address) {
+                writeln!(w, "{label}:")?;
             }
             if let Some((table, sym)) = symbols.symbol_map.get_jump_table(address)? {
                 jump_table = Some((table, sym));
-                writeln!(w, "{}: ; jump table", sym.name)?;
+                writeln!(w, "{}: {comment} jump table", sym.name)?;
             }
 
             // write data
@@ -653,9 +1349,9 @@ impl Function {
                     log::error!("Inline tables must have a known size");
                     bail!("Inline tables must have a known size");
                 };
-                parser.seek_forward(address + size as u32);
+                instructions.parser.seek_forward(address + size as u32);
 
-                writeln!(w, "{}: ; inline table", sym.name)?;
+                writeln!(w, "{}: {comment} inline table", sym.name)?;
 
                 let start = (sym.addr - base_address) as usize;
                 let end = start + size as usize;
@@ -672,17 +1368,21 @@ impl Function {
             // write instruction
             match jump_table {
                 Some((table, sym)) if !table.code => {
-                    let (directive, value) =
-                        if self.thumb { (".short", ins.code() as i16 as i32) } else { (".word", ins.code() as i32) };
-                    let label_address = (sym.addr as i32 + value + 2) as u32;
-                    let Some(label) = symbols.symbol_map.get_label(label_address)? else {
+                    // Thumb tables contain offsets relative to the table, ARM tables contain case addresses
+                    let label_address =
+                        if self.thumb { (sym.addr as i32 + ins.code() as i16 as i32 + 2) as u32 } else { ins.code() };
+                    let Some(label) = lookup.labels.get(&label_address) else {
                         log::error!("Expected label for jump table destination 0x{:08x}", label_address);
                         bail!("Expected label for jump table destination 0x{:08x}", label_address);
                     };
-                    write!(w, "    {directive} {} - {} - 2", label.name, sym.name)?;
+                    if self.thumb {
+                        write!(w, "    .short {} - {} - 2", label, sym.name)?;
+                    } else {
+                        write!(w, "    .word {}", label)?;
+                    }
                 }
                 _ => {
-                    if parser.mode != ParseMode::Data {
+                    if !matches!(ins, Ins::Data) {
                         write!(w, "    ")?;
                     }
                     let pc_load_offset = if self.thumb { 4 } else { 8 };
@@ -691,72 +1391,141 @@ impl Function {
                         "{}",
                         parsed_ins.display_with_symbols(
                             DisplayOptions { reg_names: RegNames { ip: true, ..Default::default() } },
-                            unarm::Symbols { lookup: symbols, program_counter: address, pc_load_offset }
+                            unarm::Symbols { lookup: &lookup, program_counter: address, pc_load_offset }
                         )
                     )?;
                     if let Some(reference) = parsed_ins.pc_relative_reference(address, pc_load_offset) {
-                        symbols.write_ambiguous_symbols_comment(w, address, reference)?;
+                        symbols.write_ambiguous_symbols_comment(w, address, reference, comment)?;
+                    }
+                    if options.annotate_interworking {
+                        Self::write_interworking_comment(w, symbols, address, comment)?;
+                    }
+                    if options.inst_comments {
+                        let start = (address - base_address) as usize;
+                        let end = (ins_end - base_address) as usize;
+                        Self::write_inst_comment(w, &module_code[start..end], self.thumb, self.endian, comment)?;
                     }
                 }
             }
+            if options.raw_bytes_comments {
+                let start = (address - base_address) as usize;
+                let end = (ins_end - base_address) as usize;
+                Self::write_raw_bytes_comment(w, address, &module_code[start..end], comment)?;
+            }
 
             // write jump table case
             if let Some((_table, sym)) = jump_table {
                 let case = (address - sym.addr) / ins_size;
-                writeln!(w, " ; case {case}")?;
+                writeln!(w, " {comment} case {case}")?;
             } else {
                 writeln!(w)?;
             }
+        }
 
-            // write pool constants
-            let next_address = address + ins_size;
-            for i in 0.. {
-                let pool_address = next_address + i * 4;
-                if self.pool_constants.contains(&pool_address) {
-                    let start = pool_address - base_address;
-                    let bytes = &module_code[start as usize..];
-                    let const_value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
-
-                    let Some(pool_symbol) = symbols.symbol_map.get_pool_constant(pool_address)? else {
-                        log::error!("Pool constant at 0x{:08x} in function {} has no symbol", pool_address, self.name);
-                        bail!("Pool constant at 0x{:08x} in function {} has no symbol", pool_address, self.name);
-                    };
-                    write!(w, "{}: ", pool_symbol.name)?;
+        match (options.dialect, self.thumb) {
+            (AssemblerDialect::Macros, true) => writeln!(w, "    thumb_func_end {}", self.name)?,
+            (AssemblerDialect::Macros, false) => writeln!(w, "    arm_func_end {}", self.name)?,
+            (AssemblerDialect::Gnu, _) => writeln!(w, "    .size {0}, . - {0}", self.name)?,
+        }
 
-                    if !symbols.write_symbol(w, pool_address, const_value, &mut false, "")? {
-                        writeln!(w, ".word {const_value:#x}")?;
-                    }
-                } else {
-                    if pool_address > parser.address {
-                        parser.seek_forward(pool_address);
-                    }
-                    break;
-                }
-            }
+        writeln!(w)?;
+
+        Ok(())
+    }
+
+    fn write_interworking_comment<W: io::Write>(w: &mut W, symbols: &SymbolLookup, address: u32, comment: &str) -> Result<()> {
+        let Some(relocation) = symbols.relocations.get(address) else { return Ok(()) };
+        match relocation.kind() {
+            RelocationKind::ArmCallThumb => write!(w, " {comment} switches to Thumb")?,
+            RelocationKind::ThumbCallArm => write!(w, " {comment} switches to ARM")?,
+            RelocationKind::ArmCall | RelocationKind::ThumbCall | RelocationKind::Load => {}
         }
+        Ok(())
+    }
 
-        if self.thumb {
-            writeln!(w, "    thumb_func_end {}", self.name)?;
+    fn write_raw_bytes_comment<W: io::Write>(w: &mut W, address: u32, bytes: &[u8], comment: &str) -> Result<()> {
+        write!(w, " {comment} {address:08x}:")?;
+        for byte in bytes {
+            write!(w, " {byte:02x}")?;
+        }
+        Ok(())
+    }
+
+    fn write_inst_comment<W: io::Write>(w: &mut W, code: &[u8], thumb: bool, endian: Endian, comment: &str) -> Result<()> {
+        if thumb {
+            write!(w, " {comment} .inst.n")?;
+            for (i, halfword) in code.chunks_exact(2).enumerate() {
+                let separator = if i == 0 { " " } else { ", " };
+                write!(w, "{separator}0x{:04x}", u16::from_slice(halfword, endian))?;
+            }
         } else {
-            writeln!(w, "    arm_func_end {}", self.name)?;
+            write!(w, " {comment} .inst 0x{:08x}", u32::from_slice(code, endian))?;
         }
+        Ok(())
+    }
+}
 
-        writeln!(w)?;
+/// Looks up symbol names like [`SymbolLookup`], but with [`WriteAssemblyOptions::label_prefix`] in front of label names.
+struct PrefixedLabelLookup<'a> {
+    symbols: &'a SymbolLookup<'a>,
+    /// Prefixed names of the labels in the function being written
+    labels: BTreeMap<u32, String>,
+}
 
-        Ok(())
+impl LookupSymbol for PrefixedLabelLookup<'_> {
+    fn lookup_symbol_name(&self, source: u32, destination: u32) -> Option<&str> {
+        match self.labels.get(&destination) {
+            Some(label) => Some(label),
+            None => self.symbols.lookup_symbol_name(source, destination),
+        }
+    }
+}
+
+#[derive(Default)]
+struct ModeProbe {
+    /// An instruction which pushes LR was found
+    has_entry: bool,
+    /// An illegal instruction was found
+    has_illegal: bool,
+}
+
+/// Iterator over the decoded instructions of a function, see [`Function::instructions`].
+pub struct FunctionInstructions<'a> {
+    function: &'a Function,
+    parser: Parser<'a>,
+}
+
+impl Iterator for FunctionInstructions<'_> {
+    type Item = (u32, Ins, ParsedIns);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let address = self.parser.address;
+        // Thumb code may be padded to align the pool after it, so the padding is not decoded as a word of data
+        let is_data = (address >= self.function.code_end_address && address % 4 == 0)
+            || self.function.pool_constants.contains(&address)
+            || Function::inline_table_at(&self.function.inline_tables, address).is_some();
+        self.parser.mode = if is_data { ParseMode::Data } else { self.function.parse_mode() };
+        self.parser.next()
     }
 }
 
-struct ParseFunctionContext {
+struct ParseFunctionContext<'a> {
     start_address: u32,
     thumb: bool,
     end_address: Option<u32>,
     known_end_address: Option<u32>,
+    /// Address of the next known function, where this function ends if it falls through without returning
+    next_symbol_address: Option<u32>,
+    /// End of the last conditional return outside of a conditional block. The function ends here if no unconditional
+    /// return follows, i.e. if the parser reaches an illegal instruction or the end of the code.
+    conditional_return_end: Option<u32>,
     labels: Labels,
     pool_constants: PoolConstants,
     jump_tables: JumpTables,
     inline_tables: InlineTables,
     function_calls: FunctionCalls,
+    string_loads: StringLoads,
+    pc_relative_addresses: PcRelativeAddresses,
 
     module_start_address: u32,
     module_end_address: u32,
@@ -773,28 +1542,52 @@ struct ParseFunctionContext {
     inline_table_state: InlineTableState,
     /// State machine for detecting illegal code sequences
     illegal_code_state: IllegalCodeState,
+    /// State machine for detecting strings accessed relative to the PC
+    string_load_state: StringLoadState,
 
     prev_ins: Option<Ins>,
+    /// Architecture version of the code, to recognize idioms of older versions
+    version: ArmVersion,
+    /// Whether the previous instruction was `mov lr, pc`
+    prev_ins_mov_lr_pc: bool,
+
+    /// Code of the module, to read pool constants loaded into registers
+    module_code: &'a [u8],
+    base_address: u32,
+    endian: Endian,
+    /// Values of pool constants which are loaded into registers, to find the function called by `mov lr, pc; bx rN`
+    pool_registers: Vec<(Register, u32)>,
 }
 
-impl ParseFunctionContext {
+#[bon]
+impl<'a> ParseFunctionContext<'a> {
+    #[builder]
     pub fn new(
         start_address: u32,
         thumb: bool,
         known_end_address: Option<u32>,
+        next_symbol_address: Option<u32>,
         module_start_address: u32,
         module_end_address: u32,
+        version: ArmVersion,
+        module_code: &'a [u8],
+        base_address: u32,
+        endian: Endian,
     ) -> Self {
         Self {
             start_address,
             thumb,
             end_address: None,
             known_end_address,
+            next_symbol_address,
+            conditional_return_end: None,
             labels: Labels::new(),
             pool_constants: PoolConstants::new(),
             jump_tables: JumpTables::new(),
             inline_tables: InlineTables::new(),
             function_calls: FunctionCalls::new(),
+            string_loads: StringLoads::new(),
+            pc_relative_addresses: PcRelativeAddresses::new(),
 
             module_start_address,
             module_end_address,
@@ -809,12 +1602,29 @@ impl ParseFunctionContext {
             function_branch_state: Default::default(),
             inline_table_state: Default::default(),
             illegal_code_state: Default::default(),
+            string_load_state: Default::default(),
 
             prev_ins: None,
+            version,
+            prev_ins_mov_lr_pc: false,
+
+            module_code,
+            base_address,
+            endian,
+            pool_registers: vec![],
         }
     }
 
     fn handle_ins_inner(&mut self, parser: &mut Parser, address: u32, ins: Ins, parsed_ins: &ParsedIns) -> ParseFunctionState {
+        if let Some(next_symbol_address) = self.next_symbol_address.filter(|&next| address >= next) {
+            log::debug!(
+                "Function at {:#x} has no return before the known function at {:#x}, ending it there",
+                self.start_address,
+                next_symbol_address
+            );
+            self.end_address = Some(next_symbol_address);
+            return ParseFunctionState::Done;
+        }
         if self.pool_constants.contains(&address) {
             parser.seek_forward(address + 4);
             return ParseFunctionState::Continue;
@@ -831,39 +1641,56 @@ impl ParseFunctionContext {
             self.last_conditional_destination = self.last_conditional_destination.max(Some(label));
         }
 
-        if self.jump_table_state.is_numerical_jump_offset() {
+        if self.jump_table_state.is_numerical_jump_offset(address) {
             // Not an instruction, continue
             return ParseFunctionState::Continue;
         }
 
-        let ins_size = if let Ins::Thumb(thumb_ins) = ins {
-            if thumb_ins.op != thumb::Opcode::Bl && thumb_ins.op != thumb::Opcode::BlxI {
-                // Typical Thumb instruction
-                2
-            } else if matches!(parsed_ins.args[0], Argument::BranchDest(_)) {
-                // Combined BL/BLX instruction
-                4
-            } else {
-                // Not combined
+        if let Ins::Thumb(thumb_ins) = ins {
+            let is_bl = thumb_ins.op == thumb::Opcode::Bl || thumb_ins.op == thumb::Opcode::BlxI;
+            if is_bl && !matches!(parsed_ins.args[0], Argument::BranchDest(_)) {
+                // BL/BLX half which was not combined with the other half
                 return ParseFunctionState::IllegalIns { address, ins, parsed_ins: parsed_ins.clone() };
             }
-        } else {
-            // ARM instruction
-            4
-        };
+        }
+        // Thumb instructions can be either 2 or 4 bytes long, so use the number of bytes the parser has consumed
+        let ins_size = parser.address - address;
 
         self.illegal_code_state = self.illegal_code_state.handle(ins, parsed_ins);
         if self.illegal_code_state.is_illegal() {
             return ParseFunctionState::IllegalIns { address, ins, parsed_ins: parsed_ins.clone() };
         }
 
+        if self.version == ArmVersion::V4T && self.prev_ins_mov_lr_pc && Function::is_bx_register(parsed_ins) {
+            // ARMv4T has no `blx rN`, so `mov lr, pc; bx rN` is used to call a function pointer. The function continues after
+            // the call. If rN holds a function address from a pool constant, the call is recorded, but it's relocated at the
+            // pool constant by the data analysis like any other pointer.
+            if let Argument::Reg(Reg { reg, .. }) = parsed_ins.args[0] {
+                let value = self.pool_registers.iter().find(|&&(pool_reg, _)| pool_reg == reg).map(|&(_, value)| value);
+                let thumb = value.map_or(false, |value| (value & 1) != 0);
+                if let Some(destination) = value.filter(|&value| value != 0 && (thumb || (value & 3) == 0)) {
+                    self.function_calls.insert(address, CalledFunction { ins, address: destination & !1, thumb });
+                }
+            }
+            return ParseFunctionState::Continue;
+        }
+
         let in_conditional_block = Some(address) < self.last_conditional_destination;
         if !in_conditional_block {
+            if !ins.is_conditional() && Function::is_computed_jump(ins, &parsed_ins) {
+                log::debug!("Function at {:#x} ends with computed jump at {:#x}", self.start_address, address);
+                self.end_address = Some(address + ins_size);
+                return ParseFunctionState::Done;
+            }
             if Function::is_return(ins, &parsed_ins, address, self.start_address) {
                 // We're not inside a conditional code block, so this is the final return instruction
                 self.end_address = Some(address + ins_size);
                 return ParseFunctionState::Done;
             }
+            if Function::is_conditional_return(ins, parsed_ins) {
+                // Might be an early return, so only end here if nothing but illegal code follows
+                self.conditional_return_end = Some(address + ins_size);
+            }
         }
 
         if address > self.start_address
@@ -878,9 +1705,16 @@ impl ParseFunctionContext {
         self.function_branch_state = self.function_branch_state.handle(ins, &parsed_ins);
         if let Some(destination) = Function::is_branch(ins, &parsed_ins, address) {
             let in_current_module = destination >= self.module_start_address && destination < self.module_end_address;
+            let function_end = self.known_end_address.or(self.next_symbol_address);
+            let in_current_function = destination >= self.start_address && function_end.map_or(true, |end| destination < end);
             if !in_current_module {
                 // Tail call
                 self.function_calls.insert(address, CalledFunction { ins, address: destination, thumb: self.thumb });
+            } else if !in_current_function && !ins.is_conditional() && !in_conditional_block {
+                // Unconditional tail call to another function in this module, so this function has ended
+                self.function_calls.insert(address, CalledFunction { ins, address: destination, thumb: self.thumb });
+                self.end_address = Some(address + ins_size);
+                return ParseFunctionState::Done;
             } else if self.function_branch_state.is_function_branch() {
                 if !ins.is_conditional() && !in_conditional_block {
                     // This is an unconditional backwards function branch, which means this function has ended
@@ -910,8 +1744,22 @@ impl ParseFunctionContext {
             self.inline_tables.insert(table.address, table);
         }
 
+        self.string_load_state = self.string_load_state.handle(self.thumb, address, &parsed_ins);
+        if let Some(string_address) = self.string_load_state.get_string_address() {
+            self.string_loads.insert(string_address);
+        }
+
+        if let Some((_, target)) = Function::is_pc_relative_address(parsed_ins, address, self.thumb) {
+            self.pc_relative_addresses.insert(target);
+        }
+
         if let Some(called_function) = Function::is_function_call(ins, parsed_ins, address, self.thumb) {
-            self.function_calls.insert(address, called_function);
+            if called_function.thumb == self.thumb && called_function.address == address + ins_size {
+                // `bl .+4` only captures the PC in lr for position-independent code, it's not a real call
+                self.labels.insert(called_function.address);
+            } else {
+                self.function_calls.insert(address, called_function);
+            }
         }
 
         ParseFunctionState::Continue
@@ -919,10 +1767,38 @@ impl ParseFunctionContext {
 
     pub fn handle_ins(&mut self, parser: &mut Parser, address: u32, ins: Ins, parsed_ins: &ParsedIns) -> ParseFunctionState {
         let state = self.handle_ins_inner(parser, address, ins, parsed_ins);
+        self.update_pool_registers(address, ins, parsed_ins);
         self.prev_ins = Some(ins);
+        self.prev_ins_mov_lr_pc = Function::is_mov_lr_pc(parsed_ins);
         state
     }
 
+    /// Tracks the values of pool constants loaded into registers, and forgets them when the registers are overwritten.
+    fn update_pool_registers(&mut self, address: u32, ins: Ins, parsed_ins: &ParsedIns) {
+        let mnemonic = parsed_ins.mnemonic;
+        if let Some(pool_address) = Function::is_pool_load(ins, parsed_ins, address, self.thumb) {
+            let Argument::Reg(Reg { reg, .. }) = parsed_ins.args[0] else { return };
+            self.pool_registers.retain(|&(pool_reg, _)| pool_reg != reg);
+            let offset = pool_address.wrapping_sub(self.base_address) as usize;
+            if let Some(bytes) = self.module_code.get(offset..offset.saturating_add(4)) {
+                self.pool_registers.push((reg, u32::from_slice(bytes, self.endian)));
+            }
+        } else if matches!(mnemonic, "bl" | "blx" | "pop")
+            || mnemonic.starts_with("ldm")
+            || Function::is_bx_register(parsed_ins)
+        {
+            // Calls don't preserve all registers, and multiple loads may overwrite any of them
+            self.pool_registers.clear();
+        } else if mnemonic.starts_with("str")
+            || mnemonic.starts_with("stm")
+            || matches!(mnemonic, "push" | "cmp" | "cmn" | "tst" | "teq" | "b" | "bx")
+        {
+            // Doesn't write to the register in the first argument
+        } else if let Argument::Reg(Reg { reg, .. }) = parsed_ins.args[0] {
+            self.pool_registers.retain(|&(pool_reg, _)| pool_reg != reg);
+        }
+    }
+
     fn handle_label(
         &mut self,
         destination: u32,
@@ -969,6 +1845,19 @@ impl ParseFunctionContext {
     }
 
     fn into_function(self, state: ParseFunctionState, name: String) -> Result<ParseFunctionResult> {
+        let state = match state {
+            ParseFunctionState::IllegalIns { address, .. }
+                if self.end_address.is_none() && self.conditional_return_end.is_some_and(|end| end <= address) =>
+            {
+                log::debug!(
+                    "Function at {:#x} ends with conditional return before illegal instruction at {:#x}",
+                    self.start_address,
+                    address
+                );
+                ParseFunctionState::Done
+            }
+            state => state,
+        };
         match state {
             ParseFunctionState::Continue => {
                 log::error!("Cannot turn parse context into function before parsing is done");
@@ -979,13 +1868,13 @@ impl ParseFunctionContext {
             }
             ParseFunctionState::Done => {}
         };
-        let Some(end_address) = self.end_address else {
+        let Some(code_end_address) = self.end_address.or(self.conditional_return_end) else {
             return Ok(ParseFunctionResult::NoEpilogue);
         };
 
         let end_address = self
             .known_end_address
-            .unwrap_or(end_address.max(self.last_pool_address.map(|a| a + 4).unwrap_or(0)).next_multiple_of(4));
+            .unwrap_or(code_end_address.max(self.last_pool_address.map(|a| a + 4).unwrap_or(0)).next_multiple_of(4));
         if end_address > self.module_end_address {
             return Ok(ParseFunctionResult::NoEpilogue);
         }
@@ -994,6 +1883,7 @@ impl ParseFunctionContext {
             name,
             start_address: self.start_address,
             end_address,
+            code_end_address: code_end_address.min(end_address),
             first_instruction_address: self.start_address,
             thumb: self.thumb,
             labels: self.labels,
@@ -1001,14 +1891,65 @@ impl ParseFunctionContext {
             jump_tables: self.jump_tables,
             inline_tables: self.inline_tables,
             function_calls: self.function_calls,
+            string_loads: self.string_loads,
+            pc_relative_addresses: self.pc_relative_addresses,
+            ual: false,
+            version: self.version,
+            endian: Endian::Little,
+            alignment: if self.thumb { 2 } else { 4 },
         }))
     }
 }
 
+#[derive(Default, Clone, Copy)]
+pub struct WriteAssemblyOptions {
+    /// Use Unified Assembler Language (UAL) syntax.
+    pub ual: bool,
+    /// Append the original encoding of each instruction as a `.inst` comment, to verify reassembled code against.
+    pub inst_comments: bool,
+    /// Add a comment to calls which switch between ARM and Thumb mode.
+    pub annotate_interworking: bool,
+    /// Which assembler the output is written for.
+    pub dialect: AssemblerDialect,
+    /// Don't declare the function with `.global`.
+    pub omit_global: bool,
+    /// Append the address and raw bytes of each instruction and pool constant as a comment.
+    pub raw_bytes_comments: bool,
+    /// Prefix of label names, e.g. `.L` to make them local symbols in GNU `as`.
+    pub label_prefix: &'static str,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub enum AssemblerDialect {
+    /// Declares functions with the `arm_func_start`/`thumb_func_start` and `arm_func_end`/`thumb_func_end` macros, and
+    /// writes comments with `;`.
+    #[default]
+    Macros,
+    /// Declares functions with `.arm`/`.thumb`, `.type` and `.size` for GNU `as`, and writes comments with `@`.
+    Gnu,
+}
+
+impl AssemblerDialect {
+    pub fn comment(self) -> &'static str {
+        match self {
+            Self::Macros => ";",
+            Self::Gnu => "@",
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct ParseFunctionOptions {
     /// Whether the function is in Thumb or ARM mode, or None if it should be detected automatically.
     pub thumb: Option<bool>,
+    /// Whether the function should be parsed with Unified Assembler Language (UAL) syntax after analysis.
+    pub ual: bool,
+    /// Architecture version of the code. Defaults to ARMv5TE.
+    pub version: Option<ArmVersion>,
+    /// Byte order of the code. Defaults to little endian.
+    pub endian: Option<Endian>,
+    /// Alignment of the function in bytes. Defaults to the natural alignment of ARM or Thumb code.
+    pub alignment: Option<u32>,
 }
 
 enum ParseFunctionState {
@@ -1034,7 +1975,25 @@ pub enum ParseFunctionResult {
     InvalidStart { address: u32, ins: Ins, parsed_ins: ParsedIns },
 }
 
-#[derive(Default)]
+/// Where [`Function::find_functions`] should put the data symbols and relocations it finds in pool constants.
+pub struct PoolDataSymbols<'a> {
+    pub relocations: &'a mut Relocations,
+    /// The module being analyzed, which pool constants are relocated to.
+    pub module_kind: ModuleKind,
+    /// Prefix of the names of new data symbols.
+    pub name_prefix: &'a str,
+}
+
+#[derive(Debug, Snafu)]
+pub enum FindFunctionsError {
+    #[snafu(display(
+        "Illegal {} instruction {code:#x} at {address:#010x} in function {function}",
+        if *thumb { "Thumb" } else { "ARM" }
+    ))]
+    IllegalInstruction { function: String, address: u32, code: u32, thumb: bool },
+}
+
+#[derive(Default, Clone, Copy)]
 pub struct FindFunctionsOptions {
     /// Address to start searching from. Defaults to the base address.
     pub start_address: Option<u32>,
@@ -1042,10 +2001,42 @@ pub struct FindFunctionsOptions {
     pub last_function_address: Option<u32>,
     /// Address to end the search. Defaults to the base address plus code size.
     pub end_address: Option<u32>,
+    /// Known boundary between code and data, such as the start of the first data section. The search ends at this address
+    /// if it comes before [`Self::end_address`], so no function is found in or runs into the data after it.
+    pub max_address: Option<u32>,
     /// If false, end the search when an illegal starting instruction is found.
     pub keep_searching_for_valid_function_start: bool,
     /// If true, pointers to data will be used to limit the upper bound address.
     pub use_data_as_upper_bound: bool,
+    /// If true, functions which already have a symbol with a known size end at that size instead of being analyzed to find
+    /// their end. Speeds up re-analysis of modules where function symbols are complete.
+    pub use_known_function_sizes: bool,
+    /// Whether found functions should be parsed with Unified Assembler Language (UAL) syntax after analysis.
+    pub ual: bool,
+    /// Newly found functions smaller than this many bytes are skipped. Functions with an existing symbol are always kept.
+    pub min_function_size: u32,
+    /// Architecture version of the code. Defaults to ARMv5TE.
+    pub version: Option<ArmVersion>,
+    /// Byte order of the code. Defaults to little endian.
+    pub endian: Option<Endian>,
+    /// If true, return an error when an illegal instruction is found instead of ending the search. Has no effect if
+    /// [`Self::keep_searching_for_valid_function_start`] is true.
+    pub error_on_illegal_instruction: bool,
+    /// Rules for rejecting function starts. Defaults to all rules.
+    pub start_heuristics: FunctionStartHeuristics,
+    /// If true, return an error if a found function ends after the start of the next one, which is a sign of a function
+    /// having been sized incorrectly.
+    pub check_overlaps: bool,
+}
+
+impl FindFunctionsOptions {
+    /// Returns the address to end the search at, which is [`Self::end_address`] clamped to [`Self::max_address`] but not
+    /// before the start of the search.
+    fn search_end_address(&self, base_addr: u32, code_len: usize) -> u32 {
+        let end_address = self.end_address.unwrap_or(base_addr + code_len as u32);
+        let start_address = self.start_address.unwrap_or(base_addr);
+        self.max_address.map_or(end_address, |max_address| end_address.min(max_address.max(start_address)))
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -1055,7 +2046,850 @@ pub struct CalledFunction {
     pub thumb: bool,
 }
 
+impl CalledFunction {
+    /// Returns true if the function is called through a register, i.e. `mov lr, pc; bx rN` in ARMv4T code. Such calls are
+    /// relocated at the pool constant holding the function address, not at the call instruction.
+    pub fn is_register_call(&self) -> bool {
+        self.ins.mnemonic() == "bx"
+    }
+}
+
 pub struct PoolConstant {
     pub address: u32,
     pub value: u32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::data,
+        config::{
+            relocation::RelocationModule,
+            section::{Section, SectionKind, Sections},
+            symbol::SymbolMaps,
+        },
+    };
+
+    const BASE_ADDRESS: u32 = 0x02000000;
+
+    fn arm_code(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    fn thumb_code(halfwords: &[u16]) -> Vec<u8> {
+        halfwords.iter().flat_map(|halfword| halfword.to_le_bytes()).collect()
+    }
+
+    fn parse(code: &[u8], thumb: bool) -> Function {
+        parse_with_options(code, ParseFunctionOptions { thumb: Some(thumb), ..Default::default() })
+    }
+
+    fn parse_with_options(code: &[u8], options: ParseFunctionOptions) -> Function {
+        let result = Function::parse_function()
+            .name(format!("func_{BASE_ADDRESS:08x}"))
+            .start_address(BASE_ADDRESS)
+            .base_address(BASE_ADDRESS)
+            .module_code(code)
+            .options(options)
+            .module_start_address(BASE_ADDRESS)
+            .module_end_address(BASE_ADDRESS + code.len() as u32)
+            .call()
+            .unwrap();
+        let ParseFunctionResult::Found(function) = result else { panic!("no function found: {result:?}") };
+        function
+    }
+
+    fn find(code: &[u8], symbol_map: &mut SymbolMap, options: FindFunctionsOptions) -> BTreeMap<u32, Function> {
+        Function::find_functions()
+            .module_code(code)
+            .base_addr(BASE_ADDRESS)
+            .default_name_prefix("func_")
+            .symbol_map(symbol_map)
+            .options(options)
+            .module_start_address(BASE_ADDRESS)
+            .module_end_address(BASE_ADDRESS + code.len() as u32)
+            .call()
+            .unwrap()
+    }
+
+    /// Writes the function as if it was in the main module, whose symbols are in `symbol_maps`.
+    fn write_assembly(
+        function: &Function,
+        code: &[u8],
+        symbol_maps: &SymbolMaps,
+        relocations: &Relocations,
+        options: WriteAssemblyOptions,
+    ) -> String {
+        let symbol_map = symbol_maps.get(ModuleKind::Arm9).unwrap();
+        let symbols = SymbolLookup { module_kind: ModuleKind::Arm9, symbol_map, symbol_maps, relocations };
+        let mut output = vec![];
+        function.write_assembly(&mut output, &symbols, code, BASE_ADDRESS, options).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn thumb_string_load() {
+        let mut code = thumb_code(&[
+            0xa101, // add r1, pc, #0x4
+            0x7808, // ldrb r0, [r1]
+            0x4770, // bx lr
+            0x0000,
+        ]);
+        code.extend(b"Hi\0\0");
+
+        let function = parse(&code, true);
+        assert_eq!(function.end_address(), 0x02000006);
+        assert_eq!(function.string_loads().iter().copied().collect::<Vec<_>>(), [0x02000008]);
+
+        let mut sections = Sections::new();
+        sections.add(Section::new(".text".to_string(), SectionKind::Code, 0x02000000, 0x02000008, 4).unwrap()).unwrap();
+        sections.add(Section::new(".rodata".to_string(), SectionKind::Data, 0x02000008, 0x0200000c, 4).unwrap()).unwrap();
+        let mut symbol_map = SymbolMap::new();
+        data::find_local_data_from_pools()
+            .function(&function)
+            .sections(&sections)
+            .module_kind(ModuleKind::Arm9)
+            .symbol_map(&mut symbol_map)
+            .relocations(&mut Relocations::new())
+            .name_prefix("data_")
+            .module_code(&code)
+            .base_address(BASE_ADDRESS)
+            .call()
+            .unwrap();
+        let (data, symbol) = symbol_map.get_data(0x02000008).unwrap().unwrap();
+        assert_eq!(symbol.name, "data_02000008");
+        assert_eq!(data, SymData::Asciz { count: Some(3) });
+    }
+
+    #[test]
+    fn inst_comment() {
+        let write = |code: &[u8], thumb: bool| {
+            let mut output = vec![];
+            Function::write_inst_comment(&mut output, code, thumb, Endian::Little, "@").unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(write(&arm_code(&[0xe12fff1e]), false), " @ .inst 0xe12fff1e");
+        assert_eq!(write(&thumb_code(&[0x4770]), true), " @ .inst.n 0x4770");
+        assert_eq!(write(&thumb_code(&[0xf000, 0xf800]), true), " @ .inst.n 0xf000, 0xf800");
+    }
+
+    #[test]
+    fn code_after_inline_table() {
+        let code = arm_code(&[
+            0xe3a00000, // mov r0, #0x0
+            0x00000001, // inline table
+            0xe12fff1e, // bx lr
+        ]);
+        let function = parse(&code, false);
+        let mut symbol_maps = SymbolMaps::new();
+        let symbol_map = symbol_maps.get_mut(ModuleKind::Arm9);
+        symbol_map.add(Symbol::new_data("data_02000004".to_string(), 0x02000004, SymData::Word { count: Some(1) }, false));
+
+        let assembly = write_assembly(&function, &code, &symbol_maps, &Relocations::new(), Default::default());
+        let (_, after_table) = assembly.split_once("data_02000004: ; inline table\n    .word 0x1\n").unwrap();
+        assert_eq!(after_table, "    bx lr\n    arm_func_end func_02000000\n\n");
+    }
+
+    #[test]
+    fn known_function_sizes_match_full_analysis() {
+        let code = arm_code(&[
+            0xe92d4000, // stmdb sp!, {lr}
+            0xeb000000, // bl func_0200000c
+            0xe8bd8000, // ldmia sp!, {pc}
+            0xe92d4000, // stmdb sp!, {lr}
+            0xe8bd8000, // ldmia sp!, {pc}
+        ]);
+        let mut symbol_map = SymbolMap::new();
+        let full = find(&code, &mut symbol_map, Default::default());
+        assert_eq!(full.keys().copied().collect::<Vec<_>>(), [0x02000000, 0x0200000c]);
+
+        let known =
+            find(&code, &mut symbol_map, FindFunctionsOptions { use_known_function_sizes: true, ..Default::default() });
+        assert_eq!(format!("{known:?}"), format!("{full:?}"));
+    }
+
+    #[test]
+    fn literal_pool_before_function() {
+        let code = arm_code(&[
+            0x02100000, // pool constant, far call target
+            0xe92d4000, // stmdb sp!, {lr}
+            0xe51f0010, // ldr r0, [pc, #-0x10]
+            0xe12fff30, // blx r0
+            0xe8bd8000, // ldmia sp!, {pc}
+        ]);
+        let mut symbol_map = SymbolMap::new();
+        let functions = find(&code, &mut symbol_map, Default::default());
+        assert_eq!(functions.keys().copied().collect::<Vec<_>>(), [0x02000000]);
+
+        let function = &functions[&0x02000000];
+        assert_eq!(function.start_address(), 0x02000000);
+        assert_eq!(function.first_instruction_address(), 0x02000004);
+        assert_eq!(function.end_address(), 0x02000014);
+        assert_eq!(function.pool_constants().iter().copied().collect::<Vec<_>>(), [0x02000000]);
+    }
+
+    #[test]
+    fn interworking_call_annotation() {
+        let mut code = arm_code(&[
+            0xe92d4000, // stmdb sp!, {lr}
+            0xfa000001, // blx func_02000010
+            0xe8bd8000, // ldmia sp!, {pc}
+            0x00000000,
+        ]);
+        code.extend(thumb_code(&[0x4770, 0x0000])); // bx lr
+        let function = parse(&code, false);
+        let mut symbol_maps = SymbolMaps::new();
+        symbol_maps.get_mut(ModuleKind::Arm9).add(Symbol::new_unknown_function("func_02000010".to_string(), 0x02000010, true));
+        let mut relocations = Relocations::new();
+        relocations.add_call(0x02000004, 0x02000010, RelocationModule::Main, false, true).unwrap();
+
+        let options = WriteAssemblyOptions { annotate_interworking: true, ..Default::default() };
+        let assembly = write_assembly(&function, &code, &symbol_maps, &relocations, options);
+        let call = assembly.lines().find(|line| line.starts_with("    blx ")).unwrap();
+        assert_eq!(call, "    blx func_02000010 ; switches to Thumb");
+
+        let assembly = write_assembly(&function, &code, &symbol_maps, &relocations, Default::default());
+        assert!(!assembly.contains("switches to"));
+    }
+
+    #[test]
+    fn relocations_are_written_symbolically() {
+        let code = arm_code(&[
+            0xe92d4000, // stmdb sp!, {lr}
+            0xeb000002, // bl func_02000014
+            0xe59f0000, // ldr r0, [pc, #0x0]
+            0xe8bd8000, // ldmia sp!, {pc}
+            0x02000018, // pool constant
+            0xe12fff1e, // bx lr, next function
+        ]);
+        let function = parse(&code, false);
+        let mut symbol_maps = SymbolMaps::new();
+        let symbol_map = symbol_maps.get_mut(ModuleKind::Arm9);
+        function.add_local_symbols_to_map(symbol_map).unwrap();
+        let unresolved_assembly = write_assembly(&function, &code, &symbol_maps, &Relocations::new(), Default::default());
+        assert!(unresolved_assembly.contains("\n_02000010: .word 0x2000018\n"), "{unresolved_assembly}");
+
+        let symbol_map = symbol_maps.get_mut(ModuleKind::Arm9);
+        symbol_map.add(Symbol::new_unknown_function("func_02000014".to_string(), 0x02000014, false));
+        symbol_map.add(Symbol::new_data("data_02000018".to_string(), 0x02000018, SymData::Word { count: Some(1) }, false));
+        let mut relocations = Relocations::new();
+        relocations.add_call(0x02000004, 0x02000014, RelocationModule::Main, false, false).unwrap();
+        relocations.add_load(0x02000010, 0x02000018, 0, RelocationModule::Main).unwrap();
+
+        let assembly = write_assembly(&function, &code, &symbol_maps, &relocations, Default::default());
+        assert!(assembly.lines().any(|line| line == "    bl func_02000014"), "{assembly}");
+        assert!(assembly.contains("\n_02000010: .word data_02000018\n"), "{assembly}");
+    }
+
+    #[test]
+    fn code_confidence_of_function_and_table() {
+        let code = arm_code(&[
+            0xe92d4000, // stmdb sp!, {lr}
+            0xeb000002, // bl func_02000014
+            0xe59f0000, // ldr r0, [pc, #0x0]
+            0xe8bd8000, // ldmia sp!, {pc}
+            0x02000018, // pool constant
+            0xe12fff1e, // bx lr, next function
+        ]);
+        let function = parse(&code, false);
+        let confidence = function.code_confidence(&code, BASE_ADDRESS);
+        assert!(confidence > 0.9, "{confidence}");
+
+        // Table of 16-bit integers which happens to end in `bx lr`
+        let table = thumb_code(&[0x0001, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x4770]);
+        let function = parse(&table, true);
+        let confidence = function.code_confidence(&table, BASE_ADDRESS);
+        assert!(confidence < 0.5, "{confidence}");
+    }
+
+    #[test]
+    fn function_alignment() {
+        let code = arm_code(&[0xe12fff1e]); // bx lr
+        let mut symbol_maps = SymbolMaps::new();
+        symbol_maps.get_mut(ModuleKind::Arm9);
+
+        let function = parse(&code, false);
+        assert_eq!(function.alignment(), 4);
+        let assembly = write_assembly(&function, &code, &symbol_maps, &Relocations::new(), Default::default());
+        assert!(!assembly.contains(".balign"));
+
+        let options = ParseFunctionOptions { thumb: Some(false), alignment: Some(16), ..Default::default() };
+        let function = parse_with_options(&code, options);
+        assert_eq!(function.alignment(), 16);
+        let assembly = write_assembly(&function, &code, &symbol_maps, &Relocations::new(), Default::default());
+        assert!(assembly.starts_with("    .balign 16\n    .global func_02000000\n"), "{assembly}");
+
+        let mut function = function;
+        assert!(function.set_alignment(12).is_err());
+        assert_eq!(function.alignment(), 16);
+    }
+
+    #[test]
+    fn struct_return_is_not_split() {
+        let code = arm_code(&[
+            0xe3a01001, // mov r1, #0x1
+            0xe3a02002, // mov r2, #0x2
+            0xe5801000, // str r1, [r0]
+            0xe5802004, // str r2, [r0, #0x4]
+            0xe12fff1e, // bx lr
+        ]);
+        let mut symbol_map = SymbolMap::new();
+        let functions = find(&code, &mut symbol_map, Default::default());
+        assert_eq!(functions.keys().copied().collect::<Vec<_>>(), [0x02000000]);
+        assert_eq!(functions[&0x02000000].end_address(), 0x02000014);
+    }
+
+    #[test]
+    fn ual_is_recorded_and_used() {
+        let code = arm_code(&[
+            0xe92d4010, // stmfd sp!, {r4, lr}
+            0xe3a00000, // mov r0, #0x0
+            0xe8bd8010, // ldmfd sp!, {r4, pc}
+        ]);
+        let mnemonics = |function: &Function| {
+            function.parser(&code, BASE_ADDRESS).map(|(_, _, parsed_ins)| parsed_ins.mnemonic).collect::<Vec<_>>()
+        };
+
+        // Pre-UAL syntax writes the full-descending stack ops as their stmdb/ldmia equivalents
+        let pre_ual = parse_with_options(&code, ParseFunctionOptions { thumb: Some(false), ..Default::default() });
+        assert!(!pre_ual.ual());
+        assert_eq!(mnemonics(&pre_ual), ["stmdb", "mov", "ldmia"]);
+
+        let ual = parse_with_options(&code, ParseFunctionOptions { thumb: Some(false), ual: true, ..Default::default() });
+        assert!(ual.ual());
+        assert_eq!(mnemonics(&ual), ["push", "mov", "pop"]);
+        assert_eq!(ual.end_address(), pre_ual.end_address());
+        assert_eq!(ual.end_address(), 0x0200000c);
+    }
+
+    #[test]
+    fn armv4t_register_call() {
+        let code = arm_code(&[
+            0xe92d4000, // stmdb sp!, {lr}
+            0xe59f1008, // ldr r1, [pc, #0x8]
+            0xe1a0e00f, // mov lr, pc
+            0xe12fff11, // bx r1
+            0xe8bd8000, // ldmia sp!, {pc}
+            0x02000018, // pool constant
+            0xe12fff1e, // bx lr, called function
+        ]);
+        let options = ParseFunctionOptions { thumb: Some(false), version: Some(ArmVersion::V4T), ..Default::default() };
+        let function = parse_with_options(&code, options);
+        assert_eq!(function.end_address(), 0x02000018);
+        assert_eq!(function.pool_constants().iter().copied().collect::<Vec<_>>(), [0x02000014]);
+
+        let calls =
+            function.function_calls().iter().map(|(&from, called)| (from, called.address, called.thumb)).collect::<Vec<_>>();
+        assert_eq!(calls, [(0x0200000c, 0x02000018, false)]);
+        assert!(function.function_calls()[&0x0200000c].is_register_call());
+        assert_eq!(function.calls().count(), 0);
+    }
+
+    #[test]
+    fn virtual_dispatch_ends_function() {
+        let code = arm_code(&[
+            0xe5901000, // ldr r1, [r0]
+            0xe591f008, // ldr pc, [r1, #0x8]
+            0xe12fff1e, // bx lr, next function
+        ]);
+        let function = parse(&code, false);
+        assert_eq!(function.end_address(), 0x02000008);
+        assert!(function.pool_constants().is_empty());
+        assert!(function.function_calls().is_empty());
+    }
+
+    #[test]
+    fn preview_leaves_symbol_map_unchanged() {
+        let code = arm_code(&[
+            0xe92d4000, // stmdb sp!, {lr}
+            0xeb000000, // bl 0x0200000c
+            0xe8bd8000, // ldmia sp!, {pc}
+            0xe12fff1e, // bx lr
+        ]);
+
+        let symbol_map = SymbolMap::new();
+        let (functions, new_symbols) = Function::preview_functions()
+            .module_code(&code)
+            .base_addr(BASE_ADDRESS)
+            .default_name_prefix("func_")
+            .symbol_map(&symbol_map)
+            .options(Default::default())
+            .module_start_address(BASE_ADDRESS)
+            .module_end_address(BASE_ADDRESS + code.len() as u32)
+            .call()
+            .unwrap();
+        assert_eq!(symbol_map.iter().count(), 0);
+        assert_eq!(functions.keys().copied().collect::<Vec<_>>(), [0x02000000, 0x0200000c]);
+
+        let mut committed = SymbolMap::new();
+        find(&code, &mut committed, Default::default());
+        let committed_names = committed.iter().map(|symbol| symbol.name.clone()).collect::<Vec<_>>();
+        let mut new_names = new_symbols.iter().map(|symbol| symbol.name.clone()).collect::<Vec<_>>();
+        new_names.sort();
+        assert_eq!(new_names, committed_names);
+    }
+
+    #[test]
+    fn code_and_pool_ranges() {
+        let code = arm_code(&[
+            0xe59f0000, // ldr r0, [pc, #0x0]
+            0xe12fff1e, // bx lr
+            0x02001000, // pool constant
+        ]);
+        let function = parse(&code, false);
+        assert_eq!(function.code_range(), 0x02000000..0x02000008);
+        assert_eq!(function.pool_range(), Some(0x02000008..0x0200000c));
+
+        let function = parse(&arm_code(&[0xe12fff1e]), false);
+        assert_eq!(function.code_range(), 0x02000000..0x02000004);
+        assert_eq!(function.pool_range(), None);
+    }
+
+    #[test]
+    fn default_name_prefix_falls_back_to_module_default() {
+        let code = arm_code(&[
+            0xe92d4000, // stmdb sp!, {lr}
+            0xe8bd8000, // ldmia sp!, {pc}
+        ]);
+        let mut symbol_map = SymbolMap::new();
+        Function::find_functions()
+            .module_code(&code)
+            .base_addr(BASE_ADDRESS)
+            .symbol_map(&mut symbol_map)
+            .options(Default::default())
+            .module_start_address(BASE_ADDRESS)
+            .module_end_address(BASE_ADDRESS + code.len() as u32)
+            .call()
+            .unwrap();
+        let (_, symbol) = symbol_map.get_function(BASE_ADDRESS).unwrap().unwrap();
+        assert_eq!(symbol.name, format!("{DEFAULT_FUNC_PREFIX}{BASE_ADDRESS:08x}"));
+    }
+
+    #[test]
+    fn bl_to_next_instruction_is_not_a_call() {
+        let code = arm_code(&[
+            0xe92d4000, // stmdb sp!, {lr}
+            0xebffffff, // bl 0x02000008
+            0xe8bd8000, // ldmia sp!, {pc}
+        ]);
+        let function = parse(&code, false);
+        assert_eq!(function.end_address(), 0x0200000c);
+        assert!(function.function_calls().is_empty());
+        assert_eq!(function.labels().copied().collect::<Vec<_>>(), [0x02000008]);
+    }
+
+    #[test]
+    fn thumb_32_bit_instruction_size() {
+        let code = thumb_code(&[
+            0xb500, // push {lr}
+            0xf000, 0xf801, // bl 0x02000008
+            0xbd00, // pop {pc}
+            0x4770, // bx lr, called function
+        ]);
+        let function = parse(&code, true);
+        assert_eq!(function.end_address(), 0x02000008);
+        let calls = function.function_calls().iter().map(|(&from, called)| (from, called.address)).collect::<Vec<_>>();
+        assert_eq!(calls, [(0x02000002, 0x02000008)]);
+    }
+
+    #[test]
+    fn min_function_size() {
+        let code = thumb_code(&[
+            0x4770, // bx lr
+            0xb500, // push {lr}
+            0xbd00, // pop {pc}
+            0x4770, // bx lr
+        ]);
+
+        let functions = find(&code, &mut SymbolMap::new(), Default::default());
+        assert_eq!(functions.keys().copied().collect::<Vec<_>>(), [0x02000000, 0x02000002, 0x02000006]);
+
+        let mut symbol_map = SymbolMap::new();
+        let functions = find(&code, &mut symbol_map, FindFunctionsOptions { min_function_size: 4, ..Default::default() });
+        assert_eq!(functions.keys().copied().collect::<Vec<_>>(), [0x02000002]);
+        assert_eq!(symbol_map.iter().map(|symbol| symbol.addr).collect::<Vec<_>>(), [0x02000002]);
+    }
+
+    #[test]
+    fn max_address_stops_at_data() {
+        let code = arm_code(&[
+            0xe92d4000, // stmdb sp!, {lr}
+            0xe8bd8000, // ldmia sp!, {pc}
+            0xe3a00001, // data which decodes as mov r0, #0x1
+            0xe12fff1e, // data which decodes as bx lr
+        ]);
+        let starts = |functions: BTreeMap<u32, Function>| functions.keys().copied().collect::<Vec<_>>();
+
+        assert_eq!(starts(find(&code, &mut SymbolMap::new(), Default::default())), [0x02000000, 0x02000008]);
+
+        // Data starts at 0x02000008, or in the middle of what would otherwise be the second function
+        for max_address in [0x02000008, 0x0200000c] {
+            let mut symbol_map = SymbolMap::new();
+            let options = FindFunctionsOptions { max_address: Some(max_address), ..Default::default() };
+            assert_eq!(starts(find(&code, &mut symbol_map, options)), [0x02000000]);
+            assert_eq!(symbol_map.iter().map(|symbol| symbol.addr).collect::<Vec<_>>(), [0x02000000]);
+        }
+
+        // The lower of the two addresses ends the search
+        let options =
+            FindFunctionsOptions { end_address: Some(0x02000008), max_address: Some(0x02001000), ..Default::default() };
+        assert_eq!(starts(find(&code, &mut SymbolMap::new(), options)), [0x02000000]);
+        let options = FindFunctionsOptions { max_address: Some(0x01ff0000), ..Default::default() };
+        assert!(find(&code, &mut SymbolMap::new(), options).is_empty());
+    }
+
+    #[test]
+    fn arm_branch_table() {
+        let code = arm_code(&[
+            0xe3500002, // cmp r0, #0x2
+            0x8a000005, // bhi 0x02000020
+            0xe08ff100, // add pc, pc, r0, lsl #0x2
+            0xea000003, // b 0x02000020
+            0xea000001, // b 0x0200001c, case 0
+            0xea000002, // b 0x02000024, case 1
+            0xea000003, // b 0x0200002c, case 2
+            0xe3a00001, // mov r0, #0x1
+            0xe12fff1e, // bx lr
+            0xe3a00002, // mov r0, #0x2
+            0xe12fff1e, // bx lr
+            0xe3a00003, // mov r0, #0x3
+            0xe12fff1e, // bx lr
+        ]);
+        let function = parse(&code, false);
+        assert_eq!(function.end_address(), 0x02000034);
+
+        let jump_tables = function.jump_tables().map(|table| (table.address, table.size, table.code)).collect::<Vec<_>>();
+        assert_eq!(jump_tables, [(0x02000010, 12, true)]);
+        let labels = function.labels().copied().collect::<Vec<_>>();
+        for case in [0x0200001c, 0x02000024, 0x0200002c] {
+            assert!(labels.contains(&case), "missing label for case at {case:#010x}");
+        }
+    }
+
+    #[test]
+    fn arm_address_table() {
+        let code = arm_code(&[
+            0xe3500002, // cmp r0, #0x2
+            0x979ff100, // ldrls pc, [pc, r0, lsl #0x2]
+            0xea000008, // b 0x02000030
+            0x02000018, // case 0
+            0x02000020, // case 1
+            0x02000028, // case 2
+            0xe3a00001, // mov r0, #0x1
+            0xe12fff1e, // bx lr
+            0xe3a00002, // mov r0, #0x2
+            0xe12fff1e, // bx lr
+            0xe3a00003, // mov r0, #0x3
+            0xe12fff1e, // bx lr
+            0xe3a00000, // mov r0, #0x0
+            0xe12fff1e, // bx lr
+        ]);
+        let function = parse(&code, false);
+        assert_eq!(function.end_address(), 0x02000038);
+
+        let jump_tables = function.jump_tables().map(|table| (table.address, table.size, table.code)).collect::<Vec<_>>();
+        assert_eq!(jump_tables, [(0x0200000c, 12, false)]);
+        let labels = function.labels().copied().collect::<Vec<_>>();
+        for case in [0x02000018, 0x02000020, 0x02000028] {
+            assert!(labels.contains(&case), "missing label for case at {case:#010x}");
+        }
+
+        let mut symbol_maps = SymbolMaps::new();
+        let symbol_map = symbol_maps.get_mut(ModuleKind::Arm9);
+        symbol_map.add_function(&function);
+        function.add_local_symbols_to_map(symbol_map).unwrap();
+        let mut sections = Sections::new();
+        sections.add(Section::new(".text".to_string(), SectionKind::Code, 0x02000000, 0x02000038, 4).unwrap()).unwrap();
+        let mut relocations = Relocations::new();
+        data::find_local_data_from_pools()
+            .function(&function)
+            .sections(&sections)
+            .module_kind(ModuleKind::Arm9)
+            .symbol_map(symbol_map)
+            .relocations(&mut relocations)
+            .name_prefix("data_")
+            .module_code(&code)
+            .base_address(BASE_ADDRESS)
+            .call()
+            .unwrap();
+        let targets =
+            relocations.iter().map(|relocation| (relocation.from_address(), relocation.to_address())).collect::<Vec<_>>();
+        assert_eq!(targets, [(0x0200000c, 0x02000018), (0x02000010, 0x02000020), (0x02000014, 0x02000028)]);
+
+        let assembly = write_assembly(&function, &code, &symbol_maps, &relocations, Default::default());
+        let (_, table) = assembly.split_once("; jump table\n").unwrap();
+        let entries = table.lines().take(3).collect::<Vec<_>>();
+        assert_eq!(entries, ["    .word _02000018 ; case 0", "    .word _02000020 ; case 1", "    .word _02000028 ; case 2"]);
+        assert!(assembly.contains("_02000018:\n    mov r0, #0x1\n"), "{assembly}");
+    }
+
+    #[test]
+    fn arm_address_table_past_module_end() {
+        let code = arm_code(&[
+            0xe3500002, // cmp r0, #0x2
+            0x979ff100, // ldrls pc, [pc, r0, lsl #0x2]
+            0xe12fff1e, // bx lr
+            0x02000018, // case 0
+            0x02000018, // case 1
+            0x02000018, // case 2
+            0xe12fff1e, // bx lr
+        ]);
+        let function = parse(&code, false);
+        assert_eq!(function.jump_tables().map(|table| table.size).collect::<Vec<_>>(), [12]);
+
+        // The module ends after the first entry of the table
+        let module_code = &code[..0x10];
+        let mut symbol_map = SymbolMap::new();
+        let mut sections = Sections::new();
+        sections.add(Section::new(".text".to_string(), SectionKind::Code, 0x02000000, 0x02000010, 4).unwrap()).unwrap();
+        let mut relocations = Relocations::new();
+        data::find_local_data_from_pools()
+            .function(&function)
+            .sections(&sections)
+            .module_kind(ModuleKind::Arm9)
+            .symbol_map(&mut symbol_map)
+            .relocations(&mut relocations)
+            .name_prefix("data_")
+            .module_code(module_code)
+            .base_address(BASE_ADDRESS)
+            .call()
+            .unwrap();
+        let targets =
+            relocations.iter().map(|relocation| (relocation.from_address(), relocation.to_address())).collect::<Vec<_>>();
+        assert_eq!(targets, [(0x0200000c, 0x02000018)]);
+    }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let code = arm_code(&[
+            0xe92d4000, // stmdb sp!, {lr}
+            0xeb000001, // bl 0x02000010
+            0xe8bd8000, // ldmia sp!, {pc}
+            0xe12fff1e, // bx lr
+            0xe92d4000, // stmdb sp!, {lr}
+            0xe8bd8000, // ldmia sp!, {pc}
+            0xe12fff1e, // bx lr
+        ]);
+        let mut symbol_map = SymbolMap::new();
+        symbol_map.add_unknown_function("func_0200000c".to_string(), 0x0200000c, false);
+        symbol_map.add_unknown_function("func_02000018".to_string(), 0x02000018, false);
+
+        let mut sequential_map = symbol_map.clone();
+        let sequential = find(&code, &mut sequential_map, Default::default());
+        let mut parallel_map = symbol_map.clone();
+        let parallel = Function::find_functions_parallel()
+            .module_code(&code)
+            .base_addr(BASE_ADDRESS)
+            .default_name_prefix("func_")
+            .symbol_map(&mut parallel_map)
+            .options(Default::default())
+            .module_start_address(BASE_ADDRESS)
+            .module_end_address(BASE_ADDRESS + code.len() as u32)
+            .call()
+            .unwrap();
+
+        let ranges = |functions: &BTreeMap<u32, Function>| {
+            functions.values().map(|function| (function.start_address(), function.end_address())).collect::<Vec<_>>()
+        };
+        assert_eq!(ranges(&parallel), ranges(&sequential));
+        let symbols =
+            |symbol_map: &SymbolMap| symbol_map.iter().map(|symbol| (symbol.addr, symbol.name.clone())).collect::<Vec<_>>();
+        assert_eq!(symbols(&parallel_map), symbols(&sequential_map));
+    }
+
+    #[test]
+    fn fall_through_ends_at_next_symbol() {
+        let code = arm_code(&[
+            0xe3a00000, // mov r0, #0x0
+            0xe3a01001, // mov r1, #0x1
+            0xe12fff1e, // bx lr, next function
+        ]);
+        let mut symbol_map = SymbolMap::new();
+        symbol_map.add_unknown_function("func_02000008".to_string(), 0x02000008, false);
+
+        let parse = |symbol_map: Option<&SymbolMap>| {
+            let result = Function::parse_function()
+                .name(format!("func_{BASE_ADDRESS:08x}"))
+                .start_address(BASE_ADDRESS)
+                .base_address(BASE_ADDRESS)
+                .module_code(&code)
+                .options(ParseFunctionOptions { thumb: Some(false), ..Default::default() })
+                .maybe_symbol_map(symbol_map)
+                .module_start_address(BASE_ADDRESS)
+                .module_end_address(BASE_ADDRESS + code.len() as u32)
+                .call()
+                .unwrap();
+            let ParseFunctionResult::Found(function) = result else { panic!("no function found: {result:?}") };
+            function
+        };
+        assert_eq!(parse(Some(&symbol_map)).end_address(), 0x02000008);
+        assert_eq!(parse(None).end_address(), 0x0200000c);
+    }
+
+    #[test]
+    fn pool_pointer_to_later_function_is_not_data() {
+        let code = arm_code(&[
+            0xe59f0000, // ldr r0, [pc, #0x0]
+            0xe12fff1e, // bx lr
+            0x0200000c, // pool constant, points to the function below
+            0xe92d4000, // stmdb sp!, {lr}
+            0xe8bd8000, // ldmia sp!, {pc}
+        ]);
+        let mut symbol_map = SymbolMap::new();
+        let mut relocations = Relocations::new();
+        let functions = Function::find_functions()
+            .module_code(&code)
+            .base_addr(BASE_ADDRESS)
+            .default_name_prefix("func_")
+            .symbol_map(&mut symbol_map)
+            .options(Default::default())
+            .pool_data(PoolDataSymbols { relocations: &mut relocations, module_kind: ModuleKind::Arm9, name_prefix: "data_" })
+            .module_start_address(BASE_ADDRESS)
+            .module_end_address(BASE_ADDRESS + code.len() as u32)
+            .call()
+            .unwrap();
+        assert_eq!(functions.keys().copied().collect::<Vec<_>>(), [0x02000000, 0x0200000c]);
+
+        assert!(symbol_map.get_data(0x0200000c).unwrap().is_none());
+        assert!(symbol_map.get_function(0x0200000c).unwrap().is_some());
+        let relocation = relocations.get(0x02000008).unwrap();
+        assert_eq!(relocation.kind(), RelocationKind::Load);
+        assert_eq!(relocation.to_address(), 0x0200000c);
+    }
+
+    #[test]
+    fn early_conditional_return_does_not_end_function() {
+        let code = arm_code(&[
+            0xe3500000, // cmp r0, #0x0
+            0x0a000001, // beq _02000010
+            0xc12fff1e, // bxgt lr, inside the conditional block
+            0xe3a00001, // mov r0, #0x1
+            0x012fff1e, // bxeq lr, early return
+            0xe3a00002, // mov r0, #0x2
+            0xe12fff1e, // bx lr
+        ]);
+        let function = parse(&code, false);
+        assert_eq!(function.code_end_address(), 0x0200001c);
+        assert_eq!(function.end_address(), 0x0200001c);
+    }
+
+    #[test]
+    fn trailing_conditional_return_ends_function() {
+        let code = arm_code(&[
+            0xe3500000, // cmp r0, #0x0
+            0x03a00001, // moveq r0, #0x1
+            0x012fff1e, // bxeq lr
+        ]);
+        let function = parse(&code, false);
+        assert_eq!(function.code_end_address(), 0x0200000c);
+        assert_eq!(function.end_address(), 0x0200000c);
+    }
+
+    #[test]
+    fn label_reuses_existing_symbol_name() {
+        let code = arm_code(&[
+            0xe3500000, // cmp r0, #0x0
+            0x0a000000, // beq LocalRoutine
+            0xe3a00001, // mov r0, #0x1
+            0xe12fff1e, // bx lr, LocalRoutine
+        ]);
+        let function = parse(&code, false);
+        assert!(function.labels().any(|&label| label == 0x0200000c));
+
+        let mut symbol_maps = SymbolMaps::new();
+        let symbol_map = symbol_maps.get_mut(ModuleKind::Arm9);
+        symbol_map.add_function(&function);
+        symbol_map.add_unknown_function("LocalRoutine".to_string(), 0x0200000c, false);
+        function.add_local_symbols_to_map(symbol_map).unwrap();
+        assert!(symbol_map.get_label(0x0200000c).unwrap().is_none());
+
+        let assembly = write_assembly(&function, &code, &symbol_maps, &Relocations::new(), Default::default());
+        assert!(assembly.lines().any(|line| line == "    beq LocalRoutine"), "{assembly}");
+        assert!(!assembly.contains("_0200000c"), "{assembly}");
+
+        let mut symbol_map = SymbolMap::new();
+        symbol_map.add_data(Some("gTable".to_string()), 0x02000100, SymData::Any).unwrap();
+        let (_, label) = symbol_map.add_label(0x02000100, false).unwrap();
+        assert_eq!(label.name, "gTable");
+    }
+
+    #[test]
+    fn label_prefix() {
+        let code = arm_code(&[
+            0xe3500000, // cmp r0, #0x0
+            0x0a000000, // beq _0200000c
+            0xe3a00001, // mov r0, #0x1
+            0xe12fff1e, // bx lr
+        ]);
+        let function = parse(&code, false);
+        let mut symbol_maps = SymbolMaps::new();
+        let symbol_map = symbol_maps.get_mut(ModuleKind::Arm9);
+        symbol_map.add_function(&function);
+        function.add_local_symbols_to_map(symbol_map).unwrap();
+
+        let assembly = write_assembly(&function, &code, &symbol_maps, &Relocations::new(), Default::default());
+        assert_eq!(
+            assembly,
+            concat!(
+                "    .global func_02000000\n",
+                "    arm_func_start func_02000000\n",
+                "func_02000000: ; 0x02000000\n",
+                "    cmp r0, #0x0\n",
+                "    beq _0200000c\n",
+                "    mov r0, #0x1\n",
+                "_0200000c:\n",
+                "    bx lr\n",
+                "    arm_func_end func_02000000\n",
+                "\n",
+            )
+        );
+
+        let options = WriteAssemblyOptions { label_prefix: ".L", ..Default::default() };
+        let prefixed_assembly = write_assembly(&function, &code, &symbol_maps, &Relocations::new(), options);
+        assert!(prefixed_assembly.lines().any(|line| line == ".L_0200000c:"), "{prefixed_assembly}");
+        assert!(prefixed_assembly.lines().any(|line| line == "    beq .L_0200000c"), "{prefixed_assembly}");
+        assert_eq!(prefixed_assembly.replace(".L_", "_"), assembly);
+    }
+
+    #[test]
+    fn demangled_name_comment() {
+        let code = arm_code(&[
+            0xe12fff1e, // bx lr
+        ]);
+        let parse_named = |name: &str| {
+            let result = Function::parse_function()
+                .name(name.to_string())
+                .start_address(BASE_ADDRESS)
+                .base_address(BASE_ADDRESS)
+                .module_code(&code)
+                .options(ParseFunctionOptions { thumb: Some(false), ..Default::default() })
+                .module_start_address(BASE_ADDRESS)
+                .module_end_address(BASE_ADDRESS + code.len() as u32)
+                .call()
+                .unwrap();
+            let ParseFunctionResult::Found(function) = result else { panic!("no function found: {result:?}") };
+            function
+        };
+        let write = |function: &Function| {
+            let mut symbol_maps = SymbolMaps::new();
+            symbol_maps.get_mut(ModuleKind::Arm9).add_function(function);
+            write_assembly(function, &code, &symbol_maps, &Relocations::new(), Default::default())
+        };
+
+        // Names which aren't mangled get no comment
+        let assembly = write(&parse_named("func_02000000"));
+        assert!(assembly.starts_with("    .global func_02000000\n"), "{assembly}");
+
+        let function = parse_named("_ZN5Actor6updateEv");
+        let assembly = write(&function);
+        assert_eq!(function.name(), "_ZN5Actor6updateEv");
+        assert!(assembly.contains("    .global _ZN5Actor6updateEv\n"), "{assembly}");
+        if cfg!(feature = "demangle") {
+            assert!(assembly.starts_with("    ; Actor::update()\n"), "{assembly}");
+        } else {
+            assert!(!assembly.contains("Actor::update"), "{assembly}");
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use unarm::{ArmVersion, Endian, Ins, ParseFlags, ParseMode, Parser};
+
+use super::function_start::{is_valid_function_start_arm, is_valid_function_start_thumb};
+
+/// Guesses whether a region of code is mostly ARM or Thumb, by disassembling it in both modes and comparing how many
+/// instructions look plausible. This is more reliable than looking at a single function's first instruction.
+pub fn detect_dominant_mode(code: &[u8], base_addr: u32) -> ParseMode {
+    let arm_score = plausible_ratio(ParseMode::Arm, code, base_addr);
+    let thumb_score = plausible_ratio(ParseMode::Thumb, code, base_addr);
+    if arm_score > thumb_score {
+        ParseMode::Arm
+    } else {
+        ParseMode::Thumb
+    }
+}
+
+/// Returns the fraction of instructions in `code` which would be plausible in the given mode.
+fn plausible_ratio(mode: ParseMode, code: &[u8], base_addr: u32) -> f32 {
+    let parser = Parser::new(mode, base_addr, Endian::Little, ParseFlags { version: ArmVersion::V5Te, ual: false }, code);
+
+    let mut num_ins = 0;
+    let mut num_plausible = 0;
+    for (address, ins, parsed_ins) in parser {
+        num_ins += 1;
+        let plausible = match ins {
+            Ins::Arm(ins) => is_valid_function_start_arm(address, ins, &parsed_ins),
+            Ins::Thumb(ins) => is_valid_function_start_thumb(address, ins, &parsed_ins),
+            Ins::Data => false,
+        };
+        if plausible {
+            num_plausible += 1;
+        }
+    }
+
+    if num_ins == 0 {
+        0.0
+    } else {
+        num_plausible as f32 / num_ins as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arm_region() {
+        // stmdb sp!, {r4, lr}; mov r0, #0; ldmia sp!, {r4, pc}; bx lr
+        let code: Vec<u8> =
+            [0xe92d4010u32, 0xe3a00000, 0xe8bd8010, 0xe12fff1e].iter().flat_map(|word| word.to_le_bytes()).collect();
+        assert!(matches!(detect_dominant_mode(&code, 0x02000000), ParseMode::Arm));
+    }
+
+    #[test]
+    fn thumb_region() {
+        // push {r4, lr}; movs r0, #0; pop {r4, pc}; bx lr
+        let code: Vec<u8> = [0xb510u16, 0x2000, 0xbd10, 0x4770].iter().flat_map(|halfword| halfword.to_le_bytes()).collect();
+        assert!(matches!(detect_dominant_mode(&code, 0x02000000), ParseMode::Thumb));
+    }
+}
@@ -1,5 +1,5 @@
 use unarm::{
-    args::{Argument, OffsetImm, Reg, Register, Shift, ShiftImm},
+    args::{Argument, OffsetImm, OffsetReg, Reg, Register, Shift, ShiftImm},
     Ins, ParsedIns,
 };
 
@@ -36,14 +36,14 @@ impl JumpTableState {
 
     pub fn get_label(&self, address: u32, ins: Ins) -> Option<u32> {
         match self {
-            Self::Arm(_) => None,
+            Self::Arm(state) => state.get_label(address, ins),
             Self::Thumb(state) => state.get_label(address, ins),
         }
     }
 
-    pub fn is_numerical_jump_offset(&self) -> bool {
+    pub fn is_numerical_jump_offset(&self, address: u32) -> bool {
         match self {
-            Self::Arm(_) => false,
+            Self::Arm(state) => state.is_numerical_jump_offset(address),
             Self::Thumb(state) => state.is_numerical_jump_offset(),
         }
     }
@@ -57,9 +57,16 @@ pub enum JumpTableStateArm {
 
     /// `...`                           other non-comparing instructions
     /// `addls pc, pc, index, lsl #0x2` jump to nearby branch instruction, OR
-    /// `bgt @skip`                     skip jump table if SIGNED index is out of bounds
+    /// `ldrls pc, [pc, index, lsl #0x2]` jump to address loaded from table, OR
+    /// `bgt @skip`                     skip jump table if SIGNED index is out of bounds, OR
+    /// `bhi @skip`                     skip jump table if index is out of bounds
     JumpOrBranchSigned { index: Register, limit: u32 },
 
+    /// if index is unsigned:  
+    /// `add pc, pc, index, lsl #0x2`   jump to nearby branch instruction, OR  
+    /// `ldr pc, [pc, index, lsl #0x2]` jump to address loaded from table
+    JumpUnsigned { index: Register, limit: u32 },
+
     /// if index is signed:  
     /// `cmp index, #0x0`                check that the index is non-negative
     SignedBaseline { index: Register, limit: u32 },
@@ -70,9 +77,33 @@ pub enum JumpTableStateArm {
 
     /// valid table detected, starts from `table_address` with a size of `limit`
     ValidJumpTable { table_address: u32, limit: u32 },
+
+    /// valid table of case addresses detected, starts from `table_address` with a size of `limit`
+    ValidAddressTable { table_address: u32, limit: u32 },
 }
 
 impl JumpTableStateArm {
+    /// Returns true for `ldr pc, [pc, index, lsl #0x2]` with the given mnemonic.
+    fn is_address_table_load(parsed_ins: &ParsedIns, mnemonic: &str, index: Register) -> bool {
+        let args = &parsed_ins.args;
+        match (args[0], args[1], args[2], args[3]) {
+            (
+                Argument::Reg(Reg { reg: Register::Pc, .. }),
+                Argument::Reg(Reg { reg: Register::Pc, deref: true, .. }),
+                Argument::OffsetReg(OffsetReg { reg, add: true, post_indexed: false }),
+                Argument::ShiftImm(ShiftImm { imm: 2, op: Shift::Lsl }),
+            ) => parsed_ins.mnemonic == mnemonic && reg == index,
+            _ => false,
+        }
+    }
+
+    fn address_table(address: u32, limit: u32, jump_tables: &mut JumpTables) -> Self {
+        let table_address = address + 8;
+        let size = (limit + 1) * 4;
+        jump_tables.insert(table_address, JumpTable { address: table_address, size, code: false });
+        Self::ValidAddressTable { table_address, limit }
+    }
+
     fn check_start(self, parsed_ins: &ParsedIns) -> Option<Self> {
         let args = &parsed_ins.args;
         match (parsed_ins.mnemonic, args[0], args[1], args[2]) {
@@ -84,6 +115,10 @@ impl JumpTableStateArm {
     }
 
     fn handle(self, address: u32, ins: Ins, parsed_ins: &ParsedIns, jump_tables: &mut JumpTables) -> Self {
+        if self.is_numerical_jump_offset(address) {
+            // Table entries are addresses, not instructions
+            return self;
+        }
         if let Some(start) = self.check_start(parsed_ins) {
             return start;
         };
@@ -114,10 +149,34 @@ impl JumpTableStateArm {
                     ("bgt", Argument::BranchDest(_), Argument::None, Argument::None, Argument::None, Argument::None) => {
                         Self::SignedBaseline { index, limit }
                     }
+                    ("bhi", Argument::BranchDest(_), Argument::None, Argument::None, Argument::None, Argument::None) => {
+                        Self::JumpUnsigned { index, limit }
+                    }
+                    _ if Self::is_address_table_load(parsed_ins, "ldrls", index) => {
+                        Self::address_table(address, limit, jump_tables)
+                    }
                     _ if ins.updates_condition_flags() => Self::default(),
                     _ => self,
                 }
             }
+            Self::JumpUnsigned { index, limit } => match (parsed_ins.mnemonic, args[0], args[1], args[2], args[3], args[4]) {
+                (
+                    "add",
+                    Argument::Reg(Reg { reg: Register::Pc, .. }),
+                    Argument::Reg(Reg { reg: Register::Pc, .. }),
+                    Argument::Reg(Reg { reg, .. }),
+                    Argument::ShiftImm(ShiftImm { imm: 2, op: Shift::Lsl }),
+                    Argument::None,
+                ) if reg == index => {
+                    let table_address = address + 8;
+                    let size = (limit + 1) * 4;
+                    jump_tables.insert(table_address, JumpTable { address: table_address, size, code: true });
+                    Self::ValidJumpTable { table_address, limit }
+                }
+                _ if Self::is_address_table_load(parsed_ins, "ldr", index) => Self::address_table(address, limit, jump_tables),
+                _ if ins.updates_condition_flags() => Self::default(),
+                _ => self,
+            },
             Self::SignedBaseline { index, limit } => match (parsed_ins.mnemonic, args[0], args[1], args[2]) {
                 ("cmp", Argument::Reg(Reg { reg, .. }), Argument::UImm(0), Argument::None) if reg == index => {
                     Self::JumpSigned { index, limit }
@@ -141,7 +200,7 @@ impl JumpTableStateArm {
                 _ if ins.updates_condition_flags() => Self::default(),
                 _ => self,
             },
-            Self::ValidJumpTable { table_address, limit } => {
+            Self::ValidJumpTable { table_address, limit } | Self::ValidAddressTable { table_address, limit } => {
                 let end = table_address + limit * 4;
                 if address > end {
                     Self::default()
@@ -154,10 +213,35 @@ impl JumpTableStateArm {
 
     pub fn table_end_address(&self) -> Option<u32> {
         match self {
-            Self::ValidJumpTable { table_address, limit } => Some(table_address + (limit + 1) * 4),
+            Self::ValidJumpTable { table_address, limit } | Self::ValidAddressTable { table_address, limit } => {
+                Some(table_address + (limit + 1) * 4)
+            }
             _ => None,
         }
     }
+
+    pub fn get_label(&self, address: u32, ins: Ins) -> Option<u32> {
+        match self {
+            Self::ValidAddressTable { table_address, limit } => {
+                let end = table_address + limit * 4;
+                if address < *table_address || address > end {
+                    None
+                } else {
+                    Some(ins.code())
+                }
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_numerical_jump_offset(&self, address: u32) -> bool {
+        match self {
+            Self::ValidAddressTable { table_address, limit } => {
+                address >= *table_address && address <= table_address + limit * 4
+            }
+            _ => false,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Default, Debug)]
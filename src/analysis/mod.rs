@@ -5,7 +5,9 @@ pub mod function_start;
 pub mod functions;
 pub mod illegal_code;
 pub mod inline_table;
+pub mod instruction_mode;
 pub mod jump_table;
 pub mod main;
 pub mod overlay_groups;
 pub mod secure_area;
+pub mod string_load;
@@ -0,0 +1,59 @@
+use unarm::{
+    args::{Argument, Reg, Register},
+    ParsedIns,
+};
+
+use super::functions::Function;
+
+/// Detects strings that are accessed relative to the PC, which happens in Thumb code that computes a string's address with
+/// `add rN, pc, #imm` and then reads it byte by byte with `ldrb` using rN as the base register. Such addresses are not loaded
+/// from a pool constant, so they would otherwise not be discovered as data.
+#[derive(Clone, Copy, Default, Debug)]
+pub enum StringLoadState {
+    #[default]
+    Start,
+    AddPc {
+        base: Register,
+        string_address: u32,
+    },
+    ValidLoad {
+        string_address: u32,
+    },
+}
+
+impl StringLoadState {
+    pub fn handle(self, thumb: bool, address: u32, parsed_ins: &ParsedIns) -> Self {
+        if let Some((base, string_address)) = Self::add_pc(thumb, address, parsed_ins) {
+            return Self::AddPc { base, string_address };
+        }
+
+        let args = &parsed_ins.args;
+        match self {
+            Self::Start | Self::ValidLoad { .. } => Self::Start,
+            Self::AddPc { base, string_address } => match (parsed_ins.mnemonic, args[0], args[1]) {
+                ("ldrb", Argument::Reg(_), Argument::Reg(Reg { deref: true, reg, .. })) if reg == base => {
+                    Self::ValidLoad { string_address }
+                }
+                ("cmp" | "cmn" | "tst", _, _) => self,
+                (mnemonic, Argument::Reg(Reg { reg, .. }), _) if reg == base && !mnemonic.starts_with("str") => {
+                    // Base register was overwritten
+                    Self::Start
+                }
+                _ => self,
+            },
+        }
+    }
+
+    /// Returns the destination register and computed address of `add rN, pc, #imm`.
+    fn add_pc(thumb: bool, address: u32, parsed_ins: &ParsedIns) -> Option<(Register, u32)> {
+        if !thumb {
+            return None;
+        }
+        Function::is_pc_relative_address(parsed_ins, address, thumb)
+    }
+
+    pub fn get_string_address(self) -> Option<u32> {
+        let Self::ValidLoad { string_address } = self else { return None };
+        Some(string_address)
+    }
+}
@@ -4,19 +4,18 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use anyhow::{bail, Context, Result};
+use anyhow::{Context, Result};
 use argp::FromArgs;
 use ds_rom::rom::{Rom, RomLoadOptions};
-use object::{Architecture, BinaryFormat, Endianness, RelocationFlags};
 use serde::Serialize;
 
 use crate::{
     config::{
         config::{Config, ConfigAutoload, ConfigModule, ConfigOverlay},
         delinks::{DelinkFile, Delinks},
-        module::{Module, ModuleKind},
-        relocation::Relocations,
-        section::SectionKind,
+        module::{module_base_addresses, Module, ModuleKind},
+        relocation::{ModuleBases, Relocations},
+        section::Sections,
         symbol::SymbolMaps,
     },
     util::io::{create_dir_all, create_file, open_file},
@@ -37,6 +36,14 @@ struct DelinkResult {
     num_gaps: usize,
 }
 
+/// Inputs shared by the delinking of every module.
+struct DelinkContext<'a> {
+    rom: &'a Rom<'a>,
+    elf_path: &'a Path,
+    module_bases: &'a BTreeMap<ModuleKind, u32>,
+    module_sections: &'a BTreeMap<ModuleKind, Sections>,
+}
+
 impl Delink {
     pub fn run(&self) -> Result<()> {
         let config: Config = serde_yml::from_reader(open_file(&self.config_path)?)?;
@@ -50,10 +57,15 @@ impl Delink {
 
         let elf_path = config_path.join(config.delinks_path);
         let mut result = DelinkResult::default();
+        let module_bases = module_base_addresses(&rom)?;
+        let module_sections = Delinks::sections_from_config(config_path, &config)?;
+
+        let context =
+            DelinkContext { rom: &rom, elf_path: &elf_path, module_bases: &module_bases, module_sections: &module_sections };
 
-        self.delink_arm9(&config.main_module, &rom, &elf_path, &mut symbol_maps, &mut result)?;
-        self.delink_autoloads(&config.autoloads, &rom, &elf_path, &mut symbol_maps, &mut result)?;
-        self.delink_overlays(&config.overlays, &rom, &elf_path, &mut symbol_maps, &mut result)?;
+        self.delink_arm9(&config.main_module, &context, &mut symbol_maps, &mut result)?;
+        self.delink_autoloads(&config.autoloads, &context, &mut symbol_maps, &mut result)?;
+        self.delink_overlays(&config.overlays, &context, &mut symbol_maps, &mut result)?;
 
         serde_yml::to_writer(create_file(elf_path.join("delink.yaml"))?, &result)?;
 
@@ -63,8 +75,7 @@ impl Delink {
     fn delink_arm9(
         &self,
         config: &ConfigModule,
-        rom: &Rom,
-        elf_path: &Path,
+        context: &DelinkContext,
         symbol_maps: &mut SymbolMaps,
         result: &mut DelinkResult,
     ) -> Result<()> {
@@ -73,14 +84,18 @@ impl Delink {
         let module_kind = ModuleKind::Arm9;
         let delinks = Delinks::from_file(config_path.join(&config.delinks), module_kind)?;
         let symbol_map = symbol_maps.get_mut(module_kind);
-        let relocations = Relocations::from_file(config_path.join(&config.relocations))?;
+        let mut relocations = Relocations::from_file(
+            config_path.join(&config.relocations),
+            &ModuleBases::for_module(module_kind, context.module_bases)?,
+        )?;
+        relocations.resolve_external_section_targets(module_kind, |kind| context.module_sections.get(&kind))?;
 
-        let code = rom.arm9().code()?;
+        let code = context.rom.arm9().code()?;
         let module = Module::new_arm9(config.name.clone(), symbol_map, relocations, delinks.sections, &code)?;
 
         for file in &delinks.files {
             let (file_path, _) = file.split_file_ext();
-            Self::create_elf_file(&module, file, elf_path.join(format!("{file_path}.o")), &symbol_maps)?;
+            Self::create_elf_file(&module, file, context.elf_path.join(format!("{file_path}.o")), &symbol_maps)?;
 
             if file.gap() {
                 result.num_gaps += 1;
@@ -95,19 +110,22 @@ impl Delink {
     fn delink_autoloads(
         &self,
         autoloads: &[ConfigAutoload],
-        rom: &Rom,
-        elf_path: &Path,
+        context: &DelinkContext,
         symbol_maps: &mut SymbolMaps,
         result: &mut DelinkResult,
     ) -> Result<()> {
-        let rom_autoloads = rom.arm9().autoloads()?;
+        let rom_autoloads = context.rom.arm9().autoloads()?;
         for autoload in autoloads {
             let config_path = self.config_path.parent().unwrap();
 
             let module_kind = ModuleKind::Autoload(autoload.kind);
             let delinks = Delinks::from_file(config_path.join(&autoload.module.delinks), module_kind)?;
             let symbol_map = symbol_maps.get_mut(module_kind);
-            let relocations = Relocations::from_file(config_path.join(&autoload.module.relocations))?;
+            let mut relocations = Relocations::from_file(
+                config_path.join(&autoload.module.relocations),
+                &ModuleBases::for_module(module_kind, context.module_bases)?,
+            )?;
+            relocations.resolve_external_section_targets(module_kind, |kind| context.module_sections.get(&kind))?;
 
             let code = rom_autoloads
                 .iter()
@@ -125,7 +143,7 @@ impl Delink {
 
             for file in &delinks.files {
                 let (file_path, _) = file.split_file_ext();
-                Self::create_elf_file(&module, file, elf_path.join(format!("{file_path}.o")), &symbol_maps)?;
+                Self::create_elf_file(&module, file, context.elf_path.join(format!("{file_path}.o")), &symbol_maps)?;
 
                 if file.gap() {
                     result.num_gaps += 1;
@@ -141,8 +159,7 @@ impl Delink {
     fn delink_overlays(
         &self,
         overlays: &[ConfigOverlay],
-        rom: &Rom,
-        elf_path: &Path,
+        context: &DelinkContext,
         symbol_maps: &mut SymbolMaps,
         result: &mut DelinkResult,
     ) -> Result<()> {
@@ -152,9 +169,13 @@ impl Delink {
             let module_kind = ModuleKind::Overlay(overlay.id);
             let delinks = Delinks::from_file(config_path.join(&overlay.module.delinks), module_kind)?;
             let symbol_map = symbol_maps.get_mut(module_kind);
-            let relocations = Relocations::from_file(config_path.join(&overlay.module.relocations))?;
+            let mut relocations = Relocations::from_file(
+                config_path.join(&overlay.module.relocations),
+                &ModuleBases::for_module(module_kind, context.module_bases)?,
+            )?;
+            relocations.resolve_external_section_targets(module_kind, |kind| context.module_sections.get(&kind))?;
 
-            let code = rom.arm9_overlays()[overlay.id as usize].code();
+            let code = context.rom.arm9_overlays()[overlay.id as usize].code();
             let module = Module::new_overlay(
                 overlay.module.name.clone(),
                 symbol_map,
@@ -166,7 +187,7 @@ impl Delink {
 
             for file in &delinks.files {
                 let (file_path, _) = file.split_file_ext();
-                Self::create_elf_file(&module, file, elf_path.join(format!("{file_path}.o")), &symbol_maps)?;
+                Self::create_elf_file(&module, file, context.elf_path.join(format!("{file_path}.o")), &symbol_maps)?;
 
                 if file.gap() {
                     result.num_gaps += 1;
@@ -198,160 +219,8 @@ impl Delink {
     }
 
     fn delink<'a>(symbol_maps: &SymbolMaps, module: &Module, delink_file: &DelinkFile) -> Result<object::write::Object<'a>> {
-        let symbol_map = symbol_maps.get(module.kind()).unwrap();
-        let mut object = object::write::Object::new(BinaryFormat::Elf, Architecture::Arm, Endianness::Little);
-        object.elf_is_rela = Some(true);
-
-        // Maps address to ObjSection/ObjSymbol
-        let mut obj_sections = BTreeMap::new();
-        let mut obj_symbols = BTreeMap::new();
-
-        let mut error = false;
-
-        for file_section in delink_file.sections.iter() {
-            // Get section data
-            let code = file_section.relocatable_code(module)?.unwrap_or_else(|| vec![]);
-            let name = file_section.name().as_bytes().to_vec();
-            let kind = match file_section.kind() {
-                SectionKind::Code => object::SectionKind::Text,
-                SectionKind::Data => object::SectionKind::Data, // TODO: use ReadOnlyData if .rodata?
-                SectionKind::Bss => object::SectionKind::UninitializedData,
-            };
-
-            // Create section
-            let obj_section_id = object.add_section(vec![], name.clone(), kind);
-            let section = object.section_mut(obj_section_id);
-            if file_section.kind() == SectionKind::Bss {
-                section.append_bss(file_section.size() as u64, 1);
-            } else {
-                section.set_data(code, 1);
-            }
-
-            // Add dummy symbol to make linker notice the section
-            object.add_symbol(object::write::Symbol {
-                name, // same name as section
-                value: 0,
-                size: 0,
-                kind: object::SymbolKind::Label,
-                scope: object::SymbolScope::Compilation,
-                weak: false,
-                section: object::write::SymbolSection::Section(obj_section_id),
-                flags: object::SymbolFlags::None,
-            });
-
-            // Add symbols to section
-            let mut symbols = symbol_map.iter_by_address(file_section.address_range()).peekable();
-            while let Some(symbol) = symbols.next() {
-                // Get symbol data
-                let max_address = symbols.peek().map(|s| s.addr).unwrap_or(file_section.end_address());
-                let kind = symbol.kind.into_obj_symbol_kind();
-                let scope = symbol.kind.into_obj_symbol_scope();
-                let value = (symbol.addr - file_section.start_address()) as u64;
-
-                // Create symbol
-                let symbol_section = object::write::SymbolSection::Section(obj_section_id);
-                let symbol_id = object.add_symbol(object::write::Symbol {
-                    name: symbol.name.clone().into_bytes(),
-                    value,
-                    size: symbol.size(max_address) as u64,
-                    kind,
-                    scope,
-                    weak: false,
-                    section: symbol_section,
-                    flags: object::SymbolFlags::None,
-                });
-                obj_symbols.insert((symbol.addr, module.kind()), symbol_id);
-
-                if file_section.kind() == SectionKind::Code {
-                    // Create mapping symbol
-                    if let Some(name) = symbol.mapping_symbol_name() {
-                        object.add_symbol(object::write::Symbol {
-                            name: name.to_string().into_bytes(),
-                            value,
-                            size: 0,
-                            kind: object::SymbolKind::Label,
-                            scope: object::SymbolScope::Compilation,
-                            weak: false,
-                            section: symbol_section,
-                            flags: object::SymbolFlags::None,
-                        });
-                    }
-                }
-            }
-
-            obj_sections.insert(file_section.start_address(), obj_section_id);
-        }
-
-        // Must start a new loop here so we can know which section a symbol ID belongs to
-        for file_section in delink_file.sections.iter() {
-            let obj_section_id = *obj_sections.get(&file_section.start_address()).unwrap();
-
-            // Add relocations to section
-            for (_, relocation) in module.relocations().iter_range(file_section.address_range()) {
-                // Get relocation data
-                let offset = relocation.from_address() - file_section.start_address();
-                let dest_addr = relocation.to_address();
-                let reloc_module = relocation.module().first_module().unwrap();
-
-                // Get destination symbol
-                let symbol_key = (dest_addr, reloc_module);
-                let symbol_id = if let Some(obj_symbol_id) = obj_symbols.get(&symbol_key) {
-                    *obj_symbol_id
-                } else {
-                    // Get external symbol data
-                    let external_symbol_map = symbol_maps.get(reloc_module).unwrap();
-                    let symbol = if let Some((_, symbol)) = external_symbol_map.by_address(dest_addr)? {
-                        symbol
-                    } else if let Some((_, symbol)) = external_symbol_map.get_function(dest_addr)? {
-                        symbol
-                    } else {
-                        log::error!(
-                            "No symbol found for relocation from 0x{:08x} in {} to 0x{:08x} in {}",
-                            relocation.from_address(),
-                            module.kind(),
-                            dest_addr,
-                            reloc_module
-                        );
-                        error = true;
-                        continue;
-                    };
-
-                    // Add external symbol to section
-                    let kind = relocation.kind().into_obj_symbol_kind();
-                    let symbol_section = object::write::SymbolSection::Undefined;
-                    let symbol_id = object.add_symbol(object::write::Symbol {
-                        name: symbol.name.clone().into_bytes(),
-                        value: 0,
-                        size: 0,
-                        kind,
-                        scope: object::SymbolScope::Compilation,
-                        weak: true,
-                        section: symbol_section,
-                        flags: object::SymbolFlags::None,
-                    });
-                    obj_symbols.insert(symbol_key, symbol_id);
-                    symbol_id
-                };
-
-                // Create relocation
-                let r_type = relocation.kind().into_elf_relocation_type();
-                let addend = relocation.addend();
-                object.add_relocation(
-                    obj_section_id,
-                    object::write::Relocation {
-                        offset: offset as u64,
-                        symbol: symbol_id,
-                        addend,
-                        flags: RelocationFlags::Elf { r_type },
-                    },
-                )?;
-            }
-        }
-
-        if error {
-            bail!("Failed to delink '{}', see errors above", delink_file.name);
-        }
-
-        Ok(object)
+        module
+            .to_object(symbol_maps, &delink_file.sections)
+            .with_context(|| format!("Failed to delink '{}'", delink_file.name))
     }
 }
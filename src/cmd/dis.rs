@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeMap,
     fs::{create_dir_all, File},
     io::{BufWriter, Write},
     path::{Path, PathBuf},
@@ -9,12 +10,13 @@ use argp::FromArgs;
 use ds_rom::rom::{raw::AutoloadKind, Rom, RomLoadOptions};
 
 use crate::{
+    analysis::functions::WriteAssemblyOptions,
     config::{
         config::{Config, ConfigAutoload, ConfigModule, ConfigOverlay},
         delinks::{DelinkFile, Delinks},
-        module::{Module, ModuleKind},
-        relocation::Relocations,
-        section::Section,
+        module::{module_base_addresses, Module, ModuleKind},
+        relocation::{ModuleBases, Relocations},
+        section::{Section, Sections},
         symbol::{Symbol, SymbolKind, SymbolLookup, SymbolMaps},
     },
     util::io::{create_file, open_file, read_file},
@@ -35,6 +37,14 @@ pub struct Disassemble {
     /// Disassemble with Unified Assembler Language (UAL) syntax.
     #[argp(switch, short = 'u')]
     pub ual: bool,
+
+    /// Append the original encoding of each instruction as a `.inst` comment.
+    #[argp(switch)]
+    pub inst_comments: bool,
+
+    /// Add a comment to calls which switch between ARM and Thumb mode.
+    #[argp(switch)]
+    pub annotate_interworking: bool,
 }
 
 impl Disassemble {
@@ -48,13 +58,15 @@ impl Disassemble {
         let extract_path = rom_paths_path.parent().unwrap();
 
         let mut symbol_maps = SymbolMaps::from_config(config_path, &config)?;
+        let module_bases = module_base_addresses(&rom)?;
+        let module_sections = Delinks::sections_from_config(config_path, &config)?;
 
-        self.disassemble_arm9(&config.main_module, &mut symbol_maps, &rom, &extract_path)?;
-        self.disassemble_autoloads(&config.autoloads, &mut symbol_maps, &rom, &extract_path)?;
+        self.disassemble_arm9(&config.main_module, &mut symbol_maps, &rom, &extract_path, &module_bases, &module_sections)?;
+        self.disassemble_autoloads(&config.autoloads, &mut symbol_maps, &rom, &extract_path, &module_bases, &module_sections)?;
         if let Some(arm9_overlays) = &rom.config().arm9_overlays {
             let overlays_path = extract_path.join(&arm9_overlays);
             let overlays_path = overlays_path.parent().unwrap();
-            self.disassemble_overlays(&config.overlays, &mut symbol_maps, overlays_path)?;
+            self.disassemble_overlays(&config.overlays, &mut symbol_maps, overlays_path, &module_bases, &module_sections)?;
         }
 
         Ok(())
@@ -66,13 +78,19 @@ impl Disassemble {
         symbol_maps: &mut SymbolMaps,
         rom: &Rom,
         extract_path: &Path,
+        module_bases: &BTreeMap<ModuleKind, u32>,
+        module_sections: &BTreeMap<ModuleKind, Sections>,
     ) -> Result<()> {
         let config_path = self.config_path.parent().unwrap();
 
         let module_kind = ModuleKind::Arm9;
         let delinks = Delinks::from_file(config_path.join(&config.delinks), module_kind)?;
         let symbol_map = symbol_maps.get_mut(module_kind);
-        let relocations = Relocations::from_file(config_path.join(&config.relocations))?;
+        let mut relocations = Relocations::from_file(
+            config_path.join(&config.relocations),
+            &ModuleBases::for_module(module_kind, module_bases)?,
+        )?;
+        relocations.resolve_external_section_targets(module_kind, |kind| module_sections.get(&kind))?;
 
         let code = read_file(extract_path.join(&rom.config().arm9_bin))?;
         let module = Module::new_arm9(config.name.clone(), symbol_map, relocations, delinks.sections, &code)?;
@@ -96,6 +114,8 @@ impl Disassemble {
         symbol_maps: &mut SymbolMaps,
         rom: &Rom,
         extract_path: &Path,
+        module_bases: &BTreeMap<ModuleKind, u32>,
+        module_sections: &BTreeMap<ModuleKind, Sections>,
     ) -> Result<()> {
         for autoload in autoloads {
             let config_path = self.config_path.parent().unwrap();
@@ -103,7 +123,11 @@ impl Disassemble {
             let module_kind = ModuleKind::Autoload(autoload.kind);
             let delinks = Delinks::from_file(config_path.join(&autoload.module.delinks), module_kind)?;
             let symbol_map = symbol_maps.get_mut(module_kind);
-            let relocations = Relocations::from_file(config_path.join(&autoload.module.relocations))?;
+            let mut relocations = Relocations::from_file(
+                config_path.join(&autoload.module.relocations),
+                &ModuleBases::for_module(module_kind, module_bases)?,
+            )?;
+            relocations.resolve_external_section_targets(module_kind, |kind| module_sections.get(&kind))?;
 
             let autoload_path = match autoload.kind {
                 AutoloadKind::Itcm => &rom.config().itcm.bin,
@@ -140,6 +164,8 @@ impl Disassemble {
         overlays: &[ConfigOverlay],
         symbol_maps: &mut SymbolMaps,
         overlays_path: &Path,
+        module_bases: &BTreeMap<ModuleKind, u32>,
+        module_sections: &BTreeMap<ModuleKind, Sections>,
     ) -> Result<()> {
         let config_path = self.config_path.parent().unwrap();
 
@@ -147,7 +173,11 @@ impl Disassemble {
             let module_kind = ModuleKind::Overlay(overlay.id);
             let delinks = Delinks::from_file(config_path.join(&overlay.module.delinks), module_kind)?;
             let symbol_map = symbol_maps.get_mut(module_kind);
-            let relocations = Relocations::from_file(config_path.join(&overlay.module.relocations))?;
+            let mut relocations = Relocations::from_file(
+                config_path.join(&overlay.module.relocations),
+                &ModuleBases::for_module(module_kind, module_bases)?,
+            )?;
+            relocations.resolve_external_section_targets(module_kind, |kind| module_sections.get(&kind))?;
 
             let code = read_file(overlays_path.join(format!("ov{:03}.bin", overlay.id)))?;
             let module = Module::new_overlay(
@@ -229,7 +259,13 @@ impl Disassemble {
                             writeln!(writer)?;
                         }
 
-                        function.write_assembly(writer, &symbol_lookup, module.code(), module.base_address(), self.ual)?;
+                        function.write_assembly(
+                            writer,
+                            &symbol_lookup,
+                            module.code(),
+                            module.base_address(),
+                            self.write_assembly_options(),
+                        )?;
                         offset = function.end_address() - section.start_address();
                     }
                     SymbolKind::Data(data) => {
@@ -273,6 +309,15 @@ impl Disassemble {
         Ok(())
     }
 
+    fn write_assembly_options(&self) -> WriteAssemblyOptions {
+        WriteAssemblyOptions {
+            ual: self.ual,
+            inst_comments: self.inst_comments,
+            annotate_interworking: self.annotate_interworking,
+            ..Default::default()
+        }
+    }
+
     fn size_to_next_symbol(section: &Section, symbol: &Symbol, next: Option<&&Symbol>) -> u32 {
         if let Some(next_symbol) = next {
             next_symbol.addr.min(section.end_address()) - symbol.addr
@@ -1,4 +1,7 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{bail, Result};
 use argp::FromArgs;
@@ -10,8 +13,9 @@ use crate::{
     config::{
         config::{Config, ConfigAutoload, ConfigModule, ConfigOverlay},
         delinks::Delinks,
-        module::{Module, ModuleKind},
+        module::{AnalysisOptions, Module, ModuleKind},
         program::Program,
+        relocation::ModuleBases,
         symbol::SymbolMaps,
     },
     util::io::{create_dir_all, create_file, open_file},
@@ -45,6 +49,10 @@ pub struct Init {
     /// destination function is encrypted or otherwise wasn't found during function analysis.
     #[argp(switch, hidden_help)]
     pub allow_unknown_function_calls: bool,
+
+    /// Analyzes functions which are only referenced by pointers in data, such as functions in vtables and callback tables.
+    #[argp(switch)]
+    pub follow_data_pointers: bool,
 }
 
 impl Init {
@@ -59,21 +67,26 @@ impl Init {
         let arm9_config_path = arm9_output_path.join("config.yaml");
 
         let mut symbol_maps = SymbolMaps::new();
+        let analysis_options = AnalysisOptions { follow_data_pointers: self.follow_data_pointers };
 
-        let main = Module::analyze_arm9(rom.arm9(), &mut symbol_maps)?;
-        let overlays =
-            rom.arm9_overlays().iter().map(|ov| Module::analyze_overlay(ov, &mut symbol_maps)).collect::<Result<Vec<_>>>()?;
+        let main = Module::analyze_arm9(rom.arm9(), &mut symbol_maps, &analysis_options)?;
+        let overlays = rom
+            .arm9_overlays()
+            .iter()
+            .map(|ov| Module::analyze_overlay(ov, &mut symbol_maps, &analysis_options))
+            .collect::<Result<Vec<_>>>()?;
         let autoloads = rom.arm9().autoloads()?;
         let autoloads = autoloads
             .iter()
             .map(|autoload| match autoload.kind() {
-                AutoloadKind::Itcm => Module::analyze_itcm(autoload, &mut symbol_maps),
+                AutoloadKind::Itcm => Module::analyze_itcm(autoload, &mut symbol_maps, &analysis_options),
                 AutoloadKind::Dtcm => Module::analyze_dtcm(autoload, &mut symbol_maps),
                 AutoloadKind::Unknown(_) => bail!("unknown autoload kind"),
             })
             .collect::<Result<Vec<_>>>()?;
 
         let mut program = Program::new(main, overlays, autoloads, symbol_maps);
+        program.resolve_section_targets()?;
         if !self.skip_reloc_analysis {
             program.analyze_cross_references().allow_unknown_function_calls(self.allow_unknown_function_calls).call()?;
         }
@@ -86,23 +99,18 @@ impl Init {
         rom_config.arm9_overlays = Some(self.build_path.join("build/arm9_overlays.yaml"));
         let rom_config = rom_config;
 
+        let module_bases = program.module_bases();
         let overlay_configs = self.overlay_configs(
             &arm9_output_path,
             &arm9_overlays_output_path,
             program.overlays(),
             "arm9",
             program.symbol_maps(),
+            &module_bases,
         )?;
         let autoload_configs =
-            self.autoload_configs(&arm9_output_path, &rom_config, program.autoloads(), program.symbol_maps())?;
-        let arm9_config = self.arm9_config(
-            &arm9_output_path,
-            &rom_config,
-            program.main(),
-            overlay_configs,
-            autoload_configs,
-            program.symbol_maps(),
-        )?;
+            self.autoload_configs(&arm9_output_path, &rom_config, program.autoloads(), program.symbol_maps(), &module_bases)?;
+        let arm9_config = self.arm9_config(&arm9_output_path, &rom_config, &program, overlay_configs, autoload_configs)?;
 
         if !self.dry {
             create_dir_all(&arm9_output_path)?;
@@ -120,11 +128,12 @@ impl Init {
         &self,
         path: &Path,
         rom_config: &RomConfig,
-        module: &Module,
+        program: &Program,
         overlays: Vec<ConfigOverlay>,
         autoloads: Vec<ConfigAutoload>,
-        symbol_maps: &SymbolMaps,
     ) -> Result<Config> {
+        let module = program.main();
+        let module_bases = program.module_bases();
         let code_hash = fxhash::hash64(module.code());
 
         let delinks_path = path.join("delinks.txt");
@@ -133,8 +142,8 @@ impl Init {
 
         if !self.dry {
             Delinks::to_file(&delinks_path, module.sections())?;
-            symbol_maps.get(module.kind()).unwrap().to_file(&symbols_path)?;
-            module.relocations().to_file(&relocations_path)?;
+            program.symbol_maps().get(module.kind()).unwrap().to_file(&symbols_path)?;
+            module.relocations().to_file(&relocations_path, &ModuleBases::for_module(module.kind(), &module_bases)?)?;
         }
 
         Ok(Config {
@@ -160,6 +169,7 @@ impl Init {
         rom_config: &RomConfig,
         modules: &[Module],
         symbol_maps: &SymbolMaps,
+        module_bases: &BTreeMap<ModuleKind, u32>,
     ) -> Result<Vec<ConfigAutoload>> {
         let mut autoloads = vec![];
         for module in modules {
@@ -187,7 +197,7 @@ impl Init {
             if !self.dry {
                 Delinks::to_file(&delinks_path, module.sections())?;
                 symbol_maps.get(module.kind()).unwrap().to_file(&symbols_path)?;
-                module.relocations().to_file(&relocs_path)?;
+                module.relocations().to_file(&relocs_path, &ModuleBases::for_module(module.kind(), module_bases)?)?;
             }
 
             autoloads.push(ConfigAutoload {
@@ -213,6 +223,7 @@ impl Init {
         modules: &[Module],
         processor: &str,
         symbol_maps: &SymbolMaps,
+        module_bases: &BTreeMap<ModuleKind, u32>,
     ) -> Result<Vec<ConfigOverlay>> {
         let mut overlays = vec![];
 
@@ -235,7 +246,7 @@ impl Init {
             if !self.dry {
                 Delinks::to_file(&delinks_path, module.sections())?;
                 symbol_maps.get(module.kind()).unwrap().to_file(&symbols_path)?;
-                module.relocations().to_file(&relocs_path)?;
+                module.relocations().to_file(&relocs_path, &ModuleBases::for_module(module.kind(), module_bases)?)?;
             }
 
             overlays.push(ConfigOverlay {
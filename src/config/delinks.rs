@@ -1,6 +1,6 @@
 use std::{
     cmp::Ordering,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fmt::Display,
     fs::File,
     io::{BufRead, BufReader, BufWriter, Lines, Write},
@@ -14,6 +14,7 @@ use petgraph::{graph::NodeIndex, Graph};
 use crate::util::io::{create_file, open_file};
 
 use super::{
+    config::Config,
     module::ModuleKind,
     section::{Section, Sections},
     ParseContext,
@@ -46,11 +47,13 @@ impl Delinks {
         let mut lines = reader.lines();
         while let Some(line) = lines.next() {
             context.row += 1;
+            // Trim trailing whitespace, including the '\r' of CRLF line endings
             let line = line?;
-            if Self::try_parse_delink_file(&line, &mut lines, &mut context, &mut files, &sections)? {
+            let line = line.trim_end();
+            if Self::try_parse_delink_file(line, &mut lines, &mut context, &mut files, &sections)? {
                 break;
             }
-            let Some(section) = Section::parse(&line, &context)? else {
+            let Some(section) = Section::parse(line, &context)? else {
                 continue;
             };
             sections.add(section)?;
@@ -59,7 +62,7 @@ impl Delinks {
         while let Some(line) = lines.next() {
             context.row += 1;
             let line = line?;
-            Self::try_parse_delink_file(&line, &mut lines, &mut context, &mut files, &sections)?;
+            Self::try_parse_delink_file(line.trim_end(), &mut lines, &mut context, &mut files, &sections)?;
         }
 
         let mut delinks = Delinks { sections, files, module_kind };
@@ -67,6 +70,25 @@ impl Delinks {
         Ok(delinks)
     }
 
+    /// Reads the sections of every module in the config, e.g. to resolve relocations which target sections in other modules.
+    pub fn sections_from_config<P: AsRef<Path>>(config_path: P, config: &Config) -> Result<BTreeMap<ModuleKind, Sections>> {
+        let config_path = config_path.as_ref();
+
+        let mut sections = BTreeMap::new();
+        let module_kind = ModuleKind::Arm9;
+        sections.insert(module_kind, Self::from_file(config_path.join(&config.main_module.delinks), module_kind)?.sections);
+        for autoload in &config.autoloads {
+            let module_kind = ModuleKind::Autoload(autoload.kind);
+            sections.insert(module_kind, Self::from_file(config_path.join(&autoload.module.delinks), module_kind)?.sections);
+        }
+        for overlay in &config.overlays {
+            let module_kind = ModuleKind::Overlay(overlay.id);
+            sections.insert(module_kind, Self::from_file(config_path.join(&overlay.module.delinks), module_kind)?.sections);
+        }
+
+        Ok(sections)
+    }
+
     fn try_parse_delink_file(
         line: &str,
         lines: &mut Lines<BufReader<File>>,
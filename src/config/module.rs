@@ -1,7 +1,12 @@
-use std::{collections::BTreeMap, fmt::Display};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    io::Write,
+};
 
 use anyhow::{bail, Context, Result};
-use ds_rom::rom::{raw::AutoloadKind, Arm9, Autoload, Overlay};
+use ds_rom::rom::{raw::AutoloadKind, Arm9, Autoload, Overlay, Rom};
+use object::{Architecture, BinaryFormat, Endianness};
 
 use crate::{
     analysis::{
@@ -19,6 +24,11 @@ use super::{
     symbol::{SymData, SymbolKind, SymbolMap, SymbolMaps},
 };
 
+/// Prefix of generated function names in the main module and autoloads, see [`Module::default_func_prefix`].
+pub const DEFAULT_FUNC_PREFIX: &str = "func_";
+/// Prefix of generated data and .bss symbol names in the main module and autoloads, see [`Module::default_data_prefix`].
+pub const DEFAULT_DATA_PREFIX: &str = "data_";
+
 pub struct Module<'a> {
     name: String,
     kind: ModuleKind,
@@ -26,8 +36,8 @@ pub struct Module<'a> {
     code: &'a [u8],
     base_address: u32,
     bss_size: u32,
-    pub default_func_prefix: String,
-    pub default_data_prefix: String,
+    default_func_prefix: String,
+    default_data_prefix: String,
     sections: Sections,
 }
 
@@ -35,7 +45,7 @@ impl<'a> Module<'a> {
     pub fn new_arm9(
         name: String,
         symbol_map: &mut SymbolMap,
-        relocations: Relocations,
+        mut relocations: Relocations,
         mut sections: Sections,
         code: &'a [u8],
     ) -> Result<Module<'a>> {
@@ -43,6 +53,7 @@ impl<'a> Module<'a> {
         let end_address = sections.end_address().context("no sections provided")?;
         let bss_size = sections.bss_size();
         Self::import_functions(symbol_map, &mut sections, base_address, end_address, code)?;
+        relocations.resolve_section_targets(ModuleKind::Arm9, &sections)?;
         Ok(Self {
             name,
             kind: ModuleKind::Arm9,
@@ -50,13 +61,13 @@ impl<'a> Module<'a> {
             code,
             base_address,
             bss_size,
-            default_func_prefix: "func_".to_string(),
-            default_data_prefix: "data_".to_string(),
+            default_func_prefix: DEFAULT_FUNC_PREFIX.to_string(),
+            default_data_prefix: DEFAULT_DATA_PREFIX.to_string(),
             sections,
         })
     }
 
-    pub fn analyze_arm9(arm9: &'a Arm9, symbol_maps: &mut SymbolMaps) -> Result<Self> {
+    pub fn analyze_arm9(arm9: &'a Arm9, symbol_maps: &mut SymbolMaps, options: &AnalysisOptions) -> Result<Self> {
         let ctor_range = CtorRange::find_in_arm9(&arm9)?;
         let main_func = MainFunction::find_in_arm9(&arm9)?;
 
@@ -67,13 +78,16 @@ impl<'a> Module<'a> {
             code: arm9.code()?,
             base_address: arm9.base_address(),
             bss_size: arm9.bss()?.len() as u32,
-            default_func_prefix: "func_".to_string(),
-            default_data_prefix: "data_".to_string(),
+            default_func_prefix: DEFAULT_FUNC_PREFIX.to_string(),
+            default_data_prefix: DEFAULT_DATA_PREFIX.to_string(),
             sections: Sections::new(),
         };
         let symbol_map = symbol_maps.get_mut(module.kind);
 
         module.find_sections_arm9(symbol_map, ctor_range, main_func, &arm9)?;
+        if options.follow_data_pointers {
+            module.find_functions_from_data_pointers(symbol_map)?;
+        }
         module.find_data_from_pools(symbol_map)?;
         module.find_data_from_sections(symbol_map)?;
 
@@ -86,7 +100,7 @@ impl<'a> Module<'a> {
     pub fn new_overlay(
         name: String,
         symbol_map: &mut SymbolMap,
-        relocations: Relocations,
+        mut relocations: Relocations,
         mut sections: Sections,
         id: u16,
         code: &'a [u8],
@@ -95,6 +109,7 @@ impl<'a> Module<'a> {
         let end_address = sections.end_address().context("no sections provided")?;
         let bss_size = sections.bss_size();
         Self::import_functions(symbol_map, &mut sections, base_address, end_address, code)?;
+        relocations.resolve_section_targets(ModuleKind::Overlay(id), &sections)?;
         Ok(Self {
             name,
             kind: ModuleKind::Overlay(id),
@@ -108,7 +123,7 @@ impl<'a> Module<'a> {
         })
     }
 
-    pub fn analyze_overlay(overlay: &'a Overlay, symbol_maps: &mut SymbolMaps) -> Result<Self> {
+    pub fn analyze_overlay(overlay: &'a Overlay, symbol_maps: &mut SymbolMaps, options: &AnalysisOptions) -> Result<Self> {
         let mut module = Self {
             name: format!("ov{:03}", overlay.id()),
             kind: ModuleKind::Overlay(overlay.id()),
@@ -124,6 +139,9 @@ impl<'a> Module<'a> {
 
         log::debug!("Analyzing overlay {}", overlay.id());
         module.find_sections_overlay(symbol_map, CtorRange { start: overlay.ctor_start(), end: overlay.ctor_end() })?;
+        if options.follow_data_pointers {
+            module.find_functions_from_data_pointers(symbol_map)?;
+        }
         module.find_data_from_pools(symbol_map)?;
         module.find_data_from_sections(symbol_map)?;
 
@@ -133,7 +151,7 @@ impl<'a> Module<'a> {
     pub fn new_autoload(
         name: String,
         symbol_map: &mut SymbolMap,
-        relocations: Relocations,
+        mut relocations: Relocations,
         mut sections: Sections,
         kind: AutoloadKind,
         code: &'a [u8],
@@ -142,6 +160,7 @@ impl<'a> Module<'a> {
         let end_address = sections.end_address().context("no sections provided")?;
         let bss_size = sections.bss_size();
         Self::import_functions(symbol_map, &mut sections, base_address, end_address, code)?;
+        relocations.resolve_section_targets(ModuleKind::Autoload(kind), &sections)?;
         Ok(Self {
             name,
             kind: ModuleKind::Autoload(kind),
@@ -149,13 +168,13 @@ impl<'a> Module<'a> {
             code,
             base_address,
             bss_size,
-            default_func_prefix: "func_".to_string(),
-            default_data_prefix: "data_".to_string(),
+            default_func_prefix: DEFAULT_FUNC_PREFIX.to_string(),
+            default_data_prefix: DEFAULT_DATA_PREFIX.to_string(),
             sections,
         })
     }
 
-    pub fn analyze_itcm(autoload: &'a Autoload, symbol_maps: &mut SymbolMaps) -> Result<Self> {
+    pub fn analyze_itcm(autoload: &'a Autoload, symbol_maps: &mut SymbolMaps, options: &AnalysisOptions) -> Result<Self> {
         let mut module = Self {
             name: "itcm".to_string(),
             kind: ModuleKind::Autoload(AutoloadKind::Itcm),
@@ -163,13 +182,16 @@ impl<'a> Module<'a> {
             code: autoload.code(),
             base_address: autoload.base_address(),
             bss_size: autoload.bss_size(),
-            default_func_prefix: "func_".to_string(),
-            default_data_prefix: "data_".to_string(),
+            default_func_prefix: DEFAULT_FUNC_PREFIX.to_string(),
+            default_data_prefix: DEFAULT_DATA_PREFIX.to_string(),
             sections: Sections::new(),
         };
         let symbol_map = symbol_maps.get_mut(module.kind);
 
         module.find_sections_itcm(symbol_map)?;
+        if options.follow_data_pointers {
+            module.find_functions_from_data_pointers(symbol_map)?;
+        }
         module.find_data_from_pools(symbol_map)?;
 
         Ok(module)
@@ -183,8 +205,8 @@ impl<'a> Module<'a> {
             code: autoload.code(),
             base_address: autoload.base_address(),
             bss_size: autoload.bss_size(),
-            default_func_prefix: "func_".to_string(),
-            default_data_prefix: "data_".to_string(),
+            default_func_prefix: DEFAULT_FUNC_PREFIX.to_string(),
+            default_data_prefix: DEFAULT_DATA_PREFIX.to_string(),
             sections: Sections::new(),
         };
         let symbol_map = symbol_maps.get_mut(module.kind);
@@ -211,7 +233,7 @@ impl<'a> Module<'a> {
                 .first_instruction_offset(sym_function.offset)
                 .known_end_address(symbol.addr + size)
                 .code(&code[offset as usize..])
-                .options(ParseFunctionOptions { thumb: sym_function.mode.into_thumb() })
+                .options(ParseFunctionOptions { thumb: sym_function.mode.into_thumb(), ..Default::default() })
                 .module_start_address(base_address)
                 .module_end_address(end_address)
                 .call()?;
@@ -403,7 +425,7 @@ impl<'a> Module<'a> {
             .start_address(autoload_callback_address)
             .module_code(self.code)
             .base_address(self.base_address)
-            .options(ParseFunctionOptions { thumb: None })
+            .options(ParseFunctionOptions { thumb: None, ..Default::default() })
             .module_start_address(self.base_address)
             .module_end_address(self.end_address())
             .call()?
@@ -496,6 +518,88 @@ impl<'a> Module<'a> {
         Ok(())
     }
 
+    /// Parses functions which are never called directly but are pointed to by pool constants or data, such as functions in
+    /// vtables or callback tables. Newly found functions can point to more functions, so this repeats until none are found.
+    fn find_functions_from_data_pointers(&mut self, symbol_map: &mut SymbolMap) -> Result<()> {
+        loop {
+            let mut found = false;
+            for pointer in self.code_pointers_from_data(symbol_map)? {
+                let address = pointer & !1;
+                if symbol_map.get_function_containing(address).is_some() {
+                    continue;
+                }
+                let thumb = if (pointer & 1) != 0 {
+                    true
+                } else if (pointer & 3) == 0 {
+                    false
+                } else {
+                    // Misaligned, not a function pointer
+                    continue;
+                };
+
+                let parse_result = Function::parse_function()
+                    .name(format!("{}{:08x}", self.default_func_prefix, address))
+                    .start_address(address)
+                    .module_code(self.code)
+                    .base_address(self.base_address)
+                    .options(ParseFunctionOptions { thumb: Some(thumb), ..Default::default() })
+                    .module_start_address(self.base_address)
+                    .module_end_address(self.end_address())
+                    .call()?;
+                let ParseFunctionResult::Found(function) = parse_result else {
+                    log::debug!("Pointer 0x{pointer:08x} from data does not lead to a valid function");
+                    continue;
+                };
+
+                let Some((_, section)) = self.sections.get_by_contained_address(address) else { continue };
+                let overlaps_function = symbol_map
+                    .iter_by_address(function.start_address()..function.end_address())
+                    .any(|symbol| matches!(symbol.kind, SymbolKind::Function(_)));
+                if function.end_address() > section.end_address() || overlaps_function {
+                    log::debug!("Function at 0x{pointer:08x} from data pointer overlaps other code, skipping");
+                    continue;
+                }
+
+                log::debug!("Found function {} from data pointer", function.name());
+                symbol_map.add_function(&function);
+                function.add_local_symbols_to_map(symbol_map)?;
+                self.sections.add_function(function);
+                found = true;
+            }
+            if !found {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Returns pointers into code sections from pool constants and data sections, excluding pointers to known functions.
+    fn code_pointers_from_data(&self, symbol_map: &SymbolMap) -> Result<BTreeSet<u32>> {
+        let pool_pointers = self
+            .sections
+            .functions()
+            .flat_map(|function| function.iter_pool_constants(self.code, self.base_address))
+            .map(|pool_constant| pool_constant.value);
+
+        let mut data_pointers = vec![];
+        for section in self.sections.iter() {
+            if section.kind() != SectionKind::Data {
+                continue;
+            }
+            let Some(code) = section.code(self.code, self.base_address)? else { continue };
+            data_pointers.extend(section.iter_words(code, None).map(|word| word.value));
+        }
+
+        let mut pointers = BTreeSet::new();
+        for pointer in pool_pointers.chain(data_pointers) {
+            let Some((_, section)) = self.sections.get_by_contained_address(pointer) else { continue };
+            if section.kind() != SectionKind::Code || symbol_map.get_function(pointer)?.is_some() {
+                continue;
+            }
+            pointers.insert(pointer);
+        }
+        Ok(pointers)
+    }
+
     fn find_data_from_pools(&mut self, symbol_map: &mut SymbolMap) -> Result<()> {
         for function in self.sections.functions() {
             data::find_local_data_from_pools()
@@ -516,7 +620,7 @@ impl<'a> Module<'a> {
         for section in self.sections.iter() {
             match section.kind() {
                 SectionKind::Data => {
-                    let code = section.code(&self.code, self.base_address)?.unwrap();
+                    let Some(code) = section.code(&self.code, self.base_address)? else { continue };
                     data::find_local_data_from_section()
                         .sections(&self.sections)
                         .section(section)
@@ -596,14 +700,306 @@ impl<'a> Module<'a> {
         self.base_address + self.code.len() as u32 + self.bss_size()
     }
 
+    /// Builds a relocatable ELF object of `sections`, which must be within this module. Symbols in `sections` are defined in
+    /// the object, and relocations to other symbols become undefined symbols named after them.
+    pub fn to_object<'o>(&self, symbol_maps: &SymbolMaps, sections: &Sections) -> Result<object::write::Object<'o>> {
+        let symbol_map = symbol_maps.get(self.kind()).context("No symbol map for module")?;
+        let mut object = object::write::Object::new(BinaryFormat::Elf, Architecture::Arm, Endianness::Little);
+        object.elf_is_rela = Some(true);
+
+        // Maps address to ObjSection/ObjSymbol
+        let mut obj_sections = BTreeMap::new();
+        let mut obj_symbols = BTreeMap::new();
+
+        let mut error = false;
+
+        for file_section in sections.iter() {
+            // Get section data
+            let code = file_section.relocatable_code(self)?.unwrap_or_else(|| vec![]);
+            let name = file_section.name().as_bytes().to_vec();
+            let kind = match file_section.kind() {
+                SectionKind::Code => object::SectionKind::Text,
+                SectionKind::Data => object::SectionKind::Data, // TODO: use ReadOnlyData if .rodata?
+                SectionKind::Bss => object::SectionKind::UninitializedData,
+            };
+
+            // Create section
+            let obj_section_id = object.add_section(vec![], name.clone(), kind);
+            let section = object.section_mut(obj_section_id);
+            if file_section.kind() == SectionKind::Bss {
+                section.append_bss(file_section.size() as u64, 1);
+            } else {
+                section.set_data(code, 1);
+            }
+
+            // Add dummy symbol to make linker notice the section
+            object.add_symbol(object::write::Symbol {
+                name, // same name as section
+                value: 0,
+                size: 0,
+                kind: object::SymbolKind::Label,
+                scope: object::SymbolScope::Compilation,
+                weak: false,
+                section: object::write::SymbolSection::Section(obj_section_id),
+                flags: object::SymbolFlags::None,
+            });
+
+            // Add symbols to section
+            let mut symbols = symbol_map.iter_by_address(file_section.address_range()).peekable();
+            while let Some(symbol) = symbols.next() {
+                // Get symbol data
+                let max_address = symbols.peek().map(|s| s.addr).unwrap_or(file_section.end_address());
+                let kind = symbol.kind.into_obj_symbol_kind();
+                let scope = symbol.kind.into_obj_symbol_scope();
+                let value = (symbol.addr - file_section.start_address()) as u64;
+
+                // Create symbol
+                let symbol_section = object::write::SymbolSection::Section(obj_section_id);
+                let symbol_id = object.add_symbol(object::write::Symbol {
+                    name: symbol.name.clone().into_bytes(),
+                    value,
+                    size: symbol.size(max_address) as u64,
+                    kind,
+                    scope,
+                    weak: false,
+                    section: symbol_section,
+                    flags: object::SymbolFlags::None,
+                });
+                obj_symbols.insert((symbol.addr, self.kind()), symbol_id);
+
+                if file_section.kind() == SectionKind::Code {
+                    // Create mapping symbol
+                    if let Some(name) = symbol.mapping_symbol_name() {
+                        object.add_symbol(object::write::Symbol {
+                            name: name.to_string().into_bytes(),
+                            value,
+                            size: 0,
+                            kind: object::SymbolKind::Label,
+                            scope: object::SymbolScope::Compilation,
+                            weak: false,
+                            section: symbol_section,
+                            flags: object::SymbolFlags::None,
+                        });
+                    }
+                }
+            }
+
+            obj_sections.insert(file_section.start_address(), obj_section_id);
+        }
+
+        // Must start a new loop here so we can know which section a symbol ID belongs to
+        for file_section in sections.iter() {
+            let obj_section_id = *obj_sections.get(&file_section.start_address()).unwrap();
+
+            // Add relocations to section
+            for (_, relocation) in self.relocations().iter_range(file_section.address_range()) {
+                // Get relocation data
+                let offset = relocation.from_address() - file_section.start_address();
+                let dest_addr = relocation.to_address();
+                let reloc_module = relocation.module().first_module().unwrap();
+
+                // Get destination symbol
+                let symbol_key = (dest_addr, reloc_module);
+                let symbol_id = if let Some(obj_symbol_id) = obj_symbols.get(&symbol_key) {
+                    *obj_symbol_id
+                } else {
+                    // Get external symbol data
+                    let external_symbol_map = symbol_maps.get(reloc_module).unwrap();
+                    let symbol = if let Some((_, symbol)) = external_symbol_map.by_address(dest_addr)? {
+                        symbol
+                    } else if let Some((_, symbol)) = external_symbol_map.get_function(dest_addr)? {
+                        symbol
+                    } else if relocation.is_weak() {
+                        log::warn!(
+                            "No symbol found for weak relocation from 0x{:08x} in {} to 0x{:08x} in {}, skipping",
+                            relocation.from_address(),
+                            self.kind(),
+                            dest_addr,
+                            reloc_module
+                        );
+                        continue;
+                    } else {
+                        log::error!(
+                            "No symbol found for relocation from 0x{:08x} in {} to 0x{:08x} in {}",
+                            relocation.from_address(),
+                            self.kind(),
+                            dest_addr,
+                            reloc_module
+                        );
+                        error = true;
+                        continue;
+                    };
+
+                    // Add external symbol to section
+                    let kind = relocation.kind().into_obj_symbol_kind();
+                    let symbol_section = object::write::SymbolSection::Undefined;
+                    let symbol_id = object.add_symbol(object::write::Symbol {
+                        name: symbol.name.clone().into_bytes(),
+                        value: 0,
+                        size: 0,
+                        kind,
+                        scope: object::SymbolScope::Compilation,
+                        weak: true,
+                        section: symbol_section,
+                        flags: object::SymbolFlags::None,
+                    });
+                    obj_symbols.insert(symbol_key, symbol_id);
+                    symbol_id
+                };
+
+                // Create relocation
+                object.add_relocation(
+                    obj_section_id,
+                    relocation.kind().to_object_relocation(symbol_id, offset as u64, relocation.addend()),
+                )?;
+            }
+        }
+
+        if error {
+            bail!("Failed to create object for {}, see errors above", self.kind());
+        }
+
+        Ok(object)
+    }
+
+    /// Writes all sections of this module as a relocatable ELF object, see [`Self::to_object`].
+    pub fn write_elf<W: Write>(&self, symbol_maps: &SymbolMaps, w: W) -> Result<()> {
+        let object = self.to_object(symbol_maps, &self.sections)?;
+        if let Err(error) = object.write_stream(w) {
+            log::error!("Failed to write ELF object for {}: {error}", self.kind);
+            bail!("Failed to write ELF object for {}: {error}", self.kind);
+        }
+        Ok(())
+    }
+
     pub fn get_function(&self, addr: u32) -> Option<&Function> {
         self.sections.get_by_contained_address(addr).and_then(|(_, s)| s.functions().get(&addr))
     }
 
+    /// Rebuilds the bytes of a section from the functions and data symbols in it. Bytes not covered by any function or data
+    /// symbol are zero, so comparing the result to the original section shows whether the analysis covers all of it.
+    pub fn reconstruct_section(&self, section_index: usize, symbol_map: &SymbolMap) -> Result<Vec<u8>> {
+        if section_index >= self.sections.len() {
+            log::error!("Section index {} is out of bounds in module {}", section_index, self.name);
+            bail!("Section index {} is out of bounds in module {}", section_index, self.name);
+        }
+        let section = self.sections.get(section_index);
+        let mut bytes = vec![0; section.size() as usize];
+        if section.kind() == SectionKind::Bss {
+            return Ok(bytes);
+        }
+
+        for function in section.functions().values() {
+            let Some(code) = self.code_in_range(function.start_address(), function.end_address()) else {
+                log::error!(
+                    "Function {} at 0x{:08x} is outside of the module's code",
+                    function.name(),
+                    function.start_address()
+                );
+                bail!("Function {} at 0x{:08x} is outside of the module's code", function.name(), function.start_address());
+            };
+            Self::copy_to_section(&mut bytes, section, function.start_address(), code)?;
+        }
+
+        let mut symbols = symbol_map
+            .iter_by_address(section.address_range())
+            .filter(|s| matches!(s.kind, SymbolKind::Function(_) | SymbolKind::Data(_)))
+            .peekable();
+        while let Some(symbol) = symbols.next() {
+            if !matches!(symbol.kind, SymbolKind::Data(_)) {
+                continue;
+            }
+            let next_address = symbols.peek().map(|s| s.addr).unwrap_or(section.end_address());
+            let size = symbol.size(next_address).min(section.end_address() - symbol.addr);
+            let Some(data) = self.code_in_range(symbol.addr, symbol.addr.saturating_add(size)) else {
+                log::error!("Data symbol {} at 0x{:08x} is outside of the module's code", symbol.name, symbol.addr);
+                bail!("Data symbol {} at 0x{:08x} is outside of the module's code", symbol.name, symbol.addr);
+            };
+            Self::copy_to_section(&mut bytes, section, symbol.addr, data)?;
+        }
+
+        Ok(bytes)
+    }
+
+    /// Returns the module's code from `start` to `end`, or None if the range is not in the module.
+    fn code_in_range(&self, start: u32, end: u32) -> Option<&'a [u8]> {
+        let start = start.checked_sub(self.base_address)? as usize;
+        let end = end.checked_sub(self.base_address)? as usize;
+        self.code.get(start..end)
+    }
+
+    /// Copies `data` to `address` in `bytes`, which are the contents of `section`.
+    fn copy_to_section(bytes: &mut [u8], section: &Section, address: u32, data: &[u8]) -> Result<()> {
+        let offset = address.checked_sub(section.start_address()).map(|offset| offset as usize);
+        let Some(destination) = offset.and_then(|offset| bytes.get_mut(offset..offset + data.len())) else {
+            log::error!("{:#x} bytes at 0x{:08x} don't fit in section {}", data.len(), address, section.name());
+            bail!("{:#x} bytes at 0x{:08x} don't fit in section {}", data.len(), address, section.name());
+        };
+        destination.copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Returns how many bytes of this module are classified as code, data and .bss, and how many are not covered by any
+    /// function or data symbol. Takes the module's symbol map since modules don't own their symbols.
+    pub fn size_breakdown(&self, symbol_map: &SymbolMap) -> SizeBreakdown {
+        let mut breakdown = SizeBreakdown::default();
+        for section in self.sections.iter() {
+            match section.kind() {
+                SectionKind::Bss => {
+                    breakdown.bss += section.size();
+                    continue;
+                }
+                SectionKind::Code | SectionKind::Data => {}
+            }
+
+            let section_size = section.size();
+            let code_size = section.functions().values().map(|function| function.size()).sum::<u32>().min(section_size);
+
+            let mut data_size = 0;
+            let mut symbols = symbol_map
+                .iter_by_address(section.address_range())
+                .filter(|s| matches!(s.kind, SymbolKind::Function(_) | SymbolKind::Data(_)))
+                .peekable();
+            while let Some(symbol) = symbols.next() {
+                if !matches!(symbol.kind, SymbolKind::Data(_)) {
+                    continue;
+                }
+                // A data symbol with a known size can run into the next symbol, only count the bytes up to it
+                let next_address = symbols.peek().map(|s| s.addr).unwrap_or(section.end_address());
+                data_size += symbol.size(next_address).min(next_address - symbol.addr);
+            }
+            // Data symbols inside functions, like inline tables, would otherwise be counted twice
+            let data_size = data_size.min(section_size - code_size);
+
+            breakdown.code += code_size;
+            breakdown.data += data_size;
+            breakdown.unknown += section_size - code_size - data_size;
+        }
+        breakdown
+    }
+
     pub fn bss_size(&self) -> u32 {
         self.bss_size
     }
 
+    /// Prefix of generated function names in this module, e.g. `func_ov003_`.
+    pub fn default_func_prefix(&self) -> &str {
+        &self.default_func_prefix
+    }
+
+    pub fn set_default_func_prefix(&mut self, prefix: String) {
+        self.default_func_prefix = prefix;
+    }
+
+    /// Prefix of generated data and .bss symbol names in this module, e.g. `data_ov003_`.
+    pub fn default_data_prefix(&self) -> &str {
+        &self.default_data_prefix
+    }
+
+    pub fn set_default_data_prefix(&mut self, prefix: String) {
+        self.default_data_prefix = prefix;
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -613,6 +1009,22 @@ impl<'a> Module<'a> {
     }
 }
 
+#[derive(Default, Clone, Copy)]
+pub struct AnalysisOptions {
+    /// Parse functions which are only referenced by pointers in pool constants or data, such as functions in vtables and
+    /// callback tables.
+    pub follow_data_pointers: bool,
+}
+
+/// Number of bytes per category in a module, see [`Module::size_breakdown`].
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SizeBreakdown {
+    pub code: u32,
+    pub data: u32,
+    pub bss: u32,
+    pub unknown: u32,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub enum ModuleKind {
     Arm9,
@@ -634,6 +1046,19 @@ impl ModuleKind {
     }
 }
 
+/// Returns the base address of every module in the ROM, e.g. for reading relocations relative to their modules.
+pub fn module_base_addresses(rom: &Rom) -> Result<BTreeMap<ModuleKind, u32>> {
+    let mut bases = BTreeMap::new();
+    bases.insert(ModuleKind::Arm9, rom.arm9().base_address());
+    for autoload in rom.arm9().autoloads()?.iter() {
+        bases.insert(ModuleKind::Autoload(autoload.kind()), autoload.base_address());
+    }
+    for overlay in rom.arm9_overlays() {
+        bases.insert(ModuleKind::Overlay(overlay.id()), overlay.base_address());
+    }
+    Ok(bases)
+}
+
 impl Display for ModuleKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -648,3 +1073,167 @@ struct InitFunctionRange {
     min: u32,
     max: u32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{
+        relocation::RelocationModule,
+        symbol::{InstructionMode, SymFunction, Symbol},
+    };
+
+    fn arm_code(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    fn function_symbol(address: u32, size: u32) -> Symbol {
+        Symbol {
+            name: format!("func_{address:08x}"),
+            kind: SymbolKind::Function(SymFunction { mode: InstructionMode::Arm, size, offset: 0, unknown: false }),
+            addr: address,
+            ambiguous: false,
+        }
+    }
+
+    #[test]
+    fn function_reachable_only_from_data_table() {
+        let code = arm_code(&[
+            0xe59f0000, // ldr r0, [pc, #0]
+            0xe12fff1e, // bx lr
+            0x02000010, // pool constant pointing to the table in .data
+            0xe12fff1e, // bx lr, never called directly
+            0x0200000c, // table entry in .data
+        ]);
+        let mut sections = Sections::new();
+        sections.add(Section::new(".text".to_string(), SectionKind::Code, 0x02000000, 0x02000010, 4).unwrap()).unwrap();
+        sections.add(Section::new(".data".to_string(), SectionKind::Data, 0x02000010, 0x02000014, 4).unwrap()).unwrap();
+        let mut symbol_map = SymbolMap::new();
+        symbol_map.add(function_symbol(0x02000000, 0xc));
+        let mut module = Module::new_arm9("main".to_string(), &mut symbol_map, Relocations::new(), sections, &code).unwrap();
+
+        module.find_functions_from_data_pointers(&mut symbol_map).unwrap();
+
+        let (function, symbol) = symbol_map.get_function(0x0200000c).unwrap().unwrap();
+        assert_eq!(symbol.name, "func_0200000c");
+        assert_eq!(function.size, 4);
+        let addresses = module.sections().functions().map(|function| function.start_address()).collect::<Vec<_>>();
+        assert_eq!(addresses, [0x02000000, 0x0200000c]);
+    }
+
+    #[test]
+    fn size_breakdown_clamps_overlapping_symbols() {
+        let code = arm_code(&[0xe12fff1e, 0, 0, 0, 0, 0, 0, 0]);
+        let mut sections = Sections::new();
+        sections.add(Section::new(".text".to_string(), SectionKind::Code, 0x02000000, 0x02000010, 4).unwrap()).unwrap();
+        sections.add(Section::new(".data".to_string(), SectionKind::Data, 0x02000010, 0x02000020, 4).unwrap()).unwrap();
+        sections.add(Section::new(".bss".to_string(), SectionKind::Bss, 0x02000020, 0x02000040, 4).unwrap()).unwrap();
+        let mut symbol_map = SymbolMap::new();
+        symbol_map.add(function_symbol(0x02000000, 4));
+        let module = Module::new_arm9("main".to_string(), &mut symbol_map, Relocations::new(), sections, &code).unwrap();
+
+        // Runs past the next symbol and the end of .text
+        symbol_map.add(Symbol::new_data("data_02000004".to_string(), 0x02000004, SymData::Word { count: Some(8) }, false));
+        symbol_map.add(Symbol::new_data("data_02000008".to_string(), 0x02000008, SymData::Any, false));
+        symbol_map.add(Symbol::new_data("data_02000010".to_string(), 0x02000010, SymData::Word { count: Some(2) }, false));
+
+        assert_eq!(module.size_breakdown(&symbol_map), SizeBreakdown { code: 4, data: 0x14, bss: 0x20, unknown: 8 });
+    }
+
+    #[test]
+    fn reconstruct_synthetic_section() {
+        let code = arm_code(&[
+            0xe59f0000, // ldr r0, [pc, #0]
+            0xe12fff1e, // bx lr
+            0x02000010, // pool constant
+            0x00000000, // padding
+            0x12345678, // .data
+            0x9abcdef0,
+        ]);
+        let mut sections = Sections::new();
+        sections.add(Section::new(".text".to_string(), SectionKind::Code, 0x02000000, 0x02000010, 4).unwrap()).unwrap();
+        sections.add(Section::new(".data".to_string(), SectionKind::Data, 0x02000010, 0x02000018, 4).unwrap()).unwrap();
+        sections.add(Section::new(".bss".to_string(), SectionKind::Bss, 0x02000018, 0x02000020, 4).unwrap()).unwrap();
+        let mut symbol_map = SymbolMap::new();
+        symbol_map.add(function_symbol(0x02000000, 0xc));
+        symbol_map.add(Symbol::new_data("data_02000010".to_string(), 0x02000010, SymData::Word { count: Some(2) }, false));
+        let module = Module::new_arm9("main".to_string(), &mut symbol_map, Relocations::new(), sections, &code).unwrap();
+
+        assert_eq!(module.reconstruct_section(0, &symbol_map).unwrap(), code[..0x10]);
+        assert_eq!(module.reconstruct_section(1, &symbol_map).unwrap(), code[0x10..0x18]);
+        assert_eq!(module.reconstruct_section(2, &symbol_map).unwrap(), [0; 8]);
+        assert!(module.reconstruct_section(3, &symbol_map).is_err());
+    }
+
+    #[test]
+    fn write_elf_has_one_relocation_per_relocation() {
+        use object::{Object, ObjectSection, RelocationFlags};
+
+        let code = arm_code(&[
+            0xe59f0004, // ldr r0, [pc, #4]
+            0xeb000001, // bl func_02000010
+            0xe12fff1e, // bx lr
+            0x02000014, // pool constant pointing to .data
+            0xe12fff1e, // bx lr
+            0x12345678, // .data
+        ]);
+        let mut sections = Sections::new();
+        sections.add(Section::new(".text".to_string(), SectionKind::Code, 0x02000000, 0x02000014, 4).unwrap()).unwrap();
+        sections.add(Section::new(".data".to_string(), SectionKind::Data, 0x02000014, 0x02000018, 4).unwrap()).unwrap();
+        sections.add(Section::new(".bss".to_string(), SectionKind::Bss, 0x02000018, 0x02000020, 4).unwrap()).unwrap();
+        let mut relocations = Relocations::new();
+        relocations.add_call(0x02000004, 0x02000010, RelocationModule::Main, false, false).unwrap();
+        relocations.add_load(0x0200000c, 0x02000014, 0, RelocationModule::Main).unwrap();
+
+        let mut symbol_maps = SymbolMaps::new();
+        let symbol_map = symbol_maps.get_mut(ModuleKind::Arm9);
+        symbol_map.add(function_symbol(0x02000000, 0x10));
+        symbol_map.add(function_symbol(0x02000010, 4));
+        symbol_map.add(Symbol::new_data("data_02000014".to_string(), 0x02000014, SymData::Word { count: Some(1) }, false));
+        let module = Module::new_arm9("main".to_string(), symbol_map, relocations, sections, &code).unwrap();
+
+        let mut bytes = vec![];
+        module.write_elf(&symbol_maps, &mut bytes).unwrap();
+        let object = object::File::parse(&*bytes).unwrap();
+
+        let num_relocations: usize = object.sections().map(|section| section.relocations().count()).sum();
+        assert_eq!(num_relocations, module.relocations().len());
+
+        let text = object.section_by_name(".text").unwrap();
+        let text_relocations = text.relocations().collect::<Vec<_>>();
+        assert_eq!(text_relocations.len(), 2);
+        for ((offset, relocation), expected) in text_relocations.iter().zip(module.relocations().iter()) {
+            assert_eq!(*offset, (expected.from_address() - 0x02000000) as u64);
+            assert_eq!(relocation.flags(), RelocationFlags::Elf { r_type: expected.kind().into_elf_relocation_type() });
+            assert_eq!(relocation.addend(), expected.addend());
+        }
+    }
+
+    #[test]
+    fn custom_prefixes_name_functions_and_data() {
+        let code = arm_code(&[
+            0xe59f0000, // ldr r0, [pc, #0]
+            0xe12fff1e, // bx lr
+            0x02000010, // pool constant pointing to the table in .data
+            0xe12fff1e, // bx lr, never called directly
+            0x0200000c, // table entry in .data
+        ]);
+        let mut sections = Sections::new();
+        sections.add(Section::new(".text".to_string(), SectionKind::Code, 0x02000000, 0x02000010, 4).unwrap()).unwrap();
+        sections.add(Section::new(".data".to_string(), SectionKind::Data, 0x02000010, 0x02000014, 4).unwrap()).unwrap();
+        let mut symbol_map = SymbolMap::new();
+        symbol_map.add(function_symbol(0x02000000, 0xc));
+        let mut module = Module::new_arm9("main".to_string(), &mut symbol_map, Relocations::new(), sections, &code).unwrap();
+        assert_eq!(module.default_func_prefix(), DEFAULT_FUNC_PREFIX);
+        assert_eq!(module.default_data_prefix(), DEFAULT_DATA_PREFIX);
+
+        module.set_default_func_prefix("fn_".to_string());
+        module.set_default_data_prefix("dat_".to_string());
+        module.find_functions_from_data_pointers(&mut symbol_map).unwrap();
+        module.find_data_from_pools(&mut symbol_map).unwrap();
+
+        let (_, function) = symbol_map.get_function(0x0200000c).unwrap().unwrap();
+        assert_eq!(function.name, "fn_0200000c");
+        let (_, data) = symbol_map.get_data(0x02000010).unwrap().unwrap();
+        assert_eq!(data.name, "dat_02000010");
+    }
+}
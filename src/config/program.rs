@@ -1,14 +1,21 @@
-use std::ops::Range;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Display,
+    io::Write,
+    ops::Range,
+};
 
 use anyhow::{bail, Result};
 use bon::bon;
+use ds_rom::rom::raw::AutoloadKind;
 
 use crate::analysis::data::{self, RelocationResult, SymbolCandidate};
 
 use super::{
-    module::Module,
+    module::{Module, ModuleKind},
+    relocation::{Relocation, RelocationKind, RelocationModule},
     section::SectionKind,
-    symbol::{SymBss, SymData, SymbolMaps},
+    symbol::{SymBss, SymData, Symbol, SymbolKind, SymbolMaps},
 };
 
 pub struct Program<'a> {
@@ -35,9 +42,21 @@ impl<'a> Program<'a> {
         Self { modules, symbol_maps, main, overlays, autoloads }
     }
 
+    /// Finds relocations between modules. If given, `on_progress` is called with the module index and the number of
+    /// modules before each module is analyzed. Sizes of the new .bss symbols are inferred afterwards. Returns warnings about
+    /// data symbols which conflict across modules.
     #[builder]
-    pub fn analyze_cross_references(&mut self, allow_unknown_function_calls: bool) -> Result<()> {
-        for module_index in 0..self.modules.len() {
+    pub fn analyze_cross_references(
+        &mut self,
+        allow_unknown_function_calls: bool,
+        mut on_progress: Option<&mut dyn FnMut(usize, usize)>,
+    ) -> Result<Vec<CrossReferenceWarning>> {
+        let mut warnings = vec![];
+        let num_modules = self.modules.len();
+        for module_index in 0..num_modules {
+            if let Some(on_progress) = &mut on_progress {
+                on_progress(module_index, num_modules);
+            }
             let RelocationResult { relocations, external_symbols } = data::analyze_external_references()
                 .modules(&self.modules)
                 .module_index(module_index)
@@ -45,41 +64,53 @@ impl<'a> Program<'a> {
                 .allow_unknown_function_calls(allow_unknown_function_calls)
                 .call()?;
 
-            self.modules[module_index].relocations_mut().extend(relocations)?;
+            self.module_mut(module_index).relocations_mut().extend(relocations)?;
 
             for symbol in external_symbols {
                 match symbol.candidates.len() {
                     0 => {
-                        log::error!("There should be at least one symbol candidate");
-                        bail!("There should be at least one symbol candidate");
+                        let warning = CrossReferenceWarning::NoSymbolCandidates {
+                            address: symbol.address,
+                            module: self.modules[module_index].kind(),
+                        };
+                        log::warn!("{warning}");
+                        warnings.push(warning);
                     }
                     1 => {
                         let SymbolCandidate { module_index, section_index } = symbol.candidates[0];
                         let section_kind = self.modules[module_index].sections().get(section_index).kind();
-                        let name = format!("{}{:08x}", self.modules[module_index].default_data_prefix, symbol.address);
-                        let symbol_map = self.symbol_maps.get_mut(self.modules[module_index].kind());
+                        let name = format!("{}{:08x}", self.modules[module_index].default_data_prefix(), symbol.address);
+                        let module_kind = self.modules[module_index].kind();
+                        let symbol_map = self.symbol_maps.get_mut(module_kind);
                         match section_kind {
                             SectionKind::Code => {} // Function symbol, already verified to exist
                             SectionKind::Data => {
-                                symbol_map.add_data(Some(name), symbol.address, SymData::Any)?;
+                                let (_, added) = symbol_map.add_data(Some(name), symbol.address, SymData::Any)?;
+                                warnings.extend(Self::check_added_symbol_kind(added, section_kind, module_kind));
                             }
                             SectionKind::Bss => {
-                                symbol_map.add_bss(Some(name), symbol.address, SymBss { size: None })?;
+                                let (_, added) = symbol_map.add_bss(Some(name), symbol.address, SymBss { size: None })?;
+                                warnings.extend(Self::check_added_symbol_kind(added, section_kind, module_kind));
                             }
                         }
                     }
                     _ => {
                         for SymbolCandidate { module_index, section_index } in symbol.candidates {
                             let section_kind = self.modules[module_index].sections().get(section_index).kind();
-                            let name = format!("{}{:08x}", self.modules[module_index].default_data_prefix, symbol.address);
-                            let symbol_map = self.symbol_maps.get_mut(self.modules[module_index].kind());
+                            let name = format!("{}{:08x}", self.modules[module_index].default_data_prefix(), symbol.address);
+                            let module_kind = self.modules[module_index].kind();
+                            let symbol_map = self.symbol_maps.get_mut(module_kind);
                             match section_kind {
                                 SectionKind::Code => {} // Function symbol, already verified to exist
                                 SectionKind::Data => {
-                                    symbol_map.add_ambiguous_data(Some(name), symbol.address, SymData::Any)?;
+                                    let (_, added) =
+                                        symbol_map.add_ambiguous_data(Some(name), symbol.address, SymData::Any)?;
+                                    warnings.extend(Self::check_added_symbol_kind(added, section_kind, module_kind));
                                 }
                                 SectionKind::Bss => {
-                                    symbol_map.add_ambiguous_bss(Some(name), symbol.address, SymBss { size: None })?;
+                                    let (_, added) =
+                                        symbol_map.add_ambiguous_bss(Some(name), symbol.address, SymBss { size: None })?;
+                                    warnings.extend(Self::check_added_symbol_kind(added, section_kind, module_kind));
                                 }
                             }
                         }
@@ -87,7 +118,69 @@ impl<'a> Program<'a> {
                 }
             }
         }
-        Ok(())
+
+        for module in &self.modules {
+            let symbol_map = self.symbol_maps.get_mut(module.kind());
+            for section in module.sections().iter().filter(|section| section.kind() == SectionKind::Bss) {
+                symbol_map.infer_bss_sizes(section.address_range());
+            }
+        }
+
+        for warning in self.find_conflicting_data_symbols() {
+            log::warn!("{warning}");
+            warnings.push(warning);
+        }
+        Ok(warnings)
+    }
+
+    /// Finds addresses where data or .bss symbols of different modules conflict with each other.
+    fn find_conflicting_data_symbols(&self) -> Vec<CrossReferenceWarning> {
+        let mut claims: BTreeMap<u32, Vec<(ModuleKind, bool)>> = BTreeMap::new();
+        for module in self.iter_modules() {
+            let Some(symbol_map) = self.symbol_maps.get(module.kind()) else { continue };
+            for symbol in symbol_map.iter().filter(|symbol| matches!(symbol.kind, SymbolKind::Data(_) | SymbolKind::Bss(_))) {
+                claims.entry(symbol.addr).or_default().push((module.kind(), symbol.ambiguous));
+            }
+        }
+
+        let mut warnings = vec![];
+        for (address, claims) in claims {
+            for (i, &(kind_a, ambiguous_a)) in claims.iter().enumerate() {
+                for &(kind_b, ambiguous_b) in &claims[i + 1..] {
+                    let is_overlay_a = matches!(kind_a, ModuleKind::Overlay(_));
+                    let is_overlay_b = matches!(kind_b, ModuleKind::Overlay(_));
+                    if ambiguous_a != ambiguous_b {
+                        let (concrete, ambiguous) = if ambiguous_a { (kind_b, kind_a) } else { (kind_a, kind_b) };
+                        warnings.push(CrossReferenceWarning::ConcreteAndAmbiguous { address, concrete, ambiguous });
+                    } else if !ambiguous_a && is_overlay_a != is_overlay_b {
+                        let (overlay, resident) = if is_overlay_a { (kind_a, kind_b) } else { (kind_b, kind_a) };
+                        warnings.push(CrossReferenceWarning::OverlayOverlapsResident { address, overlay, resident });
+                    }
+                }
+            }
+        }
+        warnings
+    }
+
+    /// The symbol map keeps any symbol which already exists at the address, so re-running the analysis is safe. This returns
+    /// a warning if that existing symbol doesn't fit the section it's in, e.g. a data symbol in .bss.
+    fn check_added_symbol_kind(
+        symbol: &Symbol,
+        section_kind: SectionKind,
+        module: ModuleKind,
+    ) -> Option<CrossReferenceWarning> {
+        let compatible = match section_kind {
+            SectionKind::Code => true,
+            SectionKind::Data => matches!(symbol.kind, SymbolKind::Data(_)),
+            SectionKind::Bss => matches!(symbol.kind, SymbolKind::Bss(_)),
+        };
+        if compatible {
+            return None;
+        }
+        let warning =
+            CrossReferenceWarning::IncompatibleSymbol { address: symbol.addr, module, existing: symbol.kind, section_kind };
+        log::warn!("{warning}");
+        Some(warning)
     }
 
     pub fn main(&self) -> &Module {
@@ -106,43 +199,373 @@ impl<'a> Program<'a> {
         &self.modules[index]
     }
 
-    pub fn module_mut(&'a mut self, index: usize) -> &mut Module {
+    pub fn module_mut(&mut self, index: usize) -> &mut Module<'a> {
         &mut self.modules[index]
     }
 
+    /// Borrows the module at `index` mutably, along with all other modules. Indices into [`ExternalModules`] skip the
+    /// borrowed module.
+    pub fn split_module_mut(&mut self, index: usize) -> (&mut Module<'a>, ExternalModules<'_, 'a>) {
+        let (before, rest) = self.modules.split_at_mut(index);
+        let (module, after) = rest.split_first_mut().expect("module index out of bounds");
+        (module, ExternalModules { before, after, module_index: index })
+    }
+
+    /// Resolves relocation targets which are relative to a section in another module, e.g. `overlay(7:.bss)+0x40`. Targets
+    /// in the relocation's own module are resolved when the module is created. Fails if a target module is not in the
+    /// program.
+    pub fn resolve_section_targets(&mut self) -> Result<()> {
+        for index in 0..self.modules.len() {
+            let (module, others) = self.split_module_mut(index);
+            let module_kind = module.kind();
+            module.relocations_mut().resolve_external_section_targets(module_kind, |kind| {
+                others.iter().find(|other| other.kind() == kind).map(|other| other.sections())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Returns the index of the module with the given kind, to be used with [`Self::module`] and [`Self::module_mut`].
+    pub fn module_index_of(&self, kind: ModuleKind) -> Option<usize> {
+        let range = match kind {
+            ModuleKind::Arm9 => return Some(self.main),
+            ModuleKind::Overlay(_) => self.overlays.clone(),
+            ModuleKind::Autoload(_) => self.autoloads.clone(),
+        };
+        range.into_iter().find(|&index| self.modules[index].kind() == kind)
+    }
+
+    /// Iterates over all modules: the main module, then overlays, then autoloads.
+    pub fn iter_modules(&self) -> impl Iterator<Item = &Module<'a>> {
+        self.modules.iter()
+    }
+
+    /// Iterates mutably over all modules, in the same order as [`Self::iter_modules`].
+    pub fn iter_modules_mut(&mut self) -> impl Iterator<Item = &mut Module<'a>> {
+        self.modules.iter_mut()
+    }
+
+    pub fn module_by_kind(&self, kind: ModuleKind) -> Option<&Module> {
+        self.module_index_of(kind).map(|index| &self.modules[index])
+    }
+
+    pub fn module_by_kind_mut(&mut self, kind: ModuleKind) -> Option<&mut Module<'a>> {
+        self.module_index_of(kind).map(|index| &mut self.modules[index])
+    }
+
+    /// Adds an overlay after the existing ones and returns its module index.
+    pub fn add_overlay(&mut self, overlay: Module<'a>) -> Result<usize> {
+        if !matches!(overlay.kind(), ModuleKind::Overlay(_)) {
+            log::error!("Cannot add {} as an overlay", overlay.kind());
+            bail!("Cannot add {} as an overlay", overlay.kind());
+        }
+        let index = self.overlays.end;
+        self.modules.insert(index, overlay);
+        self.overlays.end += 1;
+        self.autoloads = self.autoloads.start + 1..self.autoloads.end + 1;
+        Ok(index)
+    }
+
+    /// Adds an autoload after the existing ones and returns its module index.
+    pub fn add_autoload(&mut self, autoload: Module<'a>) -> Result<usize> {
+        if !matches!(autoload.kind(), ModuleKind::Autoload(_)) {
+            log::error!("Cannot add {} as an autoload", autoload.kind());
+            bail!("Cannot add {} as an autoload", autoload.kind());
+        }
+        let index = self.autoloads.end;
+        self.modules.insert(index, autoload);
+        self.autoloads.end += 1;
+        Ok(index)
+    }
+
+    /// Removes an overlay or autoload. The indices of the modules after it are shifted down by one.
+    pub fn remove_module(&mut self, index: usize) -> Result<Module<'a>> {
+        if index == self.main {
+            log::error!("Cannot remove the main module");
+            bail!("Cannot remove the main module");
+        }
+        if self.overlays.contains(&index) {
+            self.overlays.end -= 1;
+            self.autoloads = self.autoloads.start - 1..self.autoloads.end - 1;
+        } else if self.autoloads.contains(&index) {
+            self.autoloads.end -= 1;
+        } else {
+            log::error!("Module index {index} out of bounds, there are {} modules", self.modules.len());
+            bail!("Module index {index} out of bounds, there are {} modules", self.modules.len());
+        }
+        Ok(self.modules.remove(index))
+    }
+
     pub fn num_modules(&self) -> usize {
         self.modules.len()
     }
 
+    pub fn max_overlay_id(&self) -> Option<u16> {
+        self.overlays()
+            .iter()
+            .filter_map(|module| match module.kind() {
+                ModuleKind::Overlay(id) => Some(id),
+                _ => None,
+            })
+            .max()
+    }
+
+    pub fn has_itcm(&self) -> bool {
+        self.autoloads().iter().any(|module| module.kind() == ModuleKind::Autoload(AutoloadKind::Itcm))
+    }
+
+    pub fn has_dtcm(&self) -> bool {
+        self.autoloads().iter().any(|module| module.kind() == ModuleKind::Autoload(AutoloadKind::Dtcm))
+    }
+
     pub fn symbol_maps(&self) -> &SymbolMaps {
         &self.symbol_maps
     }
+
+    /// Returns the base address of every module, e.g. for writing relocations relative to their modules.
+    pub fn module_bases(&self) -> BTreeMap<ModuleKind, u32> {
+        self.modules.iter().map(|module| (module.kind(), module.base_address())).collect()
+    }
+
+    /// Returns every symbol of every module as one flat list, sorted by address and then by module. Overlays may place
+    /// symbols at the same address, so entries are not unique by address.
+    pub fn address_map(&self) -> Vec<SymbolEntry> {
+        self.address_map_with_module_names().into_iter().map(|(entry, _)| entry).collect()
+    }
+
+    /// Same as [`Self::address_map`], with the name of each entry's module alongside it.
+    fn address_map_with_module_names(&self) -> Vec<(SymbolEntry, &str)> {
+        let mut entries = vec![];
+        for module in self.iter_modules() {
+            let Some(symbol_map) = self.symbol_maps.get(module.kind()) else { continue };
+            entries.extend(symbol_map.iter().map(|symbol| {
+                let entry =
+                    SymbolEntry { address: symbol.addr, name: symbol.name.clone(), kind: symbol.kind, module: module.kind() };
+                (entry, module.name())
+            }));
+        }
+        entries.sort_by_key(|(entry, _)| (entry.address, entry.module));
+        entries
+    }
+
+    /// Returns all symbols at the given address, in every module whose address range contains it. Overlays which are loaded
+    /// at the same address can each have a symbol there.
+    pub fn symbols_at(&self, address: u32) -> Vec<(ModuleKind, &Symbol)> {
+        let mut symbols = vec![];
+        for module in self.iter_modules() {
+            if address < module.base_address() || address >= module.end_address() {
+                continue;
+            }
+            let Some(symbol_map) = self.symbol_maps.get(module.kind()) else { continue };
+            let Some(module_symbols) = symbol_map.for_address(address) else { continue };
+            symbols.extend(module_symbols.map(|(_, symbol)| (module.kind(), symbol)));
+        }
+        symbols
+    }
+
+    /// Returns the symbol at the given address, or None if there is no symbol or the address is ambiguous because symbols
+    /// from more than one module were found. Use [`Self::symbols_at`] to get all candidates.
+    pub fn symbol_at(&self, address: u32) -> Option<(ModuleKind, &Symbol)> {
+        let symbols = self.symbols_at(address);
+        let (module, symbol) = *symbols.first()?;
+        if symbols.iter().any(|&(other_module, _)| other_module != module) {
+            return None;
+        }
+        Some((module, symbol))
+    }
+
+    /// Returns the module and address of every call relocation to the given address in `target_module`. Relocations which are
+    /// ambiguous between overlays are included if `target_module` is one of them.
+    pub fn callers_of(&self, target: u32, target_module: ModuleKind) -> Vec<(ModuleKind, u32)> {
+        self.modules
+            .iter()
+            .flat_map(|module| {
+                module
+                    .relocations()
+                    .iter()
+                    .filter(|relocation| relocation.kind() != RelocationKind::Load && relocation.to_address() == target)
+                    .filter(|relocation| relocation.module().includes(target_module))
+                    .map(|relocation| (module.kind(), relocation.from_address()))
+            })
+            .collect()
+    }
+
+    /// Finds long-branch veneers and redirects calls to them so that they call the veneer's destination directly. The veneers
+    /// themselves are left in place. Returns the number of redirected relocations.
+    pub fn collapse_veneers(&mut self) -> Result<usize> {
+        let mut veneers = vec![];
+        for module in self.iter_modules() {
+            for function in module.sections().functions() {
+                let Some(target) = function.veneer_target(module.code(), module.base_address()) else { continue };
+                let target_address = target & !1;
+                let target_modules =
+                    self.modules.iter().filter(|m| target_address >= m.base_address() && target_address < m.end_address());
+                let target_module = RelocationModule::from_modules(target_modules)?;
+                if target_module == RelocationModule::None {
+                    log::warn!(
+                        "Veneer at 0x{:08x} in {} leads to 0x{target:08x} which is outside of all modules",
+                        function.start_address(),
+                        module.kind()
+                    );
+                    continue;
+                }
+                veneers.push((module.kind(), function.start_address(), target, target_module));
+            }
+        }
+
+        let mut num_redirected = 0;
+        for (veneer_module, veneer_address, target, target_module) in veneers {
+            for module in &mut self.modules {
+                let calls = module.relocations_mut().drain_where(|relocation| {
+                    relocation.kind() != RelocationKind::Load
+                        && relocation.to_address() == veneer_address
+                        && !matches!(relocation.module(), RelocationModule::Overlays { .. })
+                        && relocation.module().first_module() == Some(veneer_module)
+                });
+                num_redirected += calls.len();
+                for call in calls {
+                    let from_thumb = matches!(call.kind(), RelocationKind::ThumbCall | RelocationKind::ThumbCallArm);
+                    let to_thumb = (target & 1) != 0;
+                    let mut redirected =
+                        Relocation::new_call(call.from_address(), target & !1, target_module.clone(), from_thumb, to_thumb);
+                    redirected.set_weak(call.is_weak());
+                    module.relocations_mut().add(redirected)?;
+                }
+            }
+        }
+        Ok(num_redirected)
+    }
+
+    /// Same as [`Self::address_map`], but symbol names which appear in more than one module get the module name appended,
+    /// e.g. `func_02200000_ov003`. If that name is already taken, a number is appended as well, e.g.
+    /// `func_02200000_ov003_2`, so every renamed entry is unique. Names that are already unique are left unchanged.
+    pub fn unique_address_map(&self) -> Vec<SymbolEntry> {
+        let mut entries = self.address_map_with_module_names();
+
+        let mut modules_by_name: HashMap<&str, BTreeSet<ModuleKind>> = HashMap::new();
+        for (entry, _) in &entries {
+            modules_by_name.entry(&entry.name).or_default().insert(entry.module);
+        }
+        let colliding_names = modules_by_name
+            .into_iter()
+            .filter(|(_, modules)| modules.len() > 1)
+            .map(|(name, _)| name.to_string())
+            .collect::<HashSet<_>>();
+
+        let mut taken_names = entries
+            .iter()
+            .filter(|(entry, _)| !colliding_names.contains(&entry.name))
+            .map(|(entry, _)| entry.name.clone())
+            .collect::<HashSet<_>>();
+        for (entry, module_name) in &mut entries {
+            if !colliding_names.contains(&entry.name) {
+                continue;
+            }
+            let suffixed = format!("{}_{}", entry.name, module_name);
+            let mut name = suffixed.clone();
+            let mut counter = 2;
+            while taken_names.contains(&name) {
+                name = format!("{suffixed}_{counter}");
+                counter += 1;
+            }
+            taken_names.insert(name.clone());
+            entry.name = name;
+        }
+        entries.into_iter().map(|(entry, _)| entry).collect()
+    }
+
+    /// Writes a human-readable memory map, listing each module's sections and the functions, data and .bss symbols in
+    /// them. Overlays are annotated with the address they are loaded at.
+    pub fn write_memory_map<W: Write>(&self, w: &mut W) -> Result<()> {
+        for module in self.iter_modules() {
+            match module.kind() {
+                ModuleKind::Overlay(id) => {
+                    writeln!(w, "{} (overlay {id}, loaded at 0x{:08x})", module.name(), module.base_address())?
+                }
+                _ => writeln!(w, "{}", module.name())?,
+            }
+
+            let symbol_map = self.symbol_maps.get(module.kind());
+            for section in module.sections().sorted_by_address() {
+                writeln!(
+                    w,
+                    "  {:11} 0x{:08x}..0x{:08x} size:{:#x} kind:{}",
+                    section.name(),
+                    section.start_address(),
+                    section.end_address(),
+                    section.size(),
+                    section.kind()
+                )?;
+
+                let Some(symbol_map) = symbol_map else { continue };
+                for symbol in symbol_map.iter_by_address(section.address_range()) {
+                    if !matches!(symbol.kind, SymbolKind::Function(_) | SymbolKind::Data(_) | SymbolKind::Bss(_)) {
+                        continue;
+                    }
+                    writeln!(w, "    0x{:08x} {} {}", symbol.addr, symbol.name, symbol.kind)?;
+                }
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
 }
 
-pub struct ExternalModules<'a> {
-    before: &'a mut [Module<'a>],
-    after: &'a mut [Module<'a>],
-    module_index: usize,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrossReferenceWarning {
+    /// An external reference from `module` to `address` has no symbol candidates, so no symbol was added for it.
+    NoSymbolCandidates { address: u32, module: ModuleKind },
+    /// A symbol in one module is at the same address as an ambiguous symbol in another module.
+    ConcreteAndAmbiguous { address: u32, concrete: ModuleKind, ambiguous: ModuleKind },
+    /// A symbol in an overlay is at the same address as a symbol in the main module or an autoload, which are always loaded.
+    OverlayOverlapsResident { address: u32, overlay: ModuleKind, resident: ModuleKind },
+    /// A symbol which already exists in `module` doesn't fit the kind of section it was referenced in, so it was kept as is.
+    IncompatibleSymbol { address: u32, module: ModuleKind, existing: SymbolKind, section_kind: SectionKind },
 }
 
-impl<'a> ExternalModules<'a> {
-    pub fn get(&self, index: usize) -> &Module {
-        if index < self.module_index {
-            &self.before[index]
-        } else {
-            &self.after[index - self.module_index]
+impl Display for CrossReferenceWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoSymbolCandidates { address, module } => {
+                write!(f, "External reference from {module} to {address:#010x} has no symbol candidates")
+            }
+            Self::ConcreteAndAmbiguous { address, concrete, ambiguous } => {
+                write!(f, "Symbol at {address:#010x} in {concrete} is also an ambiguous symbol in {ambiguous}")
+            }
+            Self::OverlayOverlapsResident { address, overlay, resident } => {
+                write!(f, "Symbol at {address:#010x} in {overlay} overlaps a symbol in {resident}")
+            }
+            Self::IncompatibleSymbol { address, module, existing, section_kind } => {
+                write!(f, "Existing symbol at {address:#010x} in {module} is {existing} but was referenced as {section_kind}")
+            }
         }
     }
+}
 
-    pub fn get_mut(&'a mut self, index: usize) -> &mut Module {
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SymbolEntry {
+    pub address: u32,
+    pub name: String,
+    pub kind: SymbolKind,
+    pub module: ModuleKind,
+}
+
+pub struct ExternalModules<'a, 'm> {
+    before: &'a mut [Module<'m>],
+    after: &'a mut [Module<'m>],
+    module_index: usize,
+}
+
+impl<'a, 'm> ExternalModules<'a, 'm> {
+    pub fn get(&self, index: usize) -> &Module<'m> {
         if index < self.module_index {
-            &mut self.before[index]
+            &self.before[index]
         } else {
-            &mut self.after[index - self.module_index]
+            &self.after[index - self.module_index]
         }
     }
 
-    pub unsafe fn get_mut_ptr(&'a mut self, index: usize) -> *mut Module {
+    pub fn get_mut(&mut self, index: usize) -> &mut Module<'m> {
         if index < self.module_index {
             &mut self.before[index]
         } else {
@@ -154,7 +577,370 @@ impl<'a> ExternalModules<'a> {
         self.module_index + self.after.len()
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = &Module> {
+    pub fn iter(&self) -> impl Iterator<Item = &Module<'m>> {
         self.before.iter().chain(self.after.iter())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{
+            relocation::Relocations,
+            section::{Section, Sections},
+            symbol::{InstructionMode, SymFunction, SymbolMap},
+        },
+        util::testing::assert_relocations_eq,
+    };
+
+    static CODE: [u8; 0x100] = [0; 0x100];
+
+    fn test_sections(base_address: u32) -> Sections {
+        let mut sections = Sections::new();
+        let text = Section::new(".text".to_string(), SectionKind::Code, base_address, base_address + 0x80, 4).unwrap();
+        let bss = Section::new(".bss".to_string(), SectionKind::Bss, base_address + 0x100, base_address + 0x180, 4).unwrap();
+        sections.add(text).unwrap();
+        sections.add(bss).unwrap();
+        sections
+    }
+
+    fn test_overlay(id: u16, base_address: u32) -> Module<'static> {
+        let name = format!("ov{id:03}");
+        Module::new_overlay(name, &mut SymbolMap::new(), Relocations::new(), test_sections(base_address), id, &CODE).unwrap()
+    }
+
+    fn test_itcm() -> Module<'static> {
+        let name = "itcm".to_string();
+        let sections = test_sections(0x01ff8000);
+        Module::new_autoload(name, &mut SymbolMap::new(), Relocations::new(), sections, AutoloadKind::Itcm, &CODE).unwrap()
+    }
+
+    fn test_program(symbol_maps: SymbolMaps) -> Program<'static> {
+        let main =
+            Module::new_arm9("main".to_string(), &mut SymbolMap::new(), Relocations::new(), test_sections(0x02000000), &CODE)
+                .unwrap();
+        Program::new(main, vec![test_overlay(0, 0x02200000)], vec![], symbol_maps)
+    }
+
+    fn entry(address: u32, name: &str, kind: SymbolKind, module: ModuleKind) -> SymbolEntry {
+        SymbolEntry { address, name: name.to_string(), kind, module }
+    }
+
+    #[test]
+    fn address_map_is_sorted_and_complete() {
+        let mut symbol_maps = SymbolMaps::new();
+        let overlay_map = symbol_maps.get_mut(ModuleKind::Overlay(0));
+        overlay_map.add(Symbol::new_bss("data_ov000_02200100".to_string(), 0x02200100, SymBss { size: None }, false));
+        overlay_map.add(Symbol::new_data("data_ov000_02200000".to_string(), 0x02200000, SymData::Any, false));
+        let main_map = symbol_maps.get_mut(ModuleKind::Arm9);
+        main_map.add(Symbol::new_bss("data_02000100".to_string(), 0x02000100, SymBss { size: Some(4) }, false));
+        main_map.add(Symbol::new_data("data_02000040".to_string(), 0x02000040, SymData::Word { count: Some(1) }, false));
+        let program = test_program(symbol_maps);
+
+        let address_map = program.address_map();
+        assert_eq!(
+            address_map,
+            [
+                entry(0x02000040, "data_02000040", SymbolKind::Data(SymData::Word { count: Some(1) }), ModuleKind::Arm9),
+                entry(0x02000100, "data_02000100", SymbolKind::Bss(SymBss { size: Some(4) }), ModuleKind::Arm9),
+                entry(0x02200000, "data_ov000_02200000", SymbolKind::Data(SymData::Any), ModuleKind::Overlay(0)),
+                entry(0x02200100, "data_ov000_02200100", SymbolKind::Bss(SymBss { size: None }), ModuleKind::Overlay(0)),
+            ]
+        );
+        assert_eq!(program.address_map(), address_map);
+    }
+
+    #[test]
+    fn unique_address_map_suffixes_overlay_collisions() {
+        let mut symbol_maps = SymbolMaps::new();
+        for id in [0, 1] {
+            let overlay_map = symbol_maps.get_mut(ModuleKind::Overlay(id));
+            overlay_map.add(Symbol::new_unknown_function("func_02200000".to_string(), 0x02200000, false));
+        }
+        // Already has the name that the second overlay's symbol would be renamed to
+        let main_map = symbol_maps.get_mut(ModuleKind::Arm9);
+        main_map.add(Symbol::new_unknown_function("func_02200000_ov001".to_string(), 0x02000000, false));
+        let main =
+            Module::new_arm9("main".to_string(), &mut SymbolMap::new(), Relocations::new(), test_sections(0x02000000), &CODE)
+                .unwrap();
+        let overlays = vec![test_overlay(0, 0x02200000), test_overlay(1, 0x02200000)];
+        let program = Program::new(main, overlays, vec![], symbol_maps);
+
+        let entries = program.unique_address_map();
+        let names = entries.iter().map(|entry| (entry.module, entry.name.as_str())).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                (ModuleKind::Arm9, "func_02200000_ov001"),
+                (ModuleKind::Overlay(0), "func_02200000_ov000"),
+                (ModuleKind::Overlay(1), "func_02200000_ov001_2"),
+            ]
+        );
+        let unique_names = entries.iter().map(|entry| &entry.name).collect::<HashSet<_>>();
+        assert_eq!(unique_names.len(), entries.len());
+    }
+
+    #[test]
+    fn write_memory_map_golden() {
+        let mut symbol_maps = SymbolMaps::new();
+        let main_map = symbol_maps.get_mut(ModuleKind::Arm9);
+        main_map.add(Symbol::new_unknown_function("func_02000000".to_string(), 0x02000000, false));
+        main_map.add(Symbol::new_label(".L_02000004".to_string(), 0x02000004, false));
+        main_map.add(Symbol::new_data("data_02000040".to_string(), 0x02000040, SymData::Word { count: Some(2) }, false));
+        main_map.add(Symbol::new_bss("data_02000100".to_string(), 0x02000100, SymBss { size: Some(4) }, false));
+        let overlay_map = symbol_maps.get_mut(ModuleKind::Overlay(0));
+        overlay_map.add(Symbol::new_bss("data_ov000_02200100".to_string(), 0x02200100, SymBss { size: None }, false));
+        let program = test_program(symbol_maps);
+
+        let mut memory_map = vec![];
+        program.write_memory_map(&mut memory_map).unwrap();
+        assert_eq!(
+            String::from_utf8(memory_map).unwrap(),
+            "\
+main
+  .text       0x02000000..0x02000080 size:0x80 kind:code
+    0x02000000 func_02000000 function(arm,size=0x0,unknown)
+    0x02000040 data_02000040 data(word[2])
+  .bss        0x02000100..0x02000180 size:0x80 kind:bss
+    0x02000100 data_02000100 bss(size=0x4)
+
+ov000 (overlay 0, loaded at 0x02200000)
+  .text       0x02200000..0x02200080 size:0x80 kind:code
+  .bss        0x02200100..0x02200180 size:0x80 kind:bss
+    0x02200100 data_ov000_02200100 bss
+
+"
+        );
+    }
+
+    #[test]
+    fn symbol_at_aliased_overlays() {
+        let mut symbol_maps = SymbolMaps::new();
+        let main_map = symbol_maps.get_mut(ModuleKind::Arm9);
+        main_map.add(Symbol::new_unknown_function("func_02000000".to_string(), 0x02000000, false));
+        let overlay_map = symbol_maps.get_mut(ModuleKind::Overlay(0));
+        overlay_map.add(Symbol::new_unknown_function("func_ov000_02200000".to_string(), 0x02200000, false));
+        let overlay_map = symbol_maps.get_mut(ModuleKind::Overlay(1));
+        overlay_map.add(Symbol::new_unknown_function("func_ov001_02200000".to_string(), 0x02200000, false));
+        let main =
+            Module::new_arm9("main".to_string(), &mut SymbolMap::new(), Relocations::new(), test_sections(0x02000000), &CODE)
+                .unwrap();
+        let overlays = vec![test_overlay(0, 0x02200000), test_overlay(1, 0x02200000)];
+        let program = Program::new(main, overlays, vec![], symbol_maps);
+
+        let (module, symbol) = program.symbol_at(0x02000000).unwrap();
+        assert_eq!(module, ModuleKind::Arm9);
+        assert_eq!(symbol.name, "func_02000000");
+
+        let symbols = program.symbols_at(0x02200000);
+        let names = symbols.iter().map(|(module, symbol)| (*module, symbol.name.as_str())).collect::<Vec<_>>();
+        assert_eq!(names, [(ModuleKind::Overlay(0), "func_ov000_02200000"), (ModuleKind::Overlay(1), "func_ov001_02200000")]);
+        assert!(program.symbol_at(0x02200000).is_none());
+
+        assert!(program.symbols_at(0x02100000).is_empty());
+        assert!(program.symbol_at(0x02100000).is_none());
+    }
+
+    #[test]
+    fn section_target_with_addend_round_trip() {
+        let line = "from:0x02000000 kind:load to:overlay(0:.bss)+0x40 add:0x4 module:overlay(0)";
+        let relocations = || {
+            let mut relocations = Relocations::new();
+            relocations.add(Relocation::from_line(line).unwrap().unwrap()).unwrap();
+            relocations
+        };
+        let main = |relocations| {
+            Module::new_arm9("main".to_string(), &mut SymbolMap::new(), relocations, test_sections(0x02000000), &CODE).unwrap()
+        };
+
+        let mut program = Program::new(main(relocations()), vec![test_overlay(0, 0x02200000)], vec![], SymbolMaps::new());
+        program.resolve_section_targets().unwrap();
+        let relocation = program.module(0).relocations().get(0x02000000).unwrap();
+        assert_eq!(relocation.to_address(), 0x02200140);
+        assert_eq!(relocation.addend(), 4);
+        assert_eq!(relocation.to_string(), line);
+
+        // The target module is missing, so the target can't be resolved
+        let mut program = Program::new(main(relocations()), vec![], vec![], SymbolMaps::new());
+        assert!(program.resolve_section_targets().is_err());
+    }
+
+    #[test]
+    fn callers_of_filters_target_module() {
+        let mut relocations = Relocations::new();
+        relocations.add_call(0x02000000, 0x02200000, RelocationModule::Overlay { id: 0 }, false, false).unwrap();
+        relocations.add_call(0x02000004, 0x02200000, RelocationModule::Overlays { ids: vec![0, 1] }, false, false).unwrap();
+        relocations.add_call(0x02000008, 0x02200000, RelocationModule::Overlay { id: 1 }, false, false).unwrap();
+        relocations.add_load(0x0200000c, 0x02200000, 0, RelocationModule::Overlay { id: 0 }).unwrap();
+        let main = Module::new_arm9("main".to_string(), &mut SymbolMap::new(), relocations, test_sections(0x02000000), &CODE)
+            .unwrap();
+        let overlays = vec![test_overlay(0, 0x02200000), test_overlay(1, 0x02200000)];
+        let program = Program::new(main, overlays, vec![], SymbolMaps::new());
+
+        assert_eq!(
+            program.callers_of(0x02200000, ModuleKind::Overlay(0)),
+            [(ModuleKind::Arm9, 0x02000000), (ModuleKind::Arm9, 0x02000004)]
+        );
+        assert_eq!(
+            program.callers_of(0x02200000, ModuleKind::Overlay(1)),
+            [(ModuleKind::Arm9, 0x02000004), (ModuleKind::Arm9, 0x02000008)]
+        );
+        assert!(program.callers_of(0x02200000, ModuleKind::Arm9).is_empty());
+    }
+
+    /// Returns a program where the main module's .data section points to the overlay's .bss section.
+    fn cross_reference_program(code: &[u8], symbol_maps: SymbolMaps) -> Program<'_> {
+        let mut sections = Sections::new();
+        sections.add(Section::new(".text".to_string(), SectionKind::Code, 0x02000000, 0x02000080, 4).unwrap()).unwrap();
+        sections.add(Section::new(".data".to_string(), SectionKind::Data, 0x02000080, 0x02000100, 4).unwrap()).unwrap();
+        let main = Module::new_arm9("main".to_string(), &mut SymbolMap::new(), Relocations::new(), sections, code).unwrap();
+        Program::new(main, vec![test_overlay(0, 0x02200000)], vec![], symbol_maps)
+    }
+
+    #[test]
+    fn analyze_cross_references_twice() {
+        let mut code = vec![0; 0x100];
+        code[0x80..0x84].copy_from_slice(&0x02200100u32.to_le_bytes());
+        let mut program = cross_reference_program(&code, SymbolMaps::new());
+
+        for _ in 0..2 {
+            let warnings = program.analyze_cross_references().allow_unknown_function_calls(false).call().unwrap();
+            assert!(warnings.is_empty());
+        }
+        let overlay_map = program.symbol_maps().get(ModuleKind::Overlay(0)).unwrap();
+        assert_eq!(overlay_map.iter().map(|symbol| symbol.addr).collect::<Vec<_>>(), [0x02200100]);
+        assert_eq!(program.main().relocations().iter().count(), 1);
+    }
+
+    #[test]
+    fn analyze_cross_references_reports_incompatible_symbol() {
+        let mut code = vec![0; 0x100];
+        code[0x80..0x84].copy_from_slice(&0x02200100u32.to_le_bytes());
+        let mut symbol_maps = SymbolMaps::new();
+        let overlay_map = symbol_maps.get_mut(ModuleKind::Overlay(0));
+        overlay_map.add(Symbol::new_data("sTable".to_string(), 0x02200100, SymData::Any, false));
+        let mut program = cross_reference_program(&code, symbol_maps);
+
+        let warnings = program.analyze_cross_references().allow_unknown_function_calls(false).call().unwrap();
+        assert_eq!(
+            warnings,
+            [CrossReferenceWarning::IncompatibleSymbol {
+                address: 0x02200100,
+                module: ModuleKind::Overlay(0),
+                existing: SymbolKind::Data(SymData::Any),
+                section_kind: SectionKind::Bss,
+            }]
+        );
+        let overlay_map = program.symbol_maps().get(ModuleKind::Overlay(0)).unwrap();
+        assert_eq!(overlay_map.by_address(0x02200100).unwrap().unwrap().1.name, "sTable");
+    }
+
+    #[test]
+    fn collapse_veneer_chain() {
+        let mut code = vec![0; 0x100];
+        // Veneer at 0x02000010 which leads to the function at 0x02000020
+        code[0x10..0x14].copy_from_slice(&0xe51ff004u32.to_le_bytes()); // ldr pc, [pc, #-0x4]
+        code[0x14..0x18].copy_from_slice(&0x02000020u32.to_le_bytes());
+        code[0x20..0x24].copy_from_slice(&0xe12fff1eu32.to_le_bytes()); // bx lr
+
+        let mut symbol_map = SymbolMap::new();
+        let veneer = SymFunction { mode: InstructionMode::Arm, size: 8, offset: 0, unknown: false };
+        symbol_map.add(Symbol {
+            name: "Veneer".to_string(),
+            kind: SymbolKind::Function(veneer),
+            addr: 0x02000010,
+            ambiguous: false,
+        });
+        let mut relocations = Relocations::new();
+        relocations.add_call(0x02000004, 0x02000010, RelocationModule::Main, false, false).unwrap();
+        relocations.add_load(0x02000008, 0x02000010, 0, RelocationModule::Main).unwrap();
+        let main =
+            Module::new_arm9("main".to_string(), &mut symbol_map, relocations, test_sections(0x02000000), &code).unwrap();
+        let mut program = Program::new(main, vec![test_overlay(0, 0x02200000)], vec![], SymbolMaps::new());
+
+        assert_eq!(program.collapse_veneers().unwrap(), 1);
+        assert_relocations_eq(
+            program.main().relocations(),
+            &[
+                (0x02000004, 0x02000020, RelocationKind::ArmCall, RelocationModule::Main),
+                (0x02000008, 0x02000010, RelocationKind::Load, RelocationModule::Main),
+            ],
+        );
+    }
+
+    #[test]
+    fn max_overlay_id_and_autoloads() {
+        let main =
+            Module::new_arm9("main".to_string(), &mut SymbolMap::new(), Relocations::new(), test_sections(0x02000000), &CODE)
+                .unwrap();
+        let overlays = (0..4).map(|id| test_overlay(id, 0x02200000)).collect();
+        let autoloads = vec![test_itcm()];
+        let program = Program::new(main, overlays, autoloads, SymbolMaps::new());
+
+        assert_eq!(program.max_overlay_id(), Some(3));
+        assert!(program.has_itcm());
+        assert!(!program.has_dtcm());
+
+        assert_eq!(test_program(SymbolMaps::new()).max_overlay_id(), Some(0));
+    }
+
+    #[test]
+    fn module_index_of_main_overlay_and_itcm() {
+        let main =
+            Module::new_arm9("main".to_string(), &mut SymbolMap::new(), Relocations::new(), test_sections(0x02000000), &CODE)
+                .unwrap();
+        let overlays = vec![test_overlay(0, 0x02200000), test_overlay(1, 0x02200000)];
+        let program = Program::new(main, overlays, vec![test_itcm()], SymbolMaps::new());
+
+        assert_eq!(program.module_index_of(ModuleKind::Arm9), Some(0));
+        assert_eq!(program.module_index_of(ModuleKind::Overlay(1)), Some(2));
+        assert_eq!(program.module_index_of(ModuleKind::Autoload(AutoloadKind::Itcm)), Some(3));
+        assert_eq!(program.module_index_of(ModuleKind::Overlay(2)), None);
+        assert_eq!(program.module_index_of(ModuleKind::Autoload(AutoloadKind::Dtcm)), None);
+        assert_eq!(program.module(3).kind(), ModuleKind::Autoload(AutoloadKind::Itcm));
+    }
+
+    #[test]
+    fn add_and_remove_modules() {
+        let kinds = |modules: &[Module]| modules.iter().map(|module| module.kind()).collect::<Vec<_>>();
+        let itcm = ModuleKind::Autoload(AutoloadKind::Itcm);
+        let dtcm = ModuleKind::Autoload(AutoloadKind::Dtcm);
+        let main =
+            Module::new_arm9("main".to_string(), &mut SymbolMap::new(), Relocations::new(), test_sections(0x02000000), &CODE)
+                .unwrap();
+        let mut program = Program::new(main, vec![test_overlay(0, 0x02200000)], vec![test_itcm()], SymbolMaps::new());
+
+        assert_eq!(program.add_overlay(test_overlay(1, 0x02200000)).unwrap(), 2);
+        assert_eq!(kinds(program.overlays()), [ModuleKind::Overlay(0), ModuleKind::Overlay(1)]);
+        assert_eq!(kinds(program.autoloads()), [itcm]);
+        assert!(program.add_overlay(test_itcm()).is_err());
+
+        let dtcm_sections = test_sections(0x027e0000);
+        let dtcm_module = Module::new_autoload(
+            "dtcm".to_string(),
+            &mut SymbolMap::new(),
+            Relocations::new(),
+            dtcm_sections,
+            AutoloadKind::Dtcm,
+            &CODE,
+        )
+        .unwrap();
+        assert_eq!(program.add_autoload(dtcm_module).unwrap(), 4);
+        assert_eq!(kinds(program.autoloads()), [itcm, dtcm]);
+        assert!(program.add_autoload(test_overlay(2, 0x02200000)).is_err());
+
+        assert_eq!(program.remove_module(1).unwrap().kind(), ModuleKind::Overlay(0));
+        assert_eq!(kinds(program.overlays()), [ModuleKind::Overlay(1)]);
+        assert_eq!(kinds(program.autoloads()), [itcm, dtcm]);
+        assert_eq!(program.module_index_of(dtcm), Some(3));
+
+        assert_eq!(program.remove_module(2).unwrap().kind(), itcm);
+        assert_eq!(kinds(program.overlays()), [ModuleKind::Overlay(1)]);
+        assert_eq!(kinds(program.autoloads()), [dtcm]);
+
+        assert!(program.remove_module(0).is_err());
+        assert!(program.remove_module(3).is_err());
+        assert_eq!(program.num_modules(), 3);
+    }
+}
@@ -1,24 +1,32 @@
 use std::{
+    backtrace::Backtrace,
     collections::{btree_map, BTreeMap},
     fmt::Display,
-    io::{BufRead, BufReader, BufWriter, Write},
+    io::{BufRead, BufReader, BufWriter, Read, Write},
     iter,
-    ops::Range,
+    ops::{Range, RangeInclusive},
     path::Path,
 };
 
 use anyhow::{bail, Context, Result};
 use ds_rom::rom::raw::AutoloadKind;
-use object::elf::{R_ARM_ABS32, R_ARM_PC24, R_ARM_THM_PC22, R_ARM_XPC25};
+use object::{
+    elf::{R_ARM_ABS32, R_ARM_PC24, R_ARM_THM_PC22, R_ARM_XPC25},
+    write::SymbolId,
+    RelocationFlags,
+};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
 
 use crate::util::{
-    io::{create_file, open_file},
+    io::{create_file_maybe_gzip, open_file_maybe_gzip},
     parse::{parse_i32, parse_u16, parse_u32},
 };
 
 use super::{
     iter_attributes,
     module::{Module, ModuleKind},
+    section::Sections,
     ParseContext,
 };
 
@@ -31,38 +39,102 @@ impl Relocations {
         Self { relocations: BTreeMap::new() }
     }
 
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let path = path.as_ref();
+    /// Reads a file written by [`Self::to_file`]. Absolute addresses are accepted as well, so this can also read files
+    /// written by [`Self::to_file_absolute`].
+    pub fn from_file<P: AsRef<Path>>(path: P, bases: &ModuleBases) -> Result<Self> {
+        Self::read_file(path.as_ref(), Some(bases))
+    }
+
+    /// Reads a file where all addresses are absolute, see [`Self::to_file_absolute`].
+    pub fn from_file_absolute<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::read_file(path.as_ref(), None)
+    }
+
+    fn read_file(path: &Path, bases: Option<&ModuleBases>) -> Result<Self> {
         let mut context = ParseContext { file_path: path.to_str().unwrap().to_string(), row: 0 };
 
-        let file = open_file(path)?;
+        let file = open_file_maybe_gzip(path)?;
         let reader = BufReader::new(file);
 
-        let mut relocations = BTreeMap::new();
+        let mut relocations = vec![];
         for line in reader.lines() {
             context.row += 1;
-            let Some(relocation) = Relocation::parse(line?.as_str(), &context)? else {
+            // Trim trailing whitespace, including the '\r' of CRLF line endings
+            let Some(relocation) = Relocation::parse(line?.trim_end(), &context, bases)? else {
                 continue;
             };
-            relocations.insert(relocation.from, relocation);
+            relocations.push(relocation);
         }
 
+        // Files written by `to_file` are already sorted, which the stable sort handles in linear time
+        relocations.sort_by_key(|relocation| relocation.from);
+        Self::from_sorted_vec(relocations).with_context(|| format!("{}: invalid relocations", context.file_path))
+    }
+
+    /// Builds relocations from a list sorted by source address, which is faster than adding them one by one. Fails if the
+    /// list is out of order or has more than one relocation from the same address.
+    pub fn from_sorted_vec(relocations: Vec<Relocation>) -> Result<Self> {
+        for pair in relocations.windows(2) {
+            let (prev, next) = (&pair[0], &pair[1]);
+            if prev.from == next.from {
+                log::error!("Duplicate relocation from 0x{:08x}", next.from);
+                bail!("Duplicate relocation from 0x{:08x}", next.from);
+            }
+            if prev.from > next.from {
+                log::error!("Relocation from 0x{:08x} is out of order after 0x{:08x}", next.from, prev.from);
+                bail!("Relocation from 0x{:08x} is out of order after 0x{:08x}", next.from, prev.from);
+            }
+        }
+
+        let relocations = relocations.into_iter().map(|relocation| (relocation.from, relocation)).collect();
         Ok(Self { relocations })
     }
 
-    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let path = path.as_ref();
+    /// Writes addresses as offsets relative to their module's base address, e.g. `from:+0x1c`, so that the file stays valid
+    /// if the modules are moved. Targets in ambiguous or unknown modules are written as absolute addresses.
+    pub fn to_file<P: AsRef<Path>>(&self, path: P, bases: &ModuleBases) -> Result<()> {
+        self.write_file(path.as_ref(), Some(bases))
+    }
+
+    /// Writes all addresses as absolute addresses, which is only valid as long as the modules aren't moved.
+    pub fn to_file_absolute<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write_file(path.as_ref(), None)
+    }
 
-        let file = create_file(path)?;
+    fn write_file(&self, path: &Path, bases: Option<&ModuleBases>) -> Result<()> {
+        let file = create_file_maybe_gzip(path)?;
         let mut writer = BufWriter::new(file);
 
         for relocation in self.relocations.values() {
-            writeln!(writer, "{relocation}")?;
+            writeln!(writer, "{}", DisplayRelocation { relocation, bases })?;
         }
+        writer.into_inner().map_err(|error| error.into_error())?.finish()?;
+        Ok(())
+    }
+
+    /// Writes the relocations as a JSON array of objects with `from`, `to`, `kind` and `module` fields, plus optional
+    /// attributes like `addend` when they are set.
+    pub fn to_json_writer<W: Write>(&self, writer: W) -> Result<()> {
+        serde_json::to_writer_pretty(writer, &self.relocations.values().collect::<Vec<_>>())?;
         Ok(())
     }
 
-    pub fn add(&mut self, relocation: Relocation) -> Result<()> {
+    /// Reads relocations written by [`Self::to_json_writer`].
+    pub fn from_json_reader<R: Read>(reader: R) -> Result<Self> {
+        let mut relocations: Vec<Relocation> = serde_json::from_reader(reader)?;
+        for relocation in &mut relocations {
+            // Overlay ids in JSON are not normalized like those parsed from text
+            relocation.module.normalize()?;
+            if let Some(SectionTarget { module: Some(module), .. }) = &mut relocation.target_section {
+                module.normalize()?;
+            }
+        }
+        let mut result = Self::new();
+        result.extend(relocations)?;
+        Ok(result)
+    }
+
+    pub fn add(&mut self, relocation: Relocation) -> Result<(), RelocationError> {
         match self.relocations.entry(relocation.from) {
             btree_map::Entry::Vacant(entry) => {
                 entry.insert(relocation);
@@ -78,35 +150,68 @@ impl Relocations {
                     );
                     Ok(())
                 } else {
-                    log::error!(
-                        "Relocation from 0x{:08x} to 0x{:08x} in {} collides with existing one to 0x{:08x} in {}",
-                        relocation.from,
-                        relocation.to,
-                        relocation.module,
-                        entry.get().to,
-                        entry.get().module
-                    );
-                    bail!("Relocation collides with existing one")
+                    let existing = entry.get();
+                    CollisionSnafu {
+                        from: relocation.from,
+                        existing_to: existing.to,
+                        existing_module: existing.module.clone(),
+                        to: relocation.to,
+                        module: relocation.module,
+                    }
+                    .fail()
                 }
             }
         }
     }
 
-    pub fn add_call(&mut self, from: u32, to: u32, module: RelocationModule, from_thumb: bool, to_thumb: bool) -> Result<()> {
+    pub fn add_call(
+        &mut self,
+        from: u32,
+        to: u32,
+        module: RelocationModule,
+        from_thumb: bool,
+        to_thumb: bool,
+    ) -> Result<(), RelocationError> {
         self.add(Relocation::new_call(from, to, module, from_thumb, to_thumb))
     }
 
-    pub fn add_load(&mut self, from: u32, to: u32, addend: i32, module: RelocationModule) -> Result<()> {
+    pub fn add_load(&mut self, from: u32, to: u32, addend: i32, module: RelocationModule) -> Result<(), RelocationError> {
         self.add(Relocation::new_load(from, to, addend, module))
     }
 
-    pub fn extend(&mut self, relocations: Vec<Relocation>) -> Result<()> {
+    pub fn extend(&mut self, relocations: Vec<Relocation>) -> Result<(), RelocationError> {
         for relocation in relocations.into_iter() {
             self.add(relocation)?;
         }
         Ok(())
     }
 
+    /// Adds all relocations from `other`. Unlike [`Self::extend`], this doesn't stop at the first collision, so that the
+    /// error lists every conflicting relocation. Non-conflicting relocations are added either way.
+    pub fn merge(&mut self, other: Relocations) -> Result<(), Vec<RelocationError>> {
+        let conflicts =
+            other.relocations.into_values().filter_map(|relocation| self.add(relocation).err()).collect::<Vec<_>>();
+        for RelocationError::Collision { from, existing_to, to, .. } in &conflicts {
+            log::error!("Relocation from 0x{from:08x} to 0x{to:08x} collides with existing one to 0x{existing_to:08x}");
+        }
+        if !conflicts.is_empty() {
+            return Err(conflicts);
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.relocations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.relocations.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.relocations.clear();
+    }
+
     pub fn get(&self, from: u32) -> Option<&Relocation> {
         self.relocations.get(&from)
     }
@@ -115,36 +220,297 @@ impl Relocations {
         self.relocations.values()
     }
 
+    /// Returns all relocations which may lead to the given module, including ambiguous relocations to multiple overlays.
+    pub fn iter_to_module(&self, kind: ModuleKind) -> impl Iterator<Item = &Relocation> {
+        self.relocations.values().filter(move |relocation| relocation.module().includes(kind))
+    }
+
     pub fn iter_range(&self, range: Range<u32>) -> impl Iterator<Item = (&u32, &Relocation)> {
         self.relocations.range(range)
     }
+
+    pub fn iter_range_inclusive(&self, range: RangeInclusive<u32>) -> impl Iterator<Item = (&u32, &Relocation)> {
+        self.relocations.range(range)
+    }
+
+    /// Removes and returns the relocation from the given address, if there is one.
+    pub fn remove(&mut self, from: u32) -> Option<Relocation> {
+        self.relocations.remove(&from)
+    }
+
+    /// Removes all relocations from within the given range and returns them in address order.
+    pub fn remove_range(&mut self, range: Range<u32>) -> Vec<Relocation> {
+        if range.is_empty() {
+            return vec![];
+        }
+        let mut removed = self.relocations.split_off(&range.start);
+        let mut after = removed.split_off(&range.end);
+        self.relocations.append(&mut after);
+        removed.into_values().collect()
+    }
+
+    /// Removes all relocations matching the predicate and returns them in address order.
+    pub fn drain_where(&mut self, pred: impl Fn(&Relocation) -> bool) -> Vec<Relocation> {
+        let (drained, kept): (BTreeMap<_, _>, BTreeMap<_, _>) =
+            std::mem::take(&mut self.relocations).into_iter().partition(|(_, relocation)| pred(relocation));
+        self.relocations = kept;
+        drained.into_values().collect()
+    }
+
+    /// Computes the destination address of relocations whose target is relative to a section in the given module.
+    pub fn resolve_section_targets(&mut self, module_kind: ModuleKind, sections: &Sections) -> Result<()> {
+        for relocation in self.relocations.values_mut() {
+            relocation.resolve_target(module_kind, sections)?;
+        }
+        Ok(())
+    }
+
+    /// Computes the destination address of relocations whose target is relative to a section in another module, using
+    /// `sections_of` to get the sections of that module. Targets in `module_kind` itself are resolved when the module is
+    /// created. Fails if the sections of a target module are not available, as the target would stay unresolved.
+    pub fn resolve_external_section_targets<'s>(
+        &mut self,
+        module_kind: ModuleKind,
+        sections_of: impl Fn(ModuleKind) -> Option<&'s Sections>,
+    ) -> Result<()> {
+        for relocation in self.relocations.values_mut() {
+            let Some(target_section) = &relocation.target_section else { continue };
+            let target_module = target_section.module.as_ref().unwrap_or(&relocation.module);
+            let Some(target_kind) = target_module.first_module() else {
+                log::error!("Relocation from 0x{:08x} targets section {} in no module", relocation.from, target_section.name);
+                bail!("Relocation from 0x{:08x} targets section {} in no module", relocation.from, target_section.name);
+            };
+            if target_kind == module_kind {
+                continue;
+            }
+            let Some(sections) = sections_of(target_kind) else {
+                log::error!(
+                    "Relocation from 0x{:08x} targets section {} in {}, but that module's sections are not loaded",
+                    relocation.from,
+                    target_section.name,
+                    target_kind
+                );
+                bail!(
+                    "Relocation from 0x{:08x} targets section {} in unknown module {}",
+                    relocation.from,
+                    target_section.name,
+                    target_kind
+                );
+            };
+            relocation.resolve_target(target_kind, sections)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Snafu)]
+pub enum RelocationError {
+    #[snafu(display(
+        "relocation from 0x{from:08x} to 0x{to:08x} in {module} collides with existing one to 0x{existing_to:08x} in \
+         {existing_module}:\n{backtrace}"
+    ))]
+    Collision {
+        from: u32,
+        existing_to: u32,
+        existing_module: RelocationModule,
+        to: u32,
+        module: RelocationModule,
+        backtrace: Backtrace,
+    },
+}
+
+/// Collects relocations without failing on conflicts. Problems are recorded as diagnostics instead, so that they can all be
+/// reviewed at once after analysis.
+pub struct RelocationsBuilder {
+    relocations: Relocations,
+    diagnostics: Vec<RelocationDiagnostic>,
 }
 
-#[derive(PartialEq, Eq)]
+impl RelocationsBuilder {
+    pub fn new() -> Self {
+        Self { relocations: Relocations::new(), diagnostics: vec![] }
+    }
+
+    pub fn add(&mut self, relocation: Relocation) {
+        match self.relocations.relocations.entry(relocation.from) {
+            btree_map::Entry::Vacant(entry) => {
+                entry.insert(relocation);
+            }
+            btree_map::Entry::Occupied(entry) => {
+                if entry.get() == &relocation {
+                    self.diagnostics.push(RelocationDiagnostic::Duplicate { relocation });
+                } else {
+                    let existing = entry.get().clone();
+                    self.diagnostics.push(RelocationDiagnostic::Collision { existing, rejected: relocation });
+                }
+            }
+        }
+    }
+
+    pub fn extend(&mut self, relocations: Vec<Relocation>) {
+        for relocation in relocations.into_iter() {
+            self.add(relocation);
+        }
+    }
+
+    pub fn diagnostics(&self) -> &[RelocationDiagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn finish(self) -> (Relocations, Vec<RelocationDiagnostic>) {
+        (self.relocations, self.diagnostics)
+    }
+}
+
+#[derive(Debug)]
+pub enum RelocationDiagnostic {
+    /// The relocation is identical to one that was already added, and was skipped.
+    Duplicate { relocation: Relocation },
+    /// The relocation has the same source address as an existing one, but is otherwise different. The existing relocation
+    /// is kept.
+    Collision { existing: Relocation, rejected: Relocation },
+}
+
+impl Display for RelocationDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Duplicate { relocation } => write!(
+                f,
+                "Relocation from 0x{:08x} to 0x{:08x} in {} is identical to existing one",
+                relocation.from, relocation.to, relocation.module
+            ),
+            Self::Collision { existing, rejected } => write!(
+                f,
+                "Relocation from 0x{:08x} to 0x{:08x} in {} collides with existing one to 0x{:08x} in {}",
+                rejected.from, rejected.to, rejected.module, existing.to, existing.module
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Relocation {
     from: u32,
     to: u32,
+    #[serde(default)]
     addend: i32,
     kind: RelocationKind,
     module: RelocationModule,
+    /// If set, `to` was written relative to this section and is computed by [`Self::resolve_target`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target_section: Option<SectionTarget>,
+    /// Weak relocations may lead to no symbol, which is a warning instead of an error.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    weak: bool,
+}
+
+/// Destination of a relocation which is relative to a section, e.g. `.bss+0x40`. Unlike an absolute address, this stays
+/// correct if the section is moved. The section may be qualified with its module, e.g. `overlay(7:.bss)+0x40` or
+/// `main(.bss)`, otherwise it is in the relocation's target module.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct SectionTarget {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module: Option<RelocationModule>,
+    pub name: String,
+    pub offset: u32,
+}
+
+impl SectionTarget {
+    fn is_section_target(text: &str) -> bool {
+        text.starts_with('.') || text.contains("(.") || text.contains(":.")
+    }
+
+    fn parse(text: &str, context: &ParseContext) -> Result<Self> {
+        let (target, offset) = text.split_once('+').unwrap_or((text, "0"));
+        let offset = parse_u32(offset).with_context(|| format!("{context}: failed to parse section offset '{offset}'"))?;
+
+        let Some((prefix, options)) = target.strip_suffix(')').and_then(|target| target.split_once('(')) else {
+            return Ok(Self { module: None, name: target.to_string(), offset });
+        };
+        let (module, name) = match options.split_once(':') {
+            Some((options, name)) => (format!("{prefix}({options})"), name),
+            None => (prefix.to_string(), options),
+        };
+        let module = RelocationModule::parse(&module, context)?;
+        if module.first_module().is_none() || matches!(module, RelocationModule::Overlays { .. }) {
+            bail!("{context}: section-relative target '{text}' must be in exactly one module");
+        }
+        Ok(Self { module: Some(module), name: name.to_string(), offset })
+    }
+}
+
+impl Display for SectionTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.module {
+            Some(module) => {
+                let module = module.to_string();
+                match module.strip_suffix(')') {
+                    Some(module) => write!(f, "{module}:{})", self.name)?,
+                    None => write!(f, "{module}({})", self.name)?,
+                }
+            }
+            None => write!(f, "{}", self.name)?,
+        }
+        if self.offset != 0 {
+            write!(f, "+{:#x}", self.offset)?;
+        }
+        Ok(())
+    }
 }
 
 impl Relocation {
-    fn parse(line: &str, context: &ParseContext) -> Result<Option<Self>> {
+    /// Parses a single line in the format of `relocs.txt`. Returns `Ok(None)` if the line is blank or a `#` comment.
+    pub fn from_line(line: &str) -> Result<Option<Self>> {
+        let context = ParseContext { file_path: "<line>".to_string(), row: 1 };
+        Self::parse(line, &context, None)
+    }
+
+    fn parse(line: &str, context: &ParseContext, bases: Option<&ModuleBases>) -> Result<Option<Self>> {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            // Blank line or comment
+            return Ok(None);
+        }
         let words = line.split_whitespace();
 
         let mut from = None;
         let mut to = None;
+        let mut to_relative = false;
+        let mut target_section = None;
         let mut addend = 0;
+        let mut weak = false;
         let mut kind = None;
         let mut module = None;
         for (key, value) in iter_attributes(words) {
             match key {
+                "from" if value.starts_with('+') => {
+                    let bases = bases.with_context(|| format!("{context}: relative \"from\" address needs a module base"))?;
+                    let offset = parse_u32(&value[1..])
+                        .with_context(|| format!("{context}: failed to parse \"from\" offset '{value}'"))?;
+                    let from_address = bases.module_base.checked_add(offset);
+                    let from_address =
+                        from_address.with_context(|| format!("{context}: \"from\" offset '{value}' is out of range"))?;
+                    from = Some(from_address);
+                }
                 "from" => {
                     from = Some(
                         parse_u32(value).with_context(|| format!("{context}: failed to parse \"from\" address '{value}'"))?,
                     )
                 }
+                "to" if SectionTarget::is_section_target(value) => {
+                    // Section-relative target, the address is computed later by `resolve_target`
+                    let section_target = SectionTarget::parse(value, context)?;
+                    to = Some(section_target.offset);
+                    target_section = Some(section_target);
+                }
+                "to" if value.starts_with('+') => {
+                    // Relative to the target module, which may not be parsed yet
+                    to = Some(
+                        parse_u32(&value[1..])
+                            .with_context(|| format!("{context}: failed to parse \"to\" offset '{value}'"))?,
+                    );
+                    to_relative = true;
+                }
                 "to" => {
                     to = Some(
                         parse_u32(value).with_context(|| format!("{context}: failed to parse \"to\" address '{value}'"))?,
@@ -156,16 +522,41 @@ impl Relocation {
                 }
                 "kind" => kind = Some(RelocationKind::parse(value, context)?),
                 "module" => module = Some(RelocationModule::parse(value, context)?),
-                _ => bail!("{context}: expected relocation attribute 'from', 'to', 'add', 'kind' or 'module' but got '{key}'"),
+                "weak" => {
+                    weak = match value {
+                        "" | "true" => true,
+                        "false" => false,
+                        _ => bail!("{context}: expected 'true' or 'false' for \"weak\" attribute but got '{value}'"),
+                    }
+                }
+                _ => bail!(
+                    "{context}: expected relocation attribute 'from', 'to', 'add', 'kind', 'module' or 'weak' but got '{key}'"
+                ),
             }
         }
 
         let from = from.with_context(|| format!("{}: missing 'from' attribute", context))?;
         let to = to.with_context(|| format!("{}: missing 'to' attribute", context))?;
         let kind = kind.with_context(|| format!("{}: missing 'kind' attribute", context))?;
-        let module = module.with_context(|| format!("{}: missing 'module' attribute", context))?;
+        let module = match (module, target_section.as_ref().and_then(|target| target.module.as_ref())) {
+            (Some(module), Some(target_module)) if module != *target_module => {
+                bail!("{context}: relocation module {module} does not match the module of target section {target_module}")
+            }
+            (Some(module), _) => module,
+            // The module can be left out if the target section is qualified with one
+            (None, Some(target_module)) => target_module.clone(),
+            (None, None) => bail!("{}: missing 'module' attribute", context),
+        };
 
-        Ok(Some(Self { from, to, addend, kind, module }))
+        let to = if to_relative {
+            let base = bases.zip(module.first_module()).and_then(|(bases, module_kind)| bases.bases.get(&module_kind));
+            let base = base.with_context(|| format!("{context}: relative \"to\" address needs a base for module {module}"))?;
+            base.checked_add(to).with_context(|| format!("{context}: \"to\" offset {to:#x} is out of range"))?
+        } else {
+            to
+        };
+
+        Ok(Some(Self { from, to, addend, kind, module, target_section, weak }))
     }
 
     pub fn new_call(from: u32, to: u32, module: RelocationModule, from_thumb: bool, to_thumb: bool) -> Self {
@@ -180,11 +571,59 @@ impl Relocation {
                 (false, false) => RelocationKind::ArmCall,
             },
             module,
+            target_section: None,
+            weak: false,
         }
     }
 
     pub fn new_load(from: u32, to: u32, addend: i32, module: RelocationModule) -> Self {
-        Self { from, to, addend, kind: RelocationKind::Load, module }
+        Self { from, to, addend, kind: RelocationKind::Load, module, target_section: None, weak: false }
+    }
+
+    /// Computes the destination address if it's relative to a section in the given module. Targets in other modules are
+    /// left as they are, to be resolved with the sections of their own module.
+    pub fn resolve_target(&mut self, module_kind: ModuleKind, sections: &Sections) -> Result<()> {
+        let Some(target_section) = &self.target_section else { return Ok(()) };
+        let target_module = target_section.module.as_ref().unwrap_or(&self.module);
+        if matches!(target_module, RelocationModule::Overlays { .. }) {
+            log::error!(
+                "Relocation from 0x{:08x} targets section {} in {}, but section-relative targets must be in one module",
+                self.from,
+                target_section.name,
+                target_module
+            );
+            bail!("Section-relative relocation target must be in one module");
+        }
+        if target_module.first_module() != Some(module_kind) {
+            return Ok(());
+        }
+        let Some(section) = sections.by_name(&target_section.name) else {
+            log::error!("Relocation from 0x{:08x} targets unknown section {}", self.from, target_section.name);
+            bail!("Relocation from 0x{:08x} targets unknown section {}", self.from, target_section.name);
+        };
+        let Some(to) = section.start_address().checked_add(target_section.offset) else {
+            log::error!(
+                "Relocation from 0x{:08x} targets {} which is out of range of section {}",
+                self.from,
+                target_section,
+                target_section.name
+            );
+            bail!("Relocation from 0x{:08x} targets {} which is out of range", self.from, target_section);
+        };
+        self.to = to;
+        Ok(())
+    }
+
+    pub fn target_section(&self) -> Option<&SectionTarget> {
+        self.target_section.as_ref()
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    pub fn set_weak(&mut self, weak: bool) {
+        self.weak = weak;
     }
 
     pub fn from_address(&self) -> u32 {
@@ -208,13 +647,86 @@ impl Relocation {
     }
 }
 
+/// Compares what the relocation does, i.e. its source, destination, addend, kind and module. Attributes which only describe
+/// where a relocation came from or how it was written, like `weak` or a section-relative target, are left out so that
+/// [`Relocations::add`] still treats such relocations as identical.
+impl PartialEq for Relocation {
+    fn eq(&self, other: &Self) -> bool {
+        let Self { from, to, addend, kind, module, target_section: _, weak: _ } = self;
+        *from == other.from && *to == other.to && *addend == other.addend && *kind == other.kind && *module == other.module
+    }
+}
+
+impl Eq for Relocation {}
+
 impl Display for Relocation {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "from:0x{:08x} kind:{} to:0x{:08x} module:{}", self.from, self.kind, self.to, self.module)
+        DisplayRelocation { relocation: self, bases: None }.fmt(f)
+    }
+}
+
+/// Base addresses for writing and reading relocations relative to their modules, see [`Relocations::to_file`].
+pub struct ModuleBases<'a> {
+    /// Base address of the module which the relocations are from.
+    pub module_base: u32,
+    /// Base addresses of all modules, to write relocation targets relative to.
+    pub bases: &'a BTreeMap<ModuleKind, u32>,
+}
+
+impl<'a> ModuleBases<'a> {
+    /// Takes the base address of the relocations' own module from `bases`.
+    pub fn for_module(module_kind: ModuleKind, bases: &'a BTreeMap<ModuleKind, u32>) -> Result<Self> {
+        let Some(&module_base) = bases.get(&module_kind) else {
+            log::error!("No base address for {module_kind}");
+            bail!("No base address for {module_kind}");
+        };
+        Ok(Self { module_base, bases })
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+struct DisplayRelocation<'a> {
+    relocation: &'a Relocation,
+    bases: Option<&'a ModuleBases<'a>>,
+}
+
+impl<'a> Display for DisplayRelocation<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let relocation = self.relocation;
+        // Addresses below the base can't be written as an offset, so they're written as absolute addresses instead
+        if let Some(offset) = self.bases.and_then(|bases| relocation.from.checked_sub(bases.module_base)) {
+            write!(f, "from:+{offset:#x} kind:{} ", relocation.kind)?;
+        } else {
+            write!(f, "from:0x{:08x} kind:{} ", relocation.from, relocation.kind)?;
+        }
+
+        let target_base = match (self.bases, &relocation.module) {
+            (_, RelocationModule::Overlays { .. }) => None,
+            (Some(bases), module) => module.first_module().and_then(|module_kind| bases.bases.get(&module_kind)),
+            (None, _) => None,
+        };
+        if let Some(target_section) = &relocation.target_section {
+            write!(f, "to:{target_section}")?;
+        } else if let Some(offset) = target_base.and_then(|&target_base| relocation.to.checked_sub(target_base)) {
+            write!(f, "to:+{offset:#x}")?;
+        } else {
+            write!(f, "to:0x{:08x}", relocation.to)?;
+        }
+        if relocation.addend > 0 {
+            write!(f, " add:{:#x}", relocation.addend)?;
+        } else if relocation.addend < 0 {
+            write!(f, " add:-{:#x}", relocation.addend.unsigned_abs())?;
+        }
+
+        write!(f, " module:{}", relocation.module)?;
+        if relocation.weak {
+            write!(f, " weak:true")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RelocationKind {
     ArmCall,
     ThumbCall,
@@ -261,6 +773,16 @@ impl RelocationKind {
         }
     }
 
+    /// Creates an ELF relocation of this kind at `offset`, relative to the start of its section.
+    pub fn to_object_relocation(&self, symbol: SymbolId, offset: u64, addend: i64) -> object::write::Relocation {
+        object::write::Relocation {
+            offset,
+            symbol,
+            addend,
+            flags: RelocationFlags::Elf { r_type: self.into_elf_relocation_type() },
+        }
+    }
+
     pub fn addend(&self) -> i64 {
         match self {
             Self::ArmCall => -8,
@@ -284,7 +806,8 @@ impl Display for RelocationKind {
     }
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RelocationModule {
     None,
     Overlay { id: u16 },
@@ -303,23 +826,26 @@ impl RelocationModule {
 
         match first.kind() {
             ModuleKind::Arm9 => {
-                if modules.next().is_some() {
-                    log::error!("Relocations to main should be unambiguous");
-                    bail!("Relocations to main should be unambiguous");
+                if let Some(other) = modules.next() {
+                    let others = iter::once(other).chain(modules).map(|m| m.kind().to_string()).collect::<Vec<_>>().join(", ");
+                    log::error!("Relocations to main should be unambiguous, but also goes to {others}");
+                    bail!("Relocations to main should be unambiguous, but also goes to {others}");
                 }
                 Ok(Self::Main)
             }
             ModuleKind::Autoload(AutoloadKind::Itcm) => {
-                if modules.next().is_some() {
-                    log::error!("Relocations to ITCM should be unambiguous");
-                    bail!("Relocations to ITCM should be unambiguous");
+                if let Some(other) = modules.next() {
+                    let others = iter::once(other).chain(modules).map(|m| m.kind().to_string()).collect::<Vec<_>>().join(", ");
+                    log::error!("Relocations to ITCM should be unambiguous, but also goes to {others}");
+                    bail!("Relocations to ITCM should be unambiguous, but also goes to {others}");
                 }
                 Ok(Self::Itcm)
             }
             ModuleKind::Autoload(AutoloadKind::Dtcm) => {
-                if modules.next().is_some() {
-                    log::error!("Relocations to DTCM should be unambiguous");
-                    bail!("Relocations to DTCM should be unambiguous");
+                if let Some(other) = modules.next() {
+                    let others = iter::once(other).chain(modules).map(|m| m.kind().to_string()).collect::<Vec<_>>().join(", ");
+                    log::error!("Relocations to DTCM should be unambiguous, but also goes to {others}");
+                    bail!("Relocations to DTCM should be unambiguous, but also goes to {others}");
                 }
                 Ok(Self::Dtcm)
             }
@@ -327,30 +853,59 @@ impl RelocationModule {
                 log::error!("Unknown autoload kind '{kind}'");
                 bail!("Unknown autoload kind '{kind}'");
             }
-            ModuleKind::Overlay(id) => {
-                let ids = iter::once(first)
+            ModuleKind::Overlay(_) => {
+                let mut ids = iter::once(first)
                     .chain(modules)
                     .map(|module| {
                         if let ModuleKind::Overlay(id) = module.kind() {
                             Ok(id)
                         } else {
-                            log::error!("Relocations to overlays should not go to other kinds of modules");
-                            bail!("Relocations to overlays should not go to other kinds of modules");
+                            let kind = module.kind();
+                            log::error!("Relocations to overlays should not go to other kinds of modules, but got {kind}");
+                            bail!("Relocations to overlays should not go to other kinds of modules, but got {kind}");
                         }
                     })
                     .collect::<Result<Vec<_>>>()?;
+                // Sort so that relocations to the same overlays compare equal
+                ids.sort_unstable();
+                ids.dedup();
                 if ids.len() > 1 {
                     Ok(Self::Overlays { ids })
                 } else {
-                    Ok(Self::Overlay { id })
+                    Ok(Self::Overlay { id: ids[0] })
                 }
             }
         }
     }
 
+    /// Sorts and dedups the ids of [`Self::Overlays`] like [`Self::parse`] and [`Self::from_modules`] do, for modules which
+    /// were constructed some other way. Fails if fewer than two unique ids remain.
+    fn normalize(&mut self) -> Result<()> {
+        if let Self::Overlays { ids } = self {
+            ids.sort_unstable();
+            ids.dedup();
+            if ids.len() < 2 {
+                log::error!("Relocation to 'overlays' must have two or more unique overlay IDs, but got {ids:?}");
+                bail!("Relocation to 'overlays' must have two or more unique overlay IDs, but got {ids:?}");
+            }
+        }
+        Ok(())
+    }
+
     fn parse(text: &str, context: &ParseContext) -> Result<Self> {
-        let (value, options) = text.split_once('(').unwrap_or((text, ""));
-        let options = options.strip_suffix(')').unwrap_or(options);
+        let (value, options) = match text.split_once('(') {
+            Some((value, options)) => {
+                let Some(options) = options.strip_suffix(')') else {
+                    bail!("{}: missing closing parenthesis in relocation module '{}'", context, text);
+                };
+                if options.contains(['(', ')']) {
+                    bail!("{}: unbalanced parentheses in relocation module '{}'", context, text);
+                }
+                (value, options)
+            }
+            None if text.contains(')') => bail!("{}: unbalanced parentheses in relocation module '{}'", context, text),
+            None => (text, ""),
+        };
 
         match value {
             "none" => {
@@ -364,12 +919,14 @@ impl RelocationModule {
                 id: parse_u16(options).with_context(|| format!("{}: failed to parse overlay ID '{}'", context, options))?,
             }),
             "overlays" => {
-                let ids = options
+                let mut ids = options
                     .split(',')
                     .map(|x| parse_u16(x).with_context(|| format!("{}: failed to parse overlay ID '{}'", context, x)))
                     .collect::<Result<Vec<_>>>()?;
+                ids.sort_unstable();
+                ids.dedup();
                 if ids.len() < 2 {
-                    bail!("{}: relocation to 'overlays' must have two or more overlay IDs, but got {:?}", context, ids);
+                    bail!("{}: relocation to 'overlays' must have two or more unique overlay IDs, but got {:?}", context, ids);
                 }
                 Ok(Self::Overlays { ids })
             }
@@ -423,6 +980,11 @@ impl RelocationModule {
             RelocationModule::Dtcm => None,
         }
     }
+
+    /// Returns true if this relocation may point to the given module, including ambiguous relocations to multiple overlays.
+    pub fn includes(&self, kind: ModuleKind) -> bool {
+        self.first_module() == Some(kind) || self.other_modules().is_some_and(|mut others| others.any(|m| m == kind))
+    }
 }
 
 impl TryFrom<ModuleKind> for RelocationModule {
@@ -463,3 +1025,413 @@ impl Display for RelocationModule {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::config::{
+        section::{Section, SectionKind},
+        symbol::SymbolMap,
+    };
+
+    #[test]
+    fn merge_reports_every_collision() {
+        let mut relocations = Relocations::new();
+        relocations.add_load(0x02000000, 0x02001000, 0, RelocationModule::Main).unwrap();
+        relocations.add_load(0x02000004, 0x02001004, 0, RelocationModule::Main).unwrap();
+
+        let mut other = Relocations::new();
+        other.add_load(0x02000000, 0x02002000, 0, RelocationModule::Main).unwrap();
+        other.add_load(0x02000004, 0x02002004, 0, RelocationModule::Main).unwrap();
+        other.add_load(0x02000008, 0x02002008, 0, RelocationModule::Main).unwrap();
+
+        let errors = relocations.merge(other).unwrap_err();
+        let collisions = errors
+            .iter()
+            .map(|RelocationError::Collision { from, existing_to, to, .. }| (*from, *existing_to, *to))
+            .collect::<Vec<_>>();
+        assert_eq!(collisions, [(0x02000000, 0x02001000, 0x02002000), (0x02000004, 0x02001004, 0x02002004)]);
+        assert_eq!(relocations.len(), 3);
+        assert_eq!(relocations.get(0x02000008).unwrap().to_address(), 0x02002008);
+    }
+
+    #[test]
+    fn to_object_relocation_per_kind() {
+        let mut object =
+            object::write::Object::new(object::BinaryFormat::Elf, object::Architecture::Arm, object::Endianness::Little);
+        let symbol = object.add_symbol(object::write::Symbol {
+            name: b"func_02000000".to_vec(),
+            value: 0,
+            size: 0,
+            kind: object::SymbolKind::Text,
+            scope: object::SymbolScope::Dynamic,
+            weak: false,
+            section: object::write::SymbolSection::Undefined,
+            flags: object::SymbolFlags::None,
+        });
+
+        let expected = [
+            (RelocationKind::ArmCall, R_ARM_PC24, -8),
+            (RelocationKind::ThumbCall, R_ARM_THM_PC22, -4),
+            (RelocationKind::ArmCallThumb, R_ARM_XPC25, -8),
+            (RelocationKind::ThumbCallArm, R_ARM_THM_PC22, -4),
+            (RelocationKind::Load, R_ARM_ABS32, 0),
+        ];
+        for (kind, r_type, addend) in expected {
+            let relocation = kind.to_object_relocation(symbol, 0x10, kind.addend());
+            assert_eq!(relocation.offset, 0x10, "{kind}");
+            assert_eq!(relocation.symbol, symbol, "{kind}");
+            assert_eq!(relocation.addend, addend, "{kind}");
+            assert_eq!(relocation.flags, RelocationFlags::Elf { r_type }, "{kind}");
+        }
+    }
+
+    #[test]
+    fn from_sorted_vec_matches_from_file() {
+        let relocations = vec![
+            Relocation::new_load(0x02000000, 0x02001000, 0, RelocationModule::Main),
+            Relocation::new_call(0x02000004, 0x02002000, RelocationModule::Main, false, true),
+            Relocation::new_load(0x02000008, 0x02200000, 4, RelocationModule::Overlay { id: 1 }),
+        ];
+        let sorted = Relocations::from_sorted_vec(relocations.clone()).unwrap();
+
+        let path = std::env::temp_dir().join(format!("ds-decomp-sorted-relocs-{}.txt", std::process::id()));
+        sorted.to_file_absolute(&path).unwrap();
+        let read = Relocations::from_file_absolute(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(read.iter().eq(sorted.iter()));
+
+        let out_of_order = vec![relocations[1].clone(), relocations[0].clone()];
+        assert!(Relocations::from_sorted_vec(out_of_order).is_err());
+        let duplicate = vec![relocations[0].clone(), relocations[0].clone()];
+        assert!(Relocations::from_sorted_vec(duplicate).is_err());
+    }
+
+    #[test]
+    fn provenance_does_not_affect_equality() {
+        let mut relocations = Relocations::new();
+        relocations.add_load(0x02000000, 0x02200040, 0, RelocationModule::Overlay { id: 7 }).unwrap();
+
+        let mut weak = Relocation::from_line("from:0x02000000 kind:load to:overlay(7:.bss)+0x40").unwrap().unwrap();
+        weak.set_weak(true);
+        let mut sections = Sections::new();
+        sections.add(Section::new(".bss".to_string(), SectionKind::Bss, 0x02200000, 0x02200100, 32).unwrap()).unwrap();
+        weak.resolve_target(ModuleKind::Overlay(7), &sections).unwrap();
+
+        relocations.add(weak).unwrap();
+        assert_eq!(relocations.len(), 1);
+        assert!(!relocations.get(0x02000000).unwrap().is_weak());
+
+        let other_target = Relocation::new_load(0x02000000, 0x02200080, 0, RelocationModule::Overlay { id: 7 });
+        assert!(relocations.add(other_target).is_err());
+
+        let other_addend = Relocation::new_load(0x02000000, 0x02200040, 0x20, RelocationModule::Overlay { id: 7 });
+        assert!(matches!(relocations.add(other_addend), Err(RelocationError::Collision { .. })));
+        assert_eq!(relocations.get(0x02000000).unwrap().addend(), 0);
+    }
+
+    #[test]
+    fn builder_reports_every_conflict() {
+        let mut builder = RelocationsBuilder::new();
+        builder.add(Relocation::new_load(0x02000000, 0x02001000, 0, RelocationModule::Main));
+        builder.add(Relocation::new_load(0x02000004, 0x02001004, 0, RelocationModule::Main));
+        builder.extend(vec![
+            Relocation::new_load(0x02000000, 0x02002000, 0, RelocationModule::Main),
+            Relocation::new_load(0x02000004, 0x02001004, 0, RelocationModule::Main),
+            Relocation::new_call(0x02000004, 0x02003000, RelocationModule::Main, false, false),
+            Relocation::new_load(0x02000008, 0x02001008, 0, RelocationModule::Main),
+        ]);
+
+        let (relocations, diagnostics) = builder.finish();
+        assert_eq!(relocations.len(), 3);
+        assert_eq!(relocations.get(0x02000000).unwrap().to_address(), 0x02001000);
+        assert_eq!(diagnostics.len(), 3, "{diagnostics:?}");
+        assert!(
+            matches!(&diagnostics[0], RelocationDiagnostic::Collision { rejected, .. } if rejected.to_address() == 0x02002000)
+        );
+        assert!(
+            matches!(&diagnostics[1], RelocationDiagnostic::Duplicate { relocation } if relocation.from_address() == 0x02000004)
+        );
+        assert!(
+            matches!(&diagnostics[2], RelocationDiagnostic::Collision { rejected, .. } if rejected.to_address() == 0x02003000)
+        );
+    }
+
+    #[test]
+    fn drain_loads() {
+        let mut relocations = Relocations::new();
+        relocations.add_load(0x02000008, 0x02001000, 0, RelocationModule::Main).unwrap();
+        relocations.add_call(0x02000004, 0x02002000, RelocationModule::Main, false, false).unwrap();
+        relocations.add_load(0x02000000, 0x02001004, 0, RelocationModule::Main).unwrap();
+        relocations.add_call(0x0200000c, 0x02002004, RelocationModule::Main, false, true).unwrap();
+
+        let drained = relocations.drain_where(|relocation| relocation.kind() == RelocationKind::Load);
+        assert_eq!(drained.iter().map(|relocation| relocation.from_address()).collect::<Vec<_>>(), [0x02000000, 0x02000008]);
+        assert!(drained.iter().all(|relocation| relocation.kind() == RelocationKind::Load));
+        let remaining =
+            relocations.iter().map(|relocation| (relocation.from_address(), relocation.kind())).collect::<Vec<_>>();
+        assert_eq!(remaining, [(0x02000004, RelocationKind::ArmCall), (0x0200000c, RelocationKind::ArmCallThumb)]);
+    }
+
+    #[test]
+    fn module_qualified_section_target_round_trip() {
+        let line = "from:0x02000000 kind:load to:overlay(7:.bss)+0x40 module:overlay(7)";
+        let mut relocation = Relocation::from_line(line).unwrap().unwrap();
+        assert_eq!(relocation.to_string(), line);
+
+        let mut sections = Sections::new();
+        sections.add(Section::new(".text".to_string(), SectionKind::Code, 0x02200000, 0x02200400, 32).unwrap()).unwrap();
+        sections.add(Section::new(".bss".to_string(), SectionKind::Bss, 0x02200400, 0x02200800, 32).unwrap()).unwrap();
+
+        // Sections of another module don't resolve the target
+        relocation.resolve_target(ModuleKind::Arm9, &sections).unwrap();
+        assert_eq!(relocation.to_address(), 0x40);
+
+        relocation.resolve_target(ModuleKind::Overlay(7), &sections).unwrap();
+        assert_eq!(relocation.to_address(), 0x02200440);
+        assert_eq!(relocation.to_string(), line);
+    }
+
+    #[test]
+    fn section_target_out_of_range() {
+        let mut relocation =
+            Relocation::from_line("from:0x02000000 kind:load to:.bss+0xffffff00 module:main").unwrap().unwrap();
+        let mut sections = Sections::new();
+        sections.add(Section::new(".bss".to_string(), SectionKind::Bss, 0x02000100, 0x02000200, 32).unwrap()).unwrap();
+        assert!(relocation.resolve_target(ModuleKind::Arm9, &sections).is_err());
+    }
+
+    #[test]
+    fn module_qualified_section_target_implies_module() {
+        let relocation = Relocation::from_line("from:0x02000000 kind:load to:main(.bss)").unwrap().unwrap();
+        assert_eq!(relocation.module(), &RelocationModule::Main);
+        assert_eq!(relocation.to_string(), "from:0x02000000 kind:load to:main(.bss) module:main");
+
+        assert!(Relocation::from_line("from:0x02000000 kind:load to:overlay(7:.bss) module:overlay(8)").is_err());
+        assert!(Relocation::from_line("from:0x02000000 kind:load to:overlays(7,8:.bss) module:overlays(7,8)").is_err());
+    }
+
+    #[test]
+    fn overlay_relative_round_trip() {
+        let bases = BTreeMap::from([(ModuleKind::Arm9, 0x02000000), (ModuleKind::Overlay(3), 0x02200000)]);
+        let module_bases = ModuleBases::for_module(ModuleKind::Overlay(3), &bases).unwrap();
+
+        let mut relocations = Relocations::new();
+        relocations.add_load(0x02200010, 0x02200100, 0, RelocationModule::Overlay { id: 3 }).unwrap();
+        relocations.add_call(0x02200020, 0x02004000, RelocationModule::Main, false, false).unwrap();
+
+        let path = std::env::temp_dir().join(format!("ds-decomp-relocs-{}.txt", std::process::id()));
+        relocations.to_file(&path, &module_bases).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let read = Relocations::from_file(&path, &module_bases).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            text,
+            "from:+0x10 kind:load to:+0x100 module:overlay(3)\nfrom:+0x20 kind:arm_call to:+0x4000 module:main\n"
+        );
+        assert!(read.iter().eq(relocations.iter()));
+    }
+
+    #[test]
+    fn crlf_file_reads_like_lf_file() {
+        let bases = BTreeMap::from([(ModuleKind::Arm9, 0x02000000), (ModuleKind::Overlay(3), 0x02200000)]);
+        let module_bases = ModuleBases::for_module(ModuleKind::Overlay(3), &bases).unwrap();
+
+        let path = std::env::temp_dir().join(format!("ds-decomp-crlf-relocs-{}.txt", std::process::id()));
+        let text = "from:+0x10 kind:load to:+0x100 module:overlay(3)\r\nfrom:+0x20 kind:arm_call to:+0x4000 module:main\r\n";
+        std::fs::write(&path, text).unwrap();
+        let read = Relocations::from_file(&path, &module_bases).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut expected = Relocations::new();
+        expected.add_load(0x02200010, 0x02200100, 0, RelocationModule::Overlay { id: 3 }).unwrap();
+        expected.add_call(0x02200020, 0x02004000, RelocationModule::Main, false, false).unwrap();
+        assert!(read.iter().eq(expected.iter()));
+    }
+
+    #[test]
+    fn gzip_file_round_trip() {
+        let bases = BTreeMap::from([(ModuleKind::Arm9, 0x02000000), (ModuleKind::Overlay(3), 0x02200000)]);
+        let module_bases = ModuleBases::for_module(ModuleKind::Overlay(3), &bases).unwrap();
+        let mut relocations = Relocations::new();
+        relocations.add_load(0x02200010, 0x02200100, 4, RelocationModule::Overlay { id: 3 }).unwrap();
+        relocations.add_call(0x02200020, 0x02004000, RelocationModule::Main, false, true).unwrap();
+
+        let path = std::env::temp_dir().join(format!("ds-decomp-gzip-relocs-{}.txt.gz", std::process::id()));
+        relocations.to_file(&path, &module_bases).unwrap();
+        let compressed = std::fs::read(&path).unwrap();
+        let read = Relocations::from_file(&path, &module_bases).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(compressed[..2], [0x1f, 0x8b]);
+        assert!(read.iter().eq(relocations.iter()));
+    }
+
+    #[test]
+    fn weak_round_trip() {
+        let line = "from:0x02000000 kind:load to:0x02004000 module:main weak:true";
+        let weak = Relocation::from_line(line).unwrap().unwrap();
+        assert!(weak.is_weak());
+        assert_eq!(weak.to_string(), line);
+
+        let strong = Relocation::from_line("from:0x02000000 kind:load to:0x02004000 module:main weak:false").unwrap().unwrap();
+        assert!(!strong.is_weak());
+        assert_eq!(strong.to_string(), "from:0x02000000 kind:load to:0x02004000 module:main");
+        assert!(Relocation::from_line("from:0x02000000 kind:load to:0x02004000 module:main weak:maybe").is_err());
+    }
+
+    #[test]
+    fn addresses_below_base_are_written_absolute() {
+        let bases = BTreeMap::from([(ModuleKind::Arm9, 0x02000000), (ModuleKind::Overlay(3), 0x02200000)]);
+        let module_bases = ModuleBases::for_module(ModuleKind::Overlay(3), &bases).unwrap();
+
+        let mut relocations = Relocations::new();
+        relocations.add_load(0x021ffff0, 0x01fffff0, 0, RelocationModule::Main).unwrap();
+
+        let path = std::env::temp_dir().join(format!("ds-decomp-below-base-relocs-{}.txt", std::process::id()));
+        relocations.to_file(&path, &module_bases).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let read = Relocations::from_file(&path, &module_bases).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(text, "from:0x021ffff0 kind:load to:0x01fffff0 module:main\n");
+        assert!(read.iter().eq(relocations.iter()));
+    }
+
+    #[test]
+    fn kinds_and_modules_as_map_keys() {
+        use std::collections::HashMap;
+
+        let kinds = [
+            RelocationKind::ArmCall,
+            RelocationKind::ThumbCall,
+            RelocationKind::ArmCallThumb,
+            RelocationKind::ThumbCallArm,
+            RelocationKind::Load,
+        ];
+        let hashed = kinds.iter().map(|&kind| (kind, ())).collect::<HashMap<_, _>>();
+        let ordered = kinds.iter().map(|&kind| (kind, ())).collect::<BTreeMap<_, _>>();
+        assert_eq!(hashed.len(), kinds.len());
+        assert!(ordered.keys().eq(kinds.iter()));
+
+        let modules = [
+            RelocationModule::None,
+            RelocationModule::Overlay { id: 1 },
+            RelocationModule::Overlays { ids: vec![1, 2] },
+            RelocationModule::Overlays { ids: vec![1, 3] },
+            RelocationModule::Main,
+            RelocationModule::Itcm,
+            RelocationModule::Dtcm,
+        ];
+        let hashed = modules.iter().map(|module| (module.clone(), ())).collect::<HashMap<_, _>>();
+        let ordered = modules.iter().map(|module| (module.clone(), ())).collect::<BTreeMap<_, _>>();
+        assert_eq!(hashed.len(), modules.len());
+        assert!(ordered.keys().eq(modules.iter()));
+        assert!(hashed.contains_key(&RelocationModule::Overlays { ids: vec![1, 2] }));
+    }
+
+    #[test]
+    fn remove_and_remove_range() {
+        let mut relocations = Relocations::new();
+        for from in [0x02000000, 0x02000004, 0x02000008, 0x0200000c] {
+            relocations.add_load(from, 0x02001000, 0, RelocationModule::Main).unwrap();
+        }
+
+        assert_eq!(relocations.remove(0x02000000).map(|relocation| relocation.from_address()), Some(0x02000000));
+        assert!(relocations.remove(0x02000000).is_none());
+
+        let removed = relocations.remove_range(0x02000004..0x0200000c);
+        assert_eq!(removed.iter().map(|relocation| relocation.from_address()).collect::<Vec<_>>(), [0x02000004, 0x02000008]);
+        assert_eq!(relocations.iter().map(|relocation| relocation.from_address()).collect::<Vec<_>>(), [0x0200000c]);
+        assert!(relocations.remove_range(0x0200000c..0x0200000c).is_empty());
+        assert_eq!(relocations.len(), 1);
+    }
+
+    #[test]
+    fn overlay_ids_are_normalized() {
+        static CODE: [u8; 0x10] = [0; 0x10];
+        let overlay = |id: u16| {
+            let mut sections = Sections::new();
+            sections.add(Section::new(".text".to_string(), SectionKind::Code, 0x02200000, 0x02200010, 4).unwrap()).unwrap();
+            Module::new_overlay(format!("ov{id:03}"), &mut SymbolMap::new(), Relocations::new(), sections, id, &CODE).unwrap()
+        };
+        let expected = RelocationModule::Overlays { ids: vec![1, 2, 3] };
+
+        let modules = [3, 1, 3, 2].map(overlay);
+        assert_eq!(RelocationModule::from_modules(modules.iter()).unwrap(), expected);
+        let modules = [4, 4].map(overlay);
+        assert_eq!(RelocationModule::from_modules(modules.iter()).unwrap(), RelocationModule::Overlay { id: 4 });
+
+        let context = ParseContext { file_path: "<test>".to_string(), row: 1 };
+        assert_eq!(RelocationModule::parse("overlays(3,1,3,2)", &context).unwrap(), expected);
+        assert_eq!(RelocationModule::parse("overlays(1,2,3)", &context).unwrap(), expected);
+        assert!(RelocationModule::parse("overlays(2,2)", &context).is_err());
+    }
+
+    #[test]
+    fn json_overlay_ids_are_normalized() {
+        let mut relocations = Relocations::new();
+        relocations.add_load(0x02000000, 0x02200000, 0, RelocationModule::Overlays { ids: vec![3, 1, 3, 2] }).unwrap();
+        let mut json = vec![];
+        relocations.to_json_writer(&mut json).unwrap();
+        let read = Relocations::from_json_reader(json.as_slice()).unwrap();
+        assert_eq!(read.get(0x02000000).unwrap().module(), &RelocationModule::Overlays { ids: vec![1, 2, 3] });
+
+        let mut relocations = Relocations::new();
+        relocations.add_load(0x02000000, 0x02200000, 0, RelocationModule::Overlays { ids: vec![2, 2] }).unwrap();
+        let mut json = vec![];
+        relocations.to_json_writer(&mut json).unwrap();
+        assert!(Relocations::from_json_reader(json.as_slice()).is_err());
+    }
+
+    #[test]
+    fn iter_range_inclusive_includes_end() {
+        let mut relocations = Relocations::new();
+        for from in [0x02000000, 0x02000004, 0x02000008, u32::MAX - 3] {
+            relocations.add_load(from, 0x02001000, 0, RelocationModule::Main).unwrap();
+        }
+
+        let froms = relocations.iter_range_inclusive(0x02000004..=0x02000008).map(|(&from, _)| from).collect::<Vec<_>>();
+        assert_eq!(froms, [0x02000004, 0x02000008]);
+        let froms = relocations.iter_range_inclusive(0x02000008..=u32::MAX).map(|(&from, _)| from).collect::<Vec<_>>();
+        assert_eq!(froms, [0x02000008, u32::MAX - 3]);
+    }
+
+    #[test]
+    fn from_line_skips_blank_lines() {
+        assert!(Relocation::from_line("").unwrap().is_none());
+        assert!(Relocation::from_line("   \t").unwrap().is_none());
+    }
+
+    #[test]
+    fn from_line_skips_comments() {
+        assert!(Relocation::from_line("# from:0x02000000 kind:load to:0x02004000 module:main").unwrap().is_none());
+        assert!(Relocation::from_line("    # indented comment").unwrap().is_none());
+    }
+
+    #[test]
+    fn from_line_reports_malformed_lines() {
+        let error = Relocation::from_line("from:0x02000000 to:0x02004000 module:main").unwrap_err();
+        assert_eq!(error.to_string(), "<line>:1: missing 'kind' attribute");
+
+        let error = Relocation::from_line("from:0x0200zzzz kind:load to:0x02004000 module:main").unwrap_err();
+        assert!(error.to_string().contains("failed to parse \"from\" address '0x0200zzzz'"), "{error:#}");
+    }
+
+    proptest! {
+        #[test]
+        fn from_line_does_not_panic(line in "\\PC*") {
+            let _ = Relocation::from_line(&line);
+        }
+
+        #[test]
+        fn from_line_does_not_panic_on_attributes(
+            line in "((from|to|add|kind|module|weak):[-+.():,a-z0-9_]{0,16} ){0,8}"
+        ) {
+            let _ = Relocation::from_line(&line);
+        }
+    }
+}
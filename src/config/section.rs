@@ -275,7 +275,7 @@ impl Display for Section {
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum SectionKind {
     Code,
     Data,
@@ -1,8 +1,11 @@
 use anyhow::{bail, ensure, Context, Result};
+use ds_rom::rom::raw::AutoloadKind;
 use std::{
+    borrow::Cow,
     collections::{btree_map, hash_map, BTreeMap, HashMap},
     fmt::Display,
     io::{self, BufRead, BufReader, BufWriter, Write},
+    mem,
     ops::Range,
     path::Path,
     slice,
@@ -13,7 +16,8 @@ use crate::{
     analysis::{functions::Function, jump_table::JumpTable},
     util::{
         bytes::FromSlice,
-        io::{create_file, open_file},
+        demangle::demangle,
+        io::{create_file_maybe_gzip, open_file, open_file_maybe_gzip},
         parse::parse_u32,
     },
 };
@@ -75,11 +79,150 @@ impl SymbolMaps {
 
         Ok(())
     }
+
+    /// Imports symbols from a linker script style `.sym` file with lines of the form `name = 0xADDRESS; // comment`. A
+    /// symbol is a function if its address is odd (Thumb) or it is commented with `// func`, and data otherwise. Use
+    /// `// data` to import an odd address as data. `module_for_address` picks the symbol map to seed for each address.
+    /// Symbols at addresses which already have a symbol are skipped.
+    pub fn import_sym_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        module_for_address: impl Fn(u32) -> Option<ModuleKind>,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let mut context = ParseContext { file_path: path.to_str().unwrap().to_string(), row: 0 };
+
+        let file = open_file(path)?;
+        let reader = BufReader::new(file);
+
+        for line in reader.lines() {
+            context.row += 1;
+            let Some(symbol) = Symbol::parse_sym(line?.trim_end(), &context)? else { continue };
+            let module_kind = module_for_address(symbol.addr)
+                .with_context(|| format!("{context}: no module contains address {:#010x}", symbol.addr))?;
+            self.get_mut(module_kind).add_if_new_address(symbol)?;
+        }
+        Ok(())
+    }
+
+    /// Commits the ambiguous data or .bss symbol at `address` to the `chosen` module, and removes the ambiguous symbols at
+    /// the same address from all other modules.
+    pub fn resolve_ambiguous(&mut self, address: u32, chosen: ModuleKind) -> Result<()> {
+        let chosen_index = chosen.index();
+        let is_ambiguous = self
+            .get(chosen)
+            .map(|symbol_map| symbol_map.by_address(address))
+            .transpose()?
+            .flatten()
+            .is_some_and(|(_, symbol)| symbol.ambiguous);
+        if !is_ambiguous {
+            log::error!("No ambiguous symbol at {address:#010x} in {chosen}");
+            bail!("No ambiguous symbol at {address:#010x} in {chosen}");
+        }
+
+        self.get_mut(chosen).make_unambiguous(address)?;
+        for (index, symbol_map) in self.symbol_maps.iter_mut().enumerate() {
+            if index != chosen_index {
+                symbol_map.remove_ambiguous(address);
+            }
+        }
+        Ok(())
+    }
+
+    /// Iterates over the symbol maps along with their module kinds. Maps of unknown autoloads are skipped, since their
+    /// kind can't be recovered from the index.
+    fn iter_with_kinds(&self) -> impl Iterator<Item = (ModuleKind, &SymbolMap)> {
+        self.symbol_maps.iter().enumerate().filter_map(|(index, symbol_map)| {
+            let kind = match index {
+                0 => ModuleKind::Arm9,
+                1 => ModuleKind::Autoload(AutoloadKind::Itcm),
+                2 => ModuleKind::Autoload(AutoloadKind::Dtcm),
+                3 => return None,
+                _ => ModuleKind::Overlay((index - 4) as u16),
+            };
+            Some((kind, symbol_map))
+        })
+    }
+
+    /// Iterates over the function, data and .bss symbols which are worth exporting to other tools.
+    fn iter_exported_symbols(&self) -> impl Iterator<Item = (ModuleKind, &Symbol)> {
+        self.iter_with_kinds().flat_map(|(kind, symbol_map)| {
+            symbol_map
+                .iter()
+                .filter(|symbol| matches!(symbol.kind, SymbolKind::Function(_) | SymbolKind::Data(_) | SymbolKind::Bss(_)))
+                .map(move |symbol| (kind, symbol))
+        })
+    }
+
+    /// Writes a Ghidra Python script which creates a function or label for each function, data and .bss symbol. Functions
+    /// are disassembled in ARM or Thumb mode, and overlay symbols are commented with their overlay ID.
+    pub fn export_ghidra_script<W: Write>(&self, w: &mut W) -> Result<()> {
+        writeln!(w, "from ghidra.program.model.lang import RegisterValue")?;
+        writeln!(w, "from ghidra.program.model.symbol import SourceType")?;
+        writeln!(w, "from java.math import BigInteger")?;
+        writeln!(w)?;
+        writeln!(w, "tmode = currentProgram.getRegister('TMode')")?;
+        writeln!(w)?;
+        writeln!(w, "def function(address, name, thumb):")?;
+        writeln!(w, "    address = toAddr(address)")?;
+        writeln!(w, "    value = RegisterValue(tmode, BigInteger.ONE if thumb else BigInteger.ZERO)")?;
+        writeln!(w, "    currentProgram.getProgramContext().setRegisterValue(address, address, value)")?;
+        writeln!(w, "    disassemble(address)")?;
+        writeln!(w, "    if createFunction(address, name) is None:")?;
+        writeln!(w, "        createLabel(address, name, True, SourceType.IMPORTED)")?;
+        writeln!(w)?;
+        writeln!(w, "def label(address, name):")?;
+        writeln!(w, "    createLabel(toAddr(address), name, True, SourceType.IMPORTED)")?;
+        writeln!(w)?;
+
+        for (module_kind, symbol) in self.iter_exported_symbols() {
+            match symbol.kind {
+                SymbolKind::Function(function) => {
+                    let thumb = if function.mode == InstructionMode::Thumb { "True" } else { "False" };
+                    write!(w, "function({:#010x}, \"{}\", {thumb})", symbol.addr, escape_string(&symbol.name))?;
+                }
+                _ => write!(w, "label({:#010x}, \"{}\")", symbol.addr, escape_string(&symbol.name))?,
+            }
+            if let ModuleKind::Overlay(id) = module_kind {
+                write!(w, "  # overlay {id}")?;
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    /// Writes an IDC script for IDA which creates a function or name for each function, data and .bss symbol. The `T`
+    /// segment register is set for each function to select ARM or Thumb mode, and overlay symbols are commented with their
+    /// overlay ID.
+    pub fn export_ida_idc<W: Write>(&self, w: &mut W) -> Result<()> {
+        writeln!(w, "#include <idc.idc>")?;
+        writeln!(w)?;
+        writeln!(w, "static main() {{")?;
+        for (module_kind, symbol) in self.iter_exported_symbols() {
+            if let ModuleKind::Overlay(id) = module_kind {
+                writeln!(w, "    // overlay {id}")?;
+            }
+            if let SymbolKind::Function(function) = symbol.kind {
+                let thumb = if function.mode == InstructionMode::Thumb { 1 } else { 0 };
+                writeln!(w, "    split_sreg_range({:#010x}, \"T\", {thumb}, SR_user);", symbol.addr)?;
+                writeln!(w, "    add_func({:#010x});", symbol.addr)?;
+            }
+            writeln!(w, "    set_name({:#010x}, \"{}\", SN_NOWARN);", symbol.addr, escape_string(&symbol.name))?;
+        }
+        writeln!(w, "}}")?;
+        Ok(())
+    }
+}
+
+/// Escapes backslashes and double quotes, for writing `text` inside a double-quoted string in the exported scripts.
+fn escape_string(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct SymbolIndex(usize);
 
+#[derive(Clone)]
 pub struct SymbolMap {
     symbols: Vec<Symbol>,
     symbols_by_address: BTreeMap<u32, Vec<SymbolIndex>>,
@@ -113,12 +256,13 @@ impl SymbolMap {
         let path = path.as_ref();
         let mut context = ParseContext { file_path: path.to_str().unwrap().to_string(), row: 0 };
 
-        let file = open_file(path)?;
+        let file = open_file_maybe_gzip(path)?;
         let reader = BufReader::new(file);
 
         for line in reader.lines() {
             context.row += 1;
-            let Some(symbol) = Symbol::parse(line?.as_str(), &context)? else { continue };
+            // Trim trailing whitespace, including the '\r' of CRLF line endings
+            let Some(symbol) = Symbol::parse(line?.trim_end(), &context)? else { continue };
             self.add(symbol);
         }
         Ok(())
@@ -127,7 +271,7 @@ impl SymbolMap {
     pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         let path = path.as_ref();
 
-        let file = create_file(path)?;
+        let file = create_file_maybe_gzip(path)?;
         let mut writer = BufWriter::new(file);
 
         for indices in self.symbols_by_address.values() {
@@ -138,6 +282,7 @@ impl SymbolMap {
                 }
             }
         }
+        writer.into_inner().map_err(|error| error.into_error())?.finish()?;
 
         Ok(())
     }
@@ -158,25 +303,74 @@ impl SymbolMap {
         Ok(Some((index, symbol)))
     }
 
+    /// Returns the demangled name of the symbol at `address`, or its raw name if it can't be demangled. See
+    /// [`Symbol::demangled_name`].
+    pub fn demangled_name(&self, address: u32) -> Result<Option<Cow<'_, str>>> {
+        Ok(self.by_address(address)?.map(|(_, symbol)| symbol.demangled_name()))
+    }
+
     pub fn for_name(&self, name: &str) -> Option<impl DoubleEndedIterator<Item = (SymbolIndex, &Symbol)>> {
         Some(self.symbols_by_name.get(name)?.iter().map(|&i| (i, &self.symbols[i.0])))
     }
 
-    pub fn by_name(&self, name: &str) -> Result<Option<(SymbolIndex, &Symbol)>> {
-        let Some(mut symbols) = self.for_name(name) else {
-            return Ok(None);
-        };
-        let (index, symbol) = symbols.next().unwrap();
-        if let Some((_, other)) = symbols.next() {
-            bail!("multiple symbols with name '{}': 0x{:08x}, 0x{:08x}", name, symbol.addr, other.addr);
-        }
-        Ok(Some((index, symbol)))
+    /// Returns the symbol with the given name. If more than one symbol has the name, the one with the lowest address is
+    /// returned, see [`Self::all_by_name`] to get all of them.
+    pub fn by_name(&self, name: &str) -> Option<&Symbol> {
+        self.for_name(name)?.map(|(_, symbol)| symbol).min_by_key(|symbol| symbol.addr)
+    }
+
+    /// Returns every symbol with the given name, sorted by address.
+    pub fn all_by_name(&self, name: &str) -> Vec<&Symbol> {
+        let mut symbols: Vec<_> = self.for_name(name).into_iter().flatten().map(|(_, symbol)| symbol).collect();
+        symbols.sort_by_key(|symbol| symbol.addr);
+        symbols
+    }
+
+    /// Iterates over the symbols whose address is in `range`, in ascending address order. Symbols at the same address are
+    /// yielded in the order they were added.
+    pub fn iter_range(&self, range: Range<u32>) -> impl Iterator<Item = &Symbol> {
+        self.iter_by_address(range)
     }
 
+    /// Same as [`Self::iter_range`], but returns the concrete iterator type.
     pub fn iter_by_address(&self, range: Range<u32>) -> SymbolIterator {
         SymbolIterator { symbols_by_address: self.symbols_by_address.range(range), indices: [].iter(), symbols: &self.symbols }
     }
 
+    pub fn iter(&self) -> SymbolIterator {
+        SymbolIterator { symbols_by_address: self.symbols_by_address.range(..), indices: [].iter(), symbols: &self.symbols }
+    }
+
+    /// Adds the symbols of `other` to this map. Symbols with the same name and kind as an existing symbol at their address
+    /// are skipped. If the existing symbols at an address have other names or kinds, the incoming symbol is not added and
+    /// the conflict is reported instead.
+    pub fn merge(&mut self, other: SymbolMap) -> MergeReport {
+        let mut report = MergeReport::default();
+        for (address, indices) in other.symbols_by_address {
+            for index in indices {
+                let incoming = &other.symbols[index.0];
+                let Some(existing) = self.symbols_by_address.get(&address) else {
+                    self.add(incoming.clone());
+                    continue;
+                };
+                let existing = existing.iter().map(|index| &self.symbols[index.0]).collect::<Vec<_>>();
+                if existing.iter().any(|symbol| symbol.name == incoming.name && symbol.kind.same_kind(&incoming.kind)) {
+                    continue;
+                }
+
+                let conflict = MergeConflict { address, existing: existing[0].clone(), incoming: incoming.clone() };
+                log::warn!("{conflict}");
+                report.conflicts.push(conflict);
+            }
+        }
+        report
+    }
+
+    /// Returns the symbols that were added to this map after it was cloned from `original`.
+    pub fn new_symbols_since(&self, original: &SymbolMap) -> &[Symbol] {
+        &self.symbols[original.symbols.len().min(self.symbols.len())..]
+    }
+
     pub fn add(&mut self, symbol: Symbol) -> (SymbolIndex, &Symbol) {
         let index = SymbolIndex(self.symbols.len());
         self.symbols_by_address.entry(symbol.addr).or_default().push(index);
@@ -242,6 +436,8 @@ impl SymbolMap {
         format!("_{:08x}", addr)
     }
 
+    /// Adds a label with a synthetic `_XXXXXXXX` name. If a symbol already exists at `addr`, no label is added and the
+    /// existing symbol is returned instead, so branches to it are written with its real name.
     pub fn add_label(&mut self, addr: u32, thumb: bool) -> Result<(SymbolIndex, &Symbol)> {
         let name = Self::label_name(addr);
         self.add_if_new_address(Symbol::new_label(name, addr, thumb))
@@ -289,6 +485,21 @@ impl SymbolMap {
         Ok(())
     }
 
+    /// Removes the ambiguous data and .bss symbols at `addr`. Returns how many symbols were removed. The remaining symbols
+    /// are compacted, so indices returned before this call are no longer valid.
+    fn remove_ambiguous(&mut self, addr: u32) -> usize {
+        let is_removed = |symbol: &Symbol| {
+            symbol.addr == addr && symbol.ambiguous && matches!(symbol.kind, SymbolKind::Data(_) | SymbolKind::Bss(_))
+        };
+        let Some(indices) = self.symbols_by_address.get(&addr) else { return 0 };
+        let removed = indices.iter().filter(|index| is_removed(&self.symbols[index.0])).count();
+        if removed > 0 {
+            let symbols = mem::take(&mut self.symbols);
+            *self = Self::from_symbols(symbols.into_iter().filter(|symbol| !is_removed(symbol)).collect());
+        }
+        removed
+    }
+
     pub fn add_data(&mut self, name: Option<String>, addr: u32, data: SymData) -> Result<(SymbolIndex, &Symbol)> {
         let name = name.unwrap_or_else(|| Self::label_name(addr));
         self.make_unambiguous(addr)?;
@@ -318,7 +529,30 @@ impl SymbolMap {
         self.add_if_new_address(Symbol::new_bss(name, addr, data, true))
     }
 
+    /// Sets the size of each .bss symbol in `section_range` which has no size, as the distance to the next symbol or the
+    /// end of the section.
+    pub fn infer_bss_sizes(&mut self, section_range: Range<u32>) {
+        let addresses = self.symbols_by_address.range(section_range.clone()).map(|(&address, _)| address).collect::<Vec<_>>();
+        for (i, &address) in addresses.iter().enumerate() {
+            let next_address = addresses.get(i + 1).copied().unwrap_or(section_range.end);
+            for index in &self.symbols_by_address[&address] {
+                if let SymbolKind::Bss(bss) = &mut self.symbols[index.0].kind {
+                    if bss.size.is_none() {
+                        bss.size = Some(next_address - address);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Same as [`Self::rename`], but borrows the new name.
     pub fn rename_by_address(&mut self, address: u32, new_name: &str) -> Result<()> {
+        self.rename(address, new_name.to_string())
+    }
+
+    /// Renames the symbol at `address` in place, keeping its index and address entry. Fails if there is not exactly one
+    /// symbol at `address`.
+    pub fn rename(&mut self, address: u32, new_name: String) -> Result<()> {
         let symbol_indices = self
             .symbols_by_address
             .get(&address)
@@ -344,7 +578,7 @@ impl SymbolMap {
             }
         }
 
-        match self.symbols_by_name.entry(new_name.to_string()) {
+        match self.symbols_by_name.entry(new_name.clone()) {
             hash_map::Entry::Occupied(mut entry) => {
                 entry.get_mut().push(symbol_index);
             }
@@ -353,7 +587,7 @@ impl SymbolMap {
             }
         }
 
-        self.symbols[symbol_index.0].name = new_name.to_string();
+        self.symbols[symbol_index.0].name = new_name;
 
         Ok(())
     }
@@ -418,6 +652,45 @@ impl<'a, I: Iterator<Item = &'a Vec<SymbolIndex>>> Iterator for FunctionSymbolIt
     }
 }
 
+/// Symbols which were not merged by [`SymbolMap::merge`].
+#[derive(Default)]
+pub struct MergeReport {
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl MergeReport {
+    pub fn is_empty(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// An incoming symbol whose name or kind differs from the symbol already at its address.
+pub struct MergeConflict {
+    pub address: u32,
+    pub existing: Symbol,
+    pub incoming: Symbol,
+}
+
+impl MergeConflict {
+    pub fn is_name_conflict(&self) -> bool {
+        self.existing.name != self.incoming.name
+    }
+
+    pub fn is_kind_conflict(&self) -> bool {
+        !self.existing.kind.same_kind(&self.incoming.kind)
+    }
+}
+
+impl Display for MergeConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Conflicting symbols at {:#010x}: existing {} ({}), incoming {} ({})",
+            self.address, self.existing.name, self.existing.kind, self.incoming.name, self.incoming.kind
+        )
+    }
+}
+
 #[derive(Clone)]
 pub struct Symbol {
     pub name: String,
@@ -453,6 +726,43 @@ impl Symbol {
         Ok(Some(Symbol { name, kind, addr, ambiguous }))
     }
 
+    /// Parses a line of a `.sym` file, see [`SymbolMaps::import_sym_file`].
+    fn parse_sym(line: &str, context: &ParseContext) -> Result<Option<Self>> {
+        let (assignment, comment) = line.split_once("//").unwrap_or((line, ""));
+        let assignment = assignment.trim();
+        if assignment.is_empty() {
+            return Ok(None);
+        }
+
+        let Some((name, value)) = assignment.split_once('=') else {
+            bail!("{context}: expected 'name = address;' but got '{assignment}'");
+        };
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            bail!("{context}: invalid symbol name '{name}'");
+        }
+        let value = value.trim().strip_suffix(';').with_context(|| format!("{context}: missing ';' after address"))?.trim();
+        let addr = parse_u32(value).with_context(|| format!("{context}: failed to parse address '{value}'"))?;
+
+        let is_function = match comment.trim() {
+            "func" => true,
+            "data" => false,
+            _ => addr & 1 != 0,
+        };
+        let symbol = if is_function {
+            Self::new_unknown_function(name.to_string(), addr & !1, addr & 1 != 0)
+        } else {
+            Self::new_data(name.to_string(), addr, SymData::Any, false)
+        };
+        Ok(Some(symbol))
+    }
+
+    /// Returns the demangled C++ name if the `demangle` feature is enabled, otherwise the raw name. The stored name is
+    /// always kept mangled.
+    pub fn demangled_name(&self) -> Cow<'_, str> {
+        demangle(&self.name).map_or(Cow::Borrowed(self.name.as_str()), Cow::Owned)
+    }
+
     fn should_write(&self) -> bool {
         self.kind.should_write()
     }
@@ -553,7 +863,7 @@ impl Display for Symbol {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum SymbolKind {
     Function(SymFunction),
     Label(SymLabel),
@@ -564,6 +874,11 @@ pub enum SymbolKind {
 }
 
 impl SymbolKind {
+    /// Returns whether both kinds are the same variant, ignoring their options.
+    pub fn same_kind(&self, other: &Self) -> bool {
+        mem::discriminant(self) == mem::discriminant(other)
+    }
+
     fn parse(text: &str, context: &ParseContext) -> Result<Self> {
         let (kind, options) = text.split_once('(').unwrap_or((text, ""));
         let options = options.strip_suffix(')').unwrap_or(options);
@@ -636,7 +951,7 @@ impl Display for SymbolKind {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct SymFunction {
     pub mode: InstructionMode,
     pub size: u32,
@@ -703,7 +1018,7 @@ impl Display for SymFunction {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct SymLabel {
     /// If true, the label is not used by the function itself, but accessed externally. Such labels are only discovered
     /// during relocation analysis, which is not performed by the dis/delink subcommands. External label symbols are
@@ -724,7 +1039,7 @@ impl Display for SymLabel {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum InstructionMode {
     Arm,
     Thumb,
@@ -764,24 +1079,30 @@ impl Display for InstructionMode {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct SymJumpTable {
     pub size: u32,
     pub code: bool,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum SymData {
+    /// Untyped bytes.
     Any,
+    /// Array of `count` bytes, written as `byte[count]`. A count of `None` spans until the next symbol.
     Byte { count: Option<u32> },
+    /// Array of `count` halfwords, written as `short[count]`.
     Short { count: Option<u32> },
+    /// Array of `count` words, written as `word[count]`.
     Word { count: Option<u32> },
+    /// Null-terminated string, where the count includes the terminator
+    Asciz { count: Option<u32> },
 }
 
 impl SymData {
     fn parse(kind: &str, context: &ParseContext) -> Result<Self> {
         if kind.is_empty() {
-            bail!("{context}: expected data kind 'any', 'byte' or 'word' but got nothing");
+            bail!("{context}: expected data kind 'any', 'byte', 'short', 'word' or 'asciz' but got nothing");
         }
 
         let (kind, rest) = kind.split_once('[').unwrap_or((kind, ""));
@@ -806,7 +1127,8 @@ impl SymData {
             "short" => Ok(Self::Short { count }),
             "byte" => Ok(Self::Byte { count }),
             "word" => Ok(Self::Word { count }),
-            kind => bail!("{context}: expected data kind 'any', 'byte', 'short' or 'word' but got '{kind}'"),
+            "asciz" => Ok(Self::Asciz { count }),
+            kind => bail!("{context}: expected data kind 'any', 'byte', 'short', 'word' or 'asciz' but got '{kind}'"),
         }
     }
 
@@ -816,6 +1138,7 @@ impl SymData {
             Self::Byte { count } => count,
             Self::Short { count } => count,
             Self::Word { count } => count,
+            Self::Asciz { count } => count,
         }
     }
 
@@ -825,6 +1148,7 @@ impl SymData {
             Self::Byte { .. } => 1,
             Self::Short { .. } => 2,
             Self::Word { .. } => 4,
+            Self::Asciz { .. } => 1,
         }
     }
 
@@ -832,6 +1156,36 @@ impl SymData {
         self.count().map(|count| self.element_size() * count)
     }
 
+    /// Returns [`Self::Asciz`] if `bytes` starts with a non-empty printable string followed by a null terminator.
+    pub fn detect_string(bytes: &[u8]) -> Option<Self> {
+        let length = Self::string_length(bytes)?;
+        Some(Self::Asciz { count: Some(length as u32 + 1) })
+    }
+
+    fn string_length(bytes: &[u8]) -> Option<usize> {
+        let length = bytes.iter().position(|&b| b == 0)?;
+        if length == 0 {
+            return None;
+        }
+        bytes[..length].iter().all(|&b| matches!(b, b'\n' | b'\t' | b'\r' | 0x20..=0x7e)).then_some(length)
+    }
+
+    fn write_string<W: io::Write>(w: &mut W, bytes: &[u8]) -> Result<()> {
+        write!(w, "    .asciz \"")?;
+        for &b in bytes {
+            match b {
+                b'"' => write!(w, "\\\"")?,
+                b'\\' => write!(w, "\\\\")?,
+                b'\n' => write!(w, "\\n")?,
+                b'\t' => write!(w, "\\t")?,
+                b'\r' => write!(w, "\\r")?,
+                _ => write!(w, "{}", b as char)?,
+            }
+        }
+        writeln!(w, "\"")?;
+        Ok(())
+    }
+
     pub fn write_assembly<W: io::Write>(
         &self,
         w: &mut W,
@@ -846,6 +1200,12 @@ impl SymData {
             }
         }
 
+        if let SymData::Asciz { .. } = self {
+            if Self::string_length(bytes).is_some_and(|length| length + 1 == bytes.len()) {
+                return Self::write_string(w, &bytes[..bytes.len() - 1]);
+            }
+        }
+
         let mut offset = 0;
         while offset < bytes.len() {
             let mut data_directive = false;
@@ -864,7 +1224,7 @@ impl SymData {
                 if bytes.len() >= 4 && (address & 3) == 0 {
                     let pointer = u32::from_le_slice(bytes);
 
-                    if symbols.write_symbol(w, address, pointer, &mut data_directive, "    ")? {
+                    if symbols.write_symbol(w, address, pointer, &mut data_directive, "    ", ";")? {
                         column += 4;
                         continue;
                     }
@@ -874,15 +1234,15 @@ impl SymData {
                 if !data_directive {
                     match self {
                         SymData::Any => write!(w, "    .byte 0x{:02x}", bytes[0])?,
-                        SymData::Byte { .. } => write!(w, "    .byte 0x{:02x}", bytes[0])?,
-                        SymData::Short { .. } => write!(w, "    .short {:#x}", bytes[0])?,
+                        SymData::Byte { .. } | SymData::Asciz { .. } => write!(w, "    .byte 0x{:02x}", bytes[0])?,
+                        SymData::Short { .. } => write!(w, "    .short {:#x}", u16::from_le_slice(bytes))?,
                         SymData::Word { .. } => write!(w, "    .word {:#x}", u32::from_le_slice(bytes))?,
                     }
                     data_directive = true;
                 } else {
                     match self {
                         SymData::Any => write!(w, ", 0x{:02x}", bytes[0])?,
-                        SymData::Byte { .. } => write!(w, ", 0x{:02x}", bytes[0])?,
+                        SymData::Byte { .. } | SymData::Asciz { .. } => write!(w, ", 0x{:02x}", bytes[0])?,
                         SymData::Short { .. } => write!(w, ", {:#x}", u16::from_le_slice(bytes))?,
                         SymData::Word { .. } => write!(w, ", {:#x}", u32::from_le_slice(bytes))?,
                     }
@@ -913,11 +1273,13 @@ impl Display for SymData {
             Self::Byte { count: None } => write!(f, "byte[]"),
             Self::Short { count: None } => write!(f, "short[]"),
             Self::Word { count: None } => write!(f, "word[]"),
+            Self::Asciz { count: Some(count) } => write!(f, "asciz[{count}]"),
+            Self::Asciz { count: None } => write!(f, "asciz[]"),
         }
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct SymBss {
     pub size: Option<u32>,
 }
@@ -966,6 +1328,7 @@ impl<'a> SymbolLookup<'a> {
         destination: u32,
         new_line: &mut bool,
         indent: &str,
+        comment: &str,
     ) -> Result<bool> {
         if let Some(relocation) = self.relocations.get(source) {
             let relocation_to = relocation.module();
@@ -984,6 +1347,12 @@ impl<'a> SymbolLookup<'a> {
                     symbol
                 } else if let Some((_, symbol)) = external_symbol_map.get_function(symbol_address)? {
                     symbol
+                } else if relocation.is_weak() {
+                    log::warn!(
+                        "Symbol not found for weak relocation from 0x{source:08x} in {} to 0x{symbol_address:08x} in {module_kind}",
+                        self.module_kind
+                    );
+                    return Ok(false);
                 } else {
                     log::error!(
                         "Symbol not found for relocation from 0x{source:08x} in {} to 0x{symbol_address:08x} in {module_kind}",
@@ -1004,7 +1373,7 @@ impl<'a> SymbolLookup<'a> {
                     write!(w, "-{:#x}", relocation.addend().abs())?;
                 }
 
-                self.write_ambiguous_symbols_comment(w, source, symbol_address)?;
+                self.write_ambiguous_symbols_comment(w, source, symbol_address, comment)?;
 
                 writeln!(w)?;
                 Ok(true)
@@ -1026,11 +1395,18 @@ impl<'a> SymbolLookup<'a> {
         }
     }
 
-    pub fn write_ambiguous_symbols_comment<W: io::Write>(&self, w: &mut W, source: u32, destination: u32) -> Result<()> {
+    /// Writes a comment listing the other candidates of an ambiguous relocation, starting with the `comment` character.
+    pub fn write_ambiguous_symbols_comment<W: io::Write>(
+        &self,
+        w: &mut W,
+        source: u32,
+        destination: u32,
+        comment: &str,
+    ) -> Result<()> {
         let Some(relocation) = self.relocations.get(source) else { return Ok(()) };
 
         if let Some(overlays) = relocation.module().other_modules() {
-            write!(w, " ; ")?;
+            write!(w, " {comment} ")?;
             for (i, overlay) in overlays.enumerate() {
                 let Some(external_symbol_map) = self.symbol_maps.get(overlay) else {
                     log::warn!(
@@ -1094,3 +1470,295 @@ impl<'a> LookupSymbol for SymbolLookup<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rename_keeps_address_entry() {
+        let mut symbol_map = SymbolMap::new();
+        symbol_map.add(Symbol::new_unknown_function("func_02000100".to_string(), 0x02000100, false));
+
+        symbol_map.rename(0x02000100, "MainLoop".to_string()).unwrap();
+
+        let (_, symbol) = symbol_map.by_address(0x02000100).unwrap().unwrap();
+        assert_eq!(symbol.name, "MainLoop");
+        assert!(symbol_map.for_name("func_02000100").is_none());
+        assert_eq!(symbol_map.for_name("MainLoop").unwrap().count(), 1);
+        assert!(symbol_map.rename(0x02000200, "Missing".to_string()).is_err());
+    }
+
+    #[test]
+    fn by_name_returns_lowest_address() {
+        let mut symbol_map = SymbolMap::new();
+        symbol_map.add(Symbol::new_data("sTable".to_string(), 0x02000200, SymData::Any, false));
+        symbol_map.add(Symbol::new_data("sTable".to_string(), 0x02000100, SymData::Any, false));
+
+        assert_eq!(symbol_map.by_name("sTable").unwrap().addr, 0x02000100);
+        let addresses = symbol_map.all_by_name("sTable").iter().map(|symbol| symbol.addr).collect::<Vec<_>>();
+        assert_eq!(addresses, [0x02000100, 0x02000200]);
+        assert!(symbol_map.by_name("sMissing").is_none());
+    }
+
+    #[test]
+    fn merge_reports_conflicts() {
+        let mut symbol_map = SymbolMap::new();
+        symbol_map.add(Symbol::new_unknown_function("func_02000000".to_string(), 0x02000000, false));
+        symbol_map.add(Symbol::new_data("data_02000100".to_string(), 0x02000100, SymData::Any, false));
+
+        let mut other = SymbolMap::new();
+        other.add(Symbol::new_unknown_function("func_02000000".to_string(), 0x02000000, false));
+        other.add(Symbol::new_unknown_function("func_02000100".to_string(), 0x02000100, false));
+        other.add(Symbol::new_data("data_02000200".to_string(), 0x02000200, SymData::Any, false));
+
+        let report = symbol_map.merge(other);
+        assert_eq!(report.conflicts.len(), 1);
+        let conflict = &report.conflicts[0];
+        assert_eq!(conflict.address, 0x02000100);
+        assert!(conflict.is_name_conflict());
+        assert!(conflict.is_kind_conflict());
+
+        assert_eq!(symbol_map.for_address(0x02000000).unwrap().count(), 1);
+        assert_eq!(symbol_map.by_address(0x02000100).unwrap().unwrap().1.name, "data_02000100");
+        assert_eq!(symbol_map.by_address(0x02000200).unwrap().unwrap().1.name, "data_02000200");
+    }
+
+    #[test]
+    fn iter_range_is_sorted_and_bounded() {
+        let mut symbol_map = SymbolMap::new();
+        for address in [0x02000300, 0x02000100, 0x02000400, 0x02000200] {
+            symbol_map.add(Symbol::new_data(format!("data_{address:08x}"), address, SymData::Any, false));
+        }
+
+        let addresses = symbol_map.iter_range(0x02000100..0x02000400).map(|symbol| symbol.addr).collect::<Vec<_>>();
+        assert_eq!(addresses, [0x02000100, 0x02000200, 0x02000300]);
+    }
+
+    #[test]
+    fn resolve_ambiguous_removes_other_candidates() {
+        let mut symbol_maps = SymbolMaps::new();
+        for id in [1, 2] {
+            let symbol_map = symbol_maps.get_mut(ModuleKind::Overlay(id));
+            symbol_map.add_ambiguous_bss(Some("data_022001a0".to_string()), 0x022001a0, SymBss { size: None }).unwrap();
+            symbol_map.add_bss(None, 0x022001c0, SymBss { size: None }).unwrap();
+        }
+
+        symbol_maps.resolve_ambiguous(0x022001a0, ModuleKind::Overlay(1)).unwrap();
+
+        let chosen = symbol_maps.get(ModuleKind::Overlay(1)).unwrap();
+        assert!(!chosen.by_address(0x022001a0).unwrap().unwrap().1.ambiguous);
+
+        let other = symbol_maps.get(ModuleKind::Overlay(2)).unwrap();
+        assert!(other.by_address(0x022001a0).unwrap().is_none());
+        assert!(other.for_name("data_022001a0").is_none());
+        assert_eq!(other.symbols.len(), 1);
+        assert_eq!(other.by_address(0x022001c0).unwrap().unwrap().1.name, "_022001c0");
+    }
+
+    #[test]
+    fn crlf_file_reads_like_lf_file() {
+        let mut symbol_map = SymbolMap::new();
+        symbol_map.add(Symbol::new_unknown_function("func_02000000".to_string(), 0x02000000, false));
+        symbol_map.add(Symbol::new_data("data_02000100".to_string(), 0x02000100, SymData::Any, false));
+        symbol_map.add_bss(None, 0x02000200, SymBss { size: Some(0x10) }).unwrap();
+
+        let path = std::env::temp_dir().join(format!("ds-decomp-crlf-symbols-{}.txt", std::process::id()));
+        symbol_map.to_file(&path).unwrap();
+        let lf = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, lf.replace('\n', "\r\n")).unwrap();
+        let read = SymbolMap::from_file(&path).unwrap();
+        read.to_file(&path).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(written, lf);
+    }
+
+    #[test]
+    fn import_sym_file() {
+        let path = std::env::temp_dir().join(format!("ds-decomp-import-{}.sym", std::process::id()));
+        let module_for_address =
+            |address: u32| if address < 0x02200000 { Some(ModuleKind::Arm9) } else { Some(ModuleKind::Overlay(0)) };
+        std::fs::write(
+            &path,
+            concat!(
+                "// symbols\n",
+                "MainLoop = 0x02000100;\n",
+                "ThumbFunc = 0x02000201;\n",
+                "\n",
+                "ArmFunc = 0x02000300; // func\n",
+                "OddData = 0x02000401; // data\n",
+                "OverlayData = 0x02200000;\n",
+            ),
+        )
+        .unwrap();
+        let mut symbol_maps = SymbolMaps::new();
+        let result = symbol_maps.import_sym_file(&path, module_for_address);
+
+        std::fs::write(&path, "MainLoop = 0x02000100;\nBroken 0x02000200;\n").unwrap();
+        let error = SymbolMaps::new().import_sym_file(&path, module_for_address).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+
+        let main = symbol_maps.get(ModuleKind::Arm9).unwrap();
+        assert!(main.get_data(0x02000100).unwrap().is_some());
+        let (thumb_func, symbol) = main.get_function(0x02000200).unwrap().unwrap();
+        assert_eq!(symbol.name, "ThumbFunc");
+        assert_eq!(thumb_func.mode.into_thumb(), Some(true));
+        let (arm_func, symbol) = main.get_function(0x02000300).unwrap().unwrap();
+        assert_eq!(symbol.name, "ArmFunc");
+        assert_eq!(arm_func.mode.into_thumb(), Some(false));
+        let (_, symbol) = main.get_data(0x02000401).unwrap().unwrap();
+        assert_eq!(symbol.name, "OddData");
+
+        let overlay = symbol_maps.get(ModuleKind::Overlay(0)).unwrap();
+        let (_, symbol) = overlay.get_data(0x02200000).unwrap().unwrap();
+        assert_eq!(symbol.name, "OverlayData");
+
+        assert!(error.to_string().contains(&format!("{}:2:", path.display())), "{error}");
+    }
+
+    #[test]
+    fn data_arrays_round_trip() {
+        let arrays = [
+            SymData::Word { count: Some(16) },
+            SymData::Short { count: Some(3) },
+            SymData::Byte { count: None },
+            SymData::Word { count: Some(1) },
+            SymData::Asciz { count: Some(5) },
+        ];
+        let mut symbol_map = SymbolMap::new();
+        for (i, &data) in arrays.iter().enumerate() {
+            let address = 0x02000000 + i as u32 * 0x100;
+            symbol_map.add(Symbol::new_data(format!("data_{address:08x}"), address, data, false));
+        }
+
+        let path = std::env::temp_dir().join(format!("ds-decomp-data-arrays-{}.txt", std::process::id()));
+        symbol_map.to_file(&path).unwrap();
+        let read = SymbolMap::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for (i, &data) in arrays.iter().enumerate() {
+            let address = 0x02000000 + i as u32 * 0x100;
+            let (read_data, _) = read.get_data(address).unwrap().unwrap();
+            assert_eq!(read_data, data);
+        }
+
+        let context = ParseContext { file_path: "test".to_string(), row: 1 };
+        for kind in ["word[16]", "short[3]", "byte[]", "word", "asciz[5]"] {
+            assert_eq!(SymData::parse(kind, &context).unwrap().to_string(), kind);
+        }
+    }
+
+    #[test]
+    fn infer_bss_sizes_of_adjacent_symbols() {
+        let mut symbol_map = SymbolMap::new();
+        symbol_map.add_bss(None, 0x02000100, SymBss { size: None }).unwrap();
+        symbol_map.add_bss(None, 0x02000108, SymBss { size: None }).unwrap();
+        symbol_map.add_bss(None, 0x02000110, SymBss { size: Some(4) }).unwrap();
+        symbol_map.add_bss(None, 0x02000120, SymBss { size: None }).unwrap();
+        symbol_map.add_bss(None, 0x02000200, SymBss { size: None }).unwrap();
+
+        symbol_map.infer_bss_sizes(0x02000100..0x02000180);
+
+        let size = |address: u32| {
+            let (_, symbol) = symbol_map.by_address(address).unwrap().unwrap();
+            let SymbolKind::Bss(bss) = symbol.kind else { panic!("not a .bss symbol: {}", symbol.name) };
+            bss.size
+        };
+        assert_eq!(size(0x02000100), Some(8));
+        assert_eq!(size(0x02000108), Some(8));
+        assert_eq!(size(0x02000110), Some(4));
+        assert_eq!(size(0x02000120), Some(0x60));
+        assert_eq!(size(0x02000200), None);
+    }
+
+    #[test]
+    fn gzip_file_round_trip() {
+        let mut symbol_map = SymbolMap::new();
+        symbol_map.add(Symbol::new_unknown_function("func_02000000".to_string(), 0x02000000, false));
+        symbol_map.add(Symbol::new_data("data_02000100".to_string(), 0x02000100, SymData::Word { count: Some(4) }, false));
+        symbol_map.add_bss(None, 0x02000200, SymBss { size: Some(0x10) }).unwrap();
+
+        let path = std::env::temp_dir().join(format!("ds-decomp-gzip-symbols-{}.txt", std::process::id()));
+        let gzip_path = path.with_extension("txt.gz");
+        symbol_map.to_file(&path).unwrap();
+        symbol_map.to_file(&gzip_path).unwrap();
+        let plain = std::fs::read_to_string(&path).unwrap();
+        let compressed = std::fs::read(&gzip_path).unwrap();
+        let read = SymbolMap::from_file(&gzip_path).unwrap();
+        read.to_file(&path).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&gzip_path).unwrap();
+
+        assert_eq!(compressed[..2], [0x1f, 0x8b]);
+        assert_eq!(written, plain);
+    }
+
+    #[test]
+    fn demangled_name_falls_back_to_raw_name() {
+        let mut symbol_map = SymbolMap::new();
+        symbol_map.add(Symbol::new_unknown_function("func_02000100".to_string(), 0x02000100, false));
+
+        let symbol = symbol_map.by_name("func_02000100").unwrap();
+        assert_eq!(symbol.demangled_name(), "func_02000100");
+        assert_eq!(symbol_map.demangled_name(0x02000100).unwrap().as_deref(), Some("func_02000100"));
+        assert!(symbol_map.demangled_name(0x02000104).unwrap().is_none());
+    }
+
+    #[cfg(feature = "demangle")]
+    #[test]
+    fn demangled_name_keeps_stored_name_mangled() {
+        let mut symbol_map = SymbolMap::new();
+        symbol_map.add(Symbol::new_unknown_function("_ZN5Actor6updateEv".to_string(), 0x02000100, false));
+
+        assert_eq!(symbol_map.demangled_name(0x02000100).unwrap().as_deref(), Some("Actor::update()"));
+        let (_, symbol) = symbol_map.by_address(0x02000100).unwrap().unwrap();
+        assert_eq!(symbol.name, "_ZN5Actor6updateEv");
+        assert!(symbol_map.by_name("_ZN5Actor6updateEv").is_some());
+    }
+
+    #[test]
+    fn unresolved_weak_relocation_writes_no_symbol() {
+        use crate::config::relocation::{Relocation, RelocationModule};
+
+        let mut symbol_maps = SymbolMaps::new();
+        symbol_maps.get_mut(ModuleKind::Arm9);
+        let mut relocations = Relocations::new();
+        let mut weak = Relocation::new_load(0x02000000, 0x02004000, 0, RelocationModule::Main);
+        weak.set_weak(true);
+        relocations.add(weak).unwrap();
+        relocations.add_load(0x02000004, 0x02004000, 0, RelocationModule::Main).unwrap();
+
+        let lookup = SymbolLookup {
+            module_kind: ModuleKind::Arm9,
+            symbol_map: symbol_maps.get(ModuleKind::Arm9).unwrap(),
+            symbol_maps: &symbol_maps,
+            relocations: &relocations,
+        };
+        let mut out = vec![];
+        let mut new_line = false;
+        assert!(!lookup.write_symbol(&mut out, 0x02000000, 0x02004000, &mut new_line, "    ", "@").unwrap());
+        assert!(out.is_empty());
+        assert!(lookup.write_symbol(&mut out, 0x02000004, 0x02004000, &mut new_line, "    ", "@").is_err());
+    }
+
+    #[test]
+    fn exported_names_are_escaped() {
+        let mut symbol_maps = SymbolMaps::new();
+        let name = r#"operator""_x\y"#.to_string();
+        symbol_maps.get_mut(ModuleKind::Arm9).add(Symbol::new_data(name, 0x02000100, SymData::Any, false));
+
+        let mut ghidra = vec![];
+        symbol_maps.export_ghidra_script(&mut ghidra).unwrap();
+        let ghidra = String::from_utf8(ghidra).unwrap();
+        assert!(ghidra.contains(r#"label(0x02000100, "operator\"\"_x\\y")"#));
+
+        let mut idc = vec![];
+        symbol_maps.export_ida_idc(&mut idc).unwrap();
+        let idc = String::from_utf8(idc).unwrap();
+        assert!(idc.contains(r#"set_name(0x02000100, "operator\"\"_x\\y", SN_NOWARN);"#));
+    }
+}
@@ -1,5 +1,15 @@
-pub trait FromSlice {
+use unarm::Endian;
+
+pub trait FromSlice: Sized {
     fn from_le_slice(s: &[u8]) -> Self;
+    fn from_be_slice(s: &[u8]) -> Self;
+
+    fn from_slice(s: &[u8], endian: Endian) -> Self {
+        match endian {
+            Endian::Little => Self::from_le_slice(s),
+            Endian::Big => Self::from_be_slice(s),
+        }
+    }
 }
 
 impl FromSlice for u32 {
@@ -7,6 +17,11 @@ impl FromSlice for u32 {
         assert!(s.len() >= 4);
         u32::from_le_bytes([s[0], s[1], s[2], s[3]])
     }
+
+    fn from_be_slice(s: &[u8]) -> Self {
+        assert!(s.len() >= 4);
+        u32::from_be_bytes([s[0], s[1], s[2], s[3]])
+    }
 }
 
 impl FromSlice for u16 {
@@ -14,4 +29,9 @@ impl FromSlice for u16 {
         assert!(s.len() >= 2);
         u16::from_le_bytes([s[0], s[1]])
     }
+
+    fn from_be_slice(s: &[u8]) -> Self {
+        assert!(s.len() >= 2);
+        u16::from_be_bytes([s[0], s[1]])
+    }
 }
@@ -0,0 +1,12 @@
+/// Demangles a C++ symbol name. Returns `None` if `name` is not mangled, or if the `demangle` feature is disabled.
+#[cfg(feature = "demangle")]
+pub fn demangle(name: &str) -> Option<String> {
+    let symbol = cpp_demangle::Symbol::new(name.as_bytes()).ok()?;
+    symbol.demangle(&Default::default()).ok()
+}
+
+/// Demangles a C++ symbol name. Returns `None` if `name` is not mangled, or if the `demangle` feature is disabled.
+#[cfg(not(feature = "demangle"))]
+pub fn demangle(_name: &str) -> Option<String> {
+    None
+}
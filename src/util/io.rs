@@ -1,16 +1,17 @@
 use std::{
     backtrace::Backtrace,
     fs::{self, File, ReadDir},
-    io,
+    io::{self, Read, Write},
     path::Path,
 };
 
-use snafu::Snafu;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use snafu::{ResultExt, Snafu};
 
 #[derive(Debug, Snafu)]
 pub enum FileError {
-    #[snafu(transparent)]
-    Io { source: io::Error },
+    #[snafu(display("failed to {operation} '{path}': {source}\n{backtrace}"))]
+    Io { operation: &'static str, path: String, source: io::Error, backtrace: Backtrace },
     #[snafu(display("the file '{path}' was not found:\n{backtrace}"))]
     FileNotFound { path: String, backtrace: Backtrace },
     #[snafu(display("parent directory does not exist for file '{path}':\n{backtrace}"))]
@@ -34,7 +35,7 @@ pub fn open_file<P: AsRef<Path>>(path: P) -> Result<File, FileError> {
             let path = path.to_string_lossy();
             match err.kind() {
                 io::ErrorKind::NotFound => return FileNotFoundSnafu { path }.fail(),
-                _ => Err(err)?,
+                _ => return Err(err).context(IoSnafu { operation: "open file", path }),
             }
         }
     };
@@ -51,13 +52,72 @@ pub fn create_file<P: AsRef<Path>>(path: P) -> Result<File, FileError> {
             match err.kind() {
                 io::ErrorKind::AlreadyExists => return AlreadyExistsSnafu { path }.fail(),
                 io::ErrorKind::NotFound => return FileParentNotFoundSnafu { path }.fail(),
-                _ => Err(err)?,
+                _ => return Err(err).context(IoSnafu { operation: "create file", path }),
             }
         }
     };
     Ok(file)
 }
 
+fn is_gzip(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "gz")
+}
+
+/// Opens a file using [`open_file`], and decompresses it if it has a `.gz` extension.
+pub fn open_file_maybe_gzip<P: AsRef<Path>>(path: P) -> Result<Box<dyn Read>, FileError> {
+    let path = path.as_ref();
+    let file = open_file(path)?;
+    if is_gzip(path) {
+        Ok(Box::new(GzDecoder::new(file)))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// Creates a file using [`create_file`], and compresses it if it has a `.gz` extension. Call [`MaybeGzipWriter::finish`]
+/// when done writing, so that errors while writing the end of the gzip stream are not lost.
+pub fn create_file_maybe_gzip<P: AsRef<Path>>(path: P) -> Result<MaybeGzipWriter, FileError> {
+    let path = path.as_ref();
+    let file = create_file(path)?;
+    if is_gzip(path) {
+        Ok(MaybeGzipWriter::Gzip(GzEncoder::new(file, Compression::default())))
+    } else {
+        Ok(MaybeGzipWriter::Plain(file))
+    }
+}
+
+/// File writer returned by [`create_file_maybe_gzip`].
+pub enum MaybeGzipWriter {
+    Plain(File),
+    Gzip(GzEncoder<File>),
+}
+
+impl MaybeGzipWriter {
+    /// Flushes the file, and writes the end of the gzip stream if compressed.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Self::Plain(mut file) => file.flush(),
+            Self::Gzip(encoder) => encoder.finish().map(|_| ()),
+        }
+    }
+}
+
+impl Write for MaybeGzipWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(file) => file.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(file) => file.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
 /// Creates a file using [`create_file`] and its parent directories using [`create_dir_all`].
 pub fn create_file_and_dirs<P: AsRef<Path>>(path: P) -> Result<File, FileError> {
     let path = path.as_ref();
@@ -75,7 +135,7 @@ pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, FileError> {
             match err.kind() {
                 io::ErrorKind::NotFound => return FileNotFoundSnafu { path }.fail(),
                 io::ErrorKind::OutOfMemory => return FileOutOfMemorySnafu { path }.fail(),
-                _ => todo!(),
+                _ => return Err(err).context(IoSnafu { operation: "read file", path }),
             }
         }
     };
@@ -92,7 +152,8 @@ pub fn write_file<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Resul
             let path = path.to_string_lossy();
             match err.kind() {
                 io::ErrorKind::AlreadyExists => return AlreadyExistsSnafu { path }.fail(),
-                _ => Err(err)?,
+                io::ErrorKind::NotFound => return FileParentNotFoundSnafu { path }.fail(),
+                _ => return Err(err).context(IoSnafu { operation: "write file", path }),
             }
         }
     };
@@ -109,7 +170,7 @@ pub fn read_to_string<P: AsRef<Path>>(path: P) -> Result<String, FileError> {
             match err.kind() {
                 io::ErrorKind::NotFound => return FileNotFoundSnafu { path }.fail(),
                 io::ErrorKind::OutOfMemory => return FileOutOfMemorySnafu { path }.fail(),
-                _ => Err(err)?,
+                _ => return Err(err).context(IoSnafu { operation: "read file", path }),
             }
         }
     };
@@ -126,7 +187,7 @@ pub fn read_dir<P: AsRef<Path>>(path: P) -> Result<ReadDir, FileError> {
             match err.kind() {
                 io::ErrorKind::NotFound => return DirNotFoundSnafu { path }.fail(),
                 io::ErrorKind::OutOfMemory => return DirOutOfMemorySnafu { path }.fail(),
-                _ => Err(err)?,
+                _ => return Err(err).context(IoSnafu { operation: "read directory", path }),
             }
         }
     };
@@ -140,7 +201,7 @@ pub fn create_dir_all<P: AsRef<Path>>(path: P) -> Result<(), FileError> {
         let path = path.to_string_lossy();
         match err.kind() {
             io::ErrorKind::NotFound => return DirNotFoundSnafu { path }.fail(),
-            _ => Err(err)?,
+            _ => return Err(err).context(IoSnafu { operation: "create directory", path }),
         }
     }
     Ok(())
@@ -1,5 +1,8 @@
 pub mod bytes;
+pub mod demangle;
 pub mod ds;
 pub mod io;
 pub mod parse;
 pub mod path;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
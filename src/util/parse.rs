@@ -1,28 +1,68 @@
-use std::num::ParseIntError;
+use std::{borrow::Cow, num::ParseIntError};
 
-pub fn parse_u32(text: &str) -> Result<u32, ParseIntError> {
-    if let Some(hex) = text.strip_prefix("0x") {
-        u32::from_str_radix(hex, 16)
+/// Splits off a `0x`, `0b` or `0o` prefix and removes `_` digit separators, returning the digits and their radix.
+fn digits_and_radix(text: &str) -> (Cow<'_, str>, u32) {
+    let (digits, radix) = if let Some(hex) = text.strip_prefix("0x") {
+        (hex, 16)
+    } else if let Some(binary) = text.strip_prefix("0b") {
+        (binary, 2)
+    } else if let Some(octal) = text.strip_prefix("0o") {
+        (octal, 8)
     } else {
-        u32::from_str_radix(text, 10)
-    }
+        (text, 10)
+    };
+    let digits = if digits.contains('_') { Cow::Owned(digits.replace('_', "")) } else { Cow::Borrowed(digits) };
+    (digits, radix)
+}
+
+pub fn parse_u32(text: &str) -> Result<u32, ParseIntError> {
+    let (digits, radix) = digits_and_radix(text);
+    u32::from_str_radix(&digits, radix)
 }
 
 pub fn parse_u16(text: &str) -> Result<u16, ParseIntError> {
-    if let Some(hex) = text.strip_prefix("0x") {
-        u16::from_str_radix(hex, 16)
-    } else {
-        u16::from_str_radix(text, 10)
-    }
+    let (digits, radix) = digits_and_radix(text);
+    u16::from_str_radix(&digits, radix)
 }
 
 pub fn parse_i32(text: &str) -> Result<i32, ParseIntError> {
     let (negative, value) = text.strip_prefix('-').map(|abs| (true, abs)).unwrap_or((false, text));
-    let abs_value =
-        if let Some(hex) = value.strip_prefix("0x") { i32::from_str_radix(hex, 16)? } else { i32::from_str_radix(value, 10)? };
+    let (digits, radix) = digits_and_radix(value);
     if negative {
-        Ok(-abs_value)
+        // Parse with the sign attached, so that i32::MIN doesn't overflow and a second sign is rejected
+        i32::from_str_radix(&format!("-{digits}"), radix)
     } else {
-        Ok(abs_value)
+        i32::from_str_radix(&digits, radix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes_and_separators() {
+        assert_eq!(parse_u32("0b1010"), Ok(10));
+        assert_eq!(parse_u32("0o17"), Ok(15));
+        assert_eq!(parse_u32("0x00_10_00"), Ok(0x1000));
+        assert_eq!(parse_u32("1_000"), Ok(1000));
+        assert_eq!(parse_u16("0xffff"), Ok(0xffff));
+
+        assert_eq!(parse_i32("0b1010"), Ok(10));
+        assert_eq!(parse_i32("0o17"), Ok(15));
+        assert_eq!(parse_i32("0x00_10_00"), Ok(0x1000));
+    }
+
+    #[test]
+    fn negative_values() {
+        assert_eq!(parse_i32("-0b1010"), Ok(-10));
+        assert_eq!(parse_i32("-0o17"), Ok(-15));
+        assert_eq!(parse_i32("-0x00_10_00"), Ok(-0x1000));
+        assert_eq!(parse_i32("-42"), Ok(-42));
+        assert_eq!(parse_i32("-0x80000000"), Ok(i32::MIN));
+
+        assert!(parse_i32("--1").is_err());
+        assert!(parse_i32("0x80000000").is_err());
+        assert!(parse_u32("-1").is_err());
     }
 }
@@ -0,0 +1,85 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::config::relocation::{RelocationKind, RelocationModule, Relocations};
+
+/// Asserts that `actual` contains exactly the `expected` relocations, given as `(from, to, kind, module)`. On failure, the
+/// panic message lists every missing, extra and mismatching relocation.
+#[track_caller]
+pub fn assert_relocations_eq(actual: &Relocations, expected: &[(u32, u32, RelocationKind, RelocationModule)]) {
+    let diff = relocations_diff(actual, expected);
+    if !diff.is_empty() {
+        panic!("relocations differ:\n{diff}");
+    }
+}
+
+/// Returns a readable diff between `actual` and `expected`, one line per differing relocation. The diff is empty if the
+/// relocations are equal.
+pub fn relocations_diff(actual: &Relocations, expected: &[(u32, u32, RelocationKind, RelocationModule)]) -> String {
+    let expected = expected.iter().map(|(from, to, kind, module)| (*from, (*to, *kind, module))).collect::<BTreeMap<_, _>>();
+
+    let mut diff = String::new();
+    for (&from, &(to, kind, module)) in &expected {
+        match actual.get(from) {
+            None => writeln!(diff, "- from:0x{from:08x} kind:{kind} to:0x{to:08x} module:{module}").unwrap(),
+            Some(relocation)
+                if relocation.to_address() != to || relocation.kind() != kind || relocation.module() != module =>
+            {
+                writeln!(diff, "- from:0x{from:08x} kind:{kind} to:0x{to:08x} module:{module}").unwrap();
+                writeln!(diff, "+ {relocation}").unwrap();
+            }
+            Some(_) => {}
+        }
+    }
+    for relocation in actual.iter() {
+        if !expected.contains_key(&relocation.from_address()) {
+            writeln!(diff, "+ {relocation}").unwrap();
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actual() -> Relocations {
+        let mut relocations = Relocations::new();
+        relocations.add_load(0x02000000, 0x02001000, 0, RelocationModule::Main).unwrap();
+        relocations.add_load(0x02000004, 0x02002000, 0, RelocationModule::Main).unwrap();
+        relocations.add_call(0x02000008, 0x02003000, RelocationModule::Main, false, false).unwrap();
+        relocations
+    }
+
+    #[test]
+    fn diff_lists_missing_extra_and_mismatching() {
+        let expected = [
+            (0x02000000, 0x02001000, RelocationKind::Load, RelocationModule::Main),
+            (0x02000004, 0x02002004, RelocationKind::Load, RelocationModule::Main),
+            (0x0200000c, 0x02004000, RelocationKind::ArmCall, RelocationModule::Main),
+        ];
+        assert_eq!(
+            relocations_diff(&actual(), &expected),
+            "- from:0x02000004 kind:load to:0x02002004 module:main\n\
+             + from:0x02000004 kind:load to:0x02002000 module:main\n\
+             - from:0x0200000c kind:arm_call to:0x02004000 module:main\n\
+             + from:0x02000008 kind:arm_call to:0x02003000 module:main\n"
+        );
+    }
+
+    #[test]
+    fn equal_relocations_have_no_diff() {
+        let expected = [
+            (0x02000000, 0x02001000, RelocationKind::Load, RelocationModule::Main),
+            (0x02000004, 0x02002000, RelocationKind::Load, RelocationModule::Main),
+            (0x02000008, 0x02003000, RelocationKind::ArmCall, RelocationModule::Main),
+        ];
+        assert!(relocations_diff(&actual(), &expected).is_empty());
+        assert_relocations_eq(&actual(), &expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "relocations differ")]
+    fn assert_panics_on_diff() {
+        assert_relocations_eq(&actual(), &[]);
+    }
+}
@@ -77,7 +77,13 @@ fn test_roundtrip() -> Result<()> {
         assert!(directory_equals(&target_config_dir, &dsd_config_dir)?);
 
         // Disassemble
-        let disassemble = Disassemble { config_path: dsd_config_yaml.clone(), asm_path: project_path.join("asm"), ual: false };
+        let disassemble = Disassemble {
+            config_path: dsd_config_yaml.clone(),
+            asm_path: project_path.join("asm"),
+            ual: false,
+            inst_comments: false,
+            annotate_interworking: false,
+        };
         disassemble.run()?;
 
         // Delink modules
@@ -136,6 +142,7 @@ fn dsd_init(project_path: &Path, rom_config: &Path, allow_unknown_function_calls
         build_path,
         skip_reloc_analysis: false,
         allow_unknown_function_calls,
+        follow_data_pointers: false,
     };
     init.run()?;
     Ok(dsd_config_dir)